@@ -0,0 +1,110 @@
+use std::collections::VecDeque;
+
+/// # Audio Buffer
+/// Samples waiting to be picked up by the audio device, at most `latency` of them.
+///
+/// When the emulator runs ahead the oldest samples are dropped so the delay never grows past the
+/// target latency. When the device runs ahead the last sample is repeated, which clicks less than
+/// dropping to silence; before the first sample that means silence.
+pub struct AudioBuffer {
+    samples: VecDeque<f32>,
+    latency: usize,
+    last_sample: f32, // REPEATED ON UNDERRUN
+}
+
+impl AudioBuffer {
+    /// # Arguments
+    ///
+    /// * `latency` - How many samples the buffer holds before it starts dropping the oldest.
+    pub fn new(latency: usize) -> Self {
+        AudioBuffer {
+            samples: VecDeque::with_capacity(latency),
+            latency,
+            last_sample: 0.0,
+        }
+    }
+
+    pub fn latency(&self) -> usize {
+        self.latency
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Queues a sample, dropping the oldest one if the buffer is full.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample` - The mixed output, 0.0 to 1.0.
+    pub fn push_sample(&mut self, sample: f32) {
+        if self.latency == 0 {
+            return;
+        }
+        if self.samples.len() == self.latency {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    /// Fills all of `into`, oldest sample first. Slots past the queued samples get the last sample again.
+    /// Returns how many queued samples were used, anything less than `into.len()` was an underrun.
+    ///
+    /// # Arguments
+    ///
+    /// * `into` - The device's buffer.
+    pub fn drain(&mut self, into: &mut [f32]) -> usize {
+        let mut drained = 0;
+        for slot in into.iter_mut() {
+            if let Some(sample) = self.samples.pop_front() {
+                self.last_sample = sample;
+                drained += 1;
+            }
+            *slot = self.last_sample;
+        }
+        drained
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_overrun_drops_oldest() {
+        let mut buffer = AudioBuffer::new(4);
+        for sample in [0.1, 0.2, 0.3, 0.4, 0.5, 0.6] {
+            buffer.push_sample(sample);
+        }
+        assert_eq!(buffer.len(), 4);
+
+        let mut out = [0.0; 4];
+        assert_eq!(buffer.drain(&mut out), 4);
+        assert_eq!(out, [0.3, 0.4, 0.5, 0.6]);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_underrun_repeats_last_sample() {
+        let mut buffer = AudioBuffer::new(4);
+        // nothing played yet: silence
+        let mut out = [1.0; 2];
+        assert_eq!(buffer.drain(&mut out), 0);
+        assert_eq!(out, [0.0, 0.0]);
+
+        buffer.push_sample(0.25);
+        buffer.push_sample(0.5);
+        let mut out = [0.0; 5];
+        assert_eq!(buffer.drain(&mut out), 2);
+        assert_eq!(out, [0.25, 0.5, 0.5, 0.5, 0.5]);
+
+        // and keeps holding it
+        let mut out = [0.0; 1];
+        assert_eq!(buffer.drain(&mut out), 0);
+        assert_eq!(out, [0.5]);
+    }
+}
@@ -0,0 +1,270 @@
+/// Timer periods in CPU cycles for each of the 16 rates selectable through $4010 (NTSC).
+const DMC_RATE_TABLE: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+
+//  $4010  IL-- RRRR   IRQ enable, loop, rate index
+//  $4011  -DDD DDDD   direct load of the 7 bit output level
+//  $4012  AAAA AAAA   sample address = $C000 + A * 64
+//  $4013  LLLL LLLL   sample length = L * 16 + 1 bytes
+
+// DMC REGISTERS
+
+/// # Delta Modulation Channel https://www.nesdev.org/wiki/APU_DMC
+/// Plays 1 bit delta encoded samples that it fetches from CPU memory through DMA,
+/// each fetch stalling the CPU. Can raise an IRQ once a non-looping sample is exhausted.
+pub struct DmcChannel {
+    irq_enabled: bool,
+    loop_flag: bool,
+    rate: u16,
+    timer: u16,
+    output_level: u8,
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+    interrupt: bool,
+}
+
+impl DmcChannel {
+    pub fn new() -> Self {
+        DmcChannel {
+            irq_enabled: false,
+            loop_flag: false,
+            rate: DMC_RATE_TABLE[0],
+            timer: DMC_RATE_TABLE[0] - 1,
+            output_level: 0,
+            sample_address: 0xC000,
+            sample_length: 1,
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+            interrupt: false,
+        }
+    }
+
+    /// $4010: IRQ enable, loop flag and rate index. Clearing the IRQ enable also acknowledges a pending IRQ.
+    pub fn write_control(&mut self, data: u8) {
+        self.irq_enabled = data & 0b1000_0000 != 0;
+        self.loop_flag = data & 0b0100_0000 != 0;
+        self.rate = DMC_RATE_TABLE[(data & 0b1111) as usize];
+        if !self.irq_enabled {
+            self.interrupt = false;
+        }
+    }
+
+    /// $4011: loads the output level directly.
+    pub fn write_direct_load(&mut self, data: u8) {
+        self.output_level = data & 0b0111_1111;
+    }
+
+    /// $4012: sample start address.
+    pub fn write_sample_address(&mut self, data: u8) {
+        self.sample_address = 0xC000 + data as u16 * 64;
+    }
+
+    /// $4013: sample length.
+    pub fn write_sample_length(&mut self, data: u8) {
+        self.sample_length = data as u16 * 16 + 1;
+    }
+
+    /// $4015 bit 4: disabling drops the rest of the sample, enabling restarts it if it had finished.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.interrupt = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    /// Whether sample bytes remain to be fetched ($4015 bit 4 on read).
+    pub fn is_active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    pub fn irq_pending(&self) -> bool {
+        self.interrupt
+    }
+
+    pub fn output_level(&self) -> u8 {
+        self.output_level
+    }
+
+    pub fn bytes_remaining(&self) -> u16 {
+        self.bytes_remaining
+    }
+
+    /// Address the memory reader wants to fetch from, if the sample buffer is empty and bytes remain.
+    pub fn pending_fetch(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            Some(self.current_address)
+        } else {
+            None
+        }
+    }
+
+    /// Completes a DMA fetch started from `pending_fetch`.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The byte read from `pending_fetch`'s address.
+    pub fn load_sample(&mut self, data: u8) {
+        self.sample_buffer = Some(data);
+        self.current_address = if self.current_address == 0xFFFF {
+            0x8000
+        } else {
+            self.current_address + 1
+        };
+        self.bytes_remaining -= 1;
+
+        if self.bytes_remaining == 0 {
+            if self.loop_flag {
+                self.restart();
+            } else if self.irq_enabled {
+                self.interrupt = true;
+            }
+        }
+    }
+
+    /// Advances the channel by one CPU cycle.
+    pub fn tick(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.rate - 1;
+            self.clock_output();
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    /// Applies the next delta bit to the output level and reloads the shift register once it's drained.
+    fn clock_output(&mut self) {
+        if !self.silence {
+            if self.shift_register & 1 == 1 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+        self.bits_remaining -= 1;
+
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(data) => {
+                    self.silence = false;
+                    self.shift_register = data;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+}
+
+impl Default for DmcChannel {
+    fn default() -> Self {
+        DmcChannel::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sample_address_and_length() {
+        let mut dmc = DmcChannel::new();
+        dmc.write_sample_address(0x01);
+        dmc.write_sample_length(0x01);
+        dmc.set_enabled(true);
+
+        assert_eq!(dmc.pending_fetch(), Some(0xC040));
+        assert_eq!(dmc.bytes_remaining(), 17);
+    }
+
+    #[test]
+    fn test_fetch_address_wraps_to_8000() {
+        let mut dmc = DmcChannel::new();
+        dmc.write_sample_address(0xff);
+        dmc.write_sample_length(0x04);
+        dmc.set_enabled(true);
+        assert_eq!(dmc.pending_fetch(), Some(0xFFC0));
+
+        for _ in 0..64 {
+            dmc.load_sample(0);
+            dmc.sample_buffer = None;
+        }
+        assert_eq!(dmc.pending_fetch(), Some(0x8000));
+    }
+
+    #[test]
+    fn test_output_level_follows_delta_bits() {
+        let mut dmc = DmcChannel::new();
+        dmc.write_control(0x0f);
+        dmc.write_direct_load(0x40);
+        dmc.write_sample_length(0x00);
+        dmc.set_enabled(true);
+        dmc.load_sample(0b0000_0011);
+        dmc.timer = 0;
+
+        // the first output cycle is silent and picks up the buffered byte
+        for _ in 0..8 * 54 {
+            dmc.tick();
+        }
+        assert_eq!(dmc.output_level(), 0x40);
+
+        // then two +2 steps followed by six -2 steps
+        for _ in 0..2 * 54 {
+            dmc.tick();
+        }
+        assert_eq!(dmc.output_level(), 0x44);
+        for _ in 0..6 * 54 {
+            dmc.tick();
+        }
+        assert_eq!(dmc.output_level(), 0x38);
+    }
+
+    #[test]
+    fn test_irq_raised_at_end_of_sample() {
+        let mut dmc = DmcChannel::new();
+        dmc.write_control(0b1000_0000);
+        dmc.write_sample_length(0x00);
+        dmc.set_enabled(true);
+        assert!(!dmc.irq_pending());
+
+        dmc.load_sample(0x55);
+        assert!(dmc.irq_pending());
+        assert!(!dmc.is_active());
+
+        // clearing the IRQ enable acknowledges it
+        dmc.write_control(0x00);
+        assert!(!dmc.irq_pending());
+    }
+
+    #[test]
+    fn test_loop_restarts_without_irq() {
+        let mut dmc = DmcChannel::new();
+        dmc.write_control(0b1100_0000);
+        dmc.write_sample_length(0x00);
+        dmc.set_enabled(true);
+
+        dmc.load_sample(0x55);
+        assert!(!dmc.irq_pending());
+        assert_eq!(dmc.bytes_remaining(), 1);
+    }
+}
@@ -0,0 +1,153 @@
+/// CPU cycles, counted from the last $4017 write, at which each sequencer step fires (NTSC).
+const STEP_1: usize = 7457;
+const STEP_2: usize = 14913;
+const STEP_3: usize = 22371;
+const FOUR_STEP_LAST: usize = 29829;
+const FIVE_STEP_LAST: usize = 37281;
+
+//  $4017  MI-- ----   mode (0 = 4-step, 1 = 5-step), IRQ inhibit
+
+// FRAME COUNTER REGISTER
+
+/// Which units a frame counter step clocks, and whether it raises the frame interrupt.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FrameClock {
+    pub quarter_frame: bool, // ENVELOPES + TRIANGLE LINEAR COUNTER
+    pub half_frame: bool,    // LENGTH COUNTERS + SWEEP UNITS
+    pub irq: bool,
+}
+
+impl FrameClock {
+    const QUARTER: FrameClock = FrameClock { quarter_frame: true, half_frame: false, irq: false };
+    const HALF: FrameClock = FrameClock { quarter_frame: true, half_frame: true, irq: false };
+}
+
+/// # Frame Counter https://www.nesdev.org/wiki/APU_Frame_Counter
+/// Sequencer driving the low frequency clocks of the sound channels.
+///
+/// ```text
+///  4-step:  Q   Q+H   Q   Q+H+IRQ
+///  5-step:  Q   Q+H   Q   -   Q+H
+/// ```
+pub struct FrameCounter {
+    five_step: bool,
+    irq_inhibit: bool,
+    cycle: usize,
+}
+
+impl FrameCounter {
+    pub fn new() -> Self {
+        FrameCounter {
+            five_step: false,
+            irq_inhibit: false,
+            cycle: 0,
+        }
+    }
+
+    /// $4017: selects the sequence mode and IRQ inhibit, and restarts the sequence.
+    /// Selecting 5-step mode clocks the quarter and half frame units right away, which is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The byte written.
+    pub fn write(&mut self, data: u8) -> FrameClock {
+        self.five_step = data & 0b1000_0000 != 0;
+        self.irq_inhibit = data & 0b0100_0000 != 0;
+        self.cycle = 0;
+        if self.five_step {
+            FrameClock::HALF
+        } else {
+            FrameClock::default()
+        }
+    }
+
+    pub fn is_five_step(&self) -> bool {
+        self.five_step
+    }
+
+    pub fn irq_inhibited(&self) -> bool {
+        self.irq_inhibit
+    }
+
+    /// Advances the sequencer by one CPU cycle and returns what fired on it.
+    pub fn tick(&mut self) -> FrameClock {
+        self.cycle += 1;
+        match (self.cycle, self.five_step) {
+            (STEP_1, _) | (STEP_3, _) => FrameClock::QUARTER,
+            (STEP_2, _) => FrameClock::HALF,
+            (FOUR_STEP_LAST, false) => {
+                self.cycle = 0;
+                FrameClock { irq: !self.irq_inhibit, ..FrameClock::HALF }
+            }
+            (FIVE_STEP_LAST, true) => {
+                self.cycle = 0;
+                FrameClock::HALF
+            }
+            _ => FrameClock::default(),
+        }
+    }
+}
+
+impl Default for FrameCounter {
+    fn default() -> Self {
+        FrameCounter::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Ticks through `cycles` CPU cycles and returns every non-empty clock with the cycle it fired on.
+    fn run(counter: &mut FrameCounter, cycles: usize) -> Vec<(usize, FrameClock)> {
+        (1..=cycles)
+            .map(|cycle| (cycle, counter.tick()))
+            .filter(|(_, clock)| *clock != FrameClock::default())
+            .collect()
+    }
+
+    #[test]
+    fn test_four_step_sequence() {
+        let mut counter = FrameCounter::new();
+        counter.write(0x00);
+        let irq = FrameClock { irq: true, ..FrameClock::HALF };
+        assert_eq!(
+            run(&mut counter, FOUR_STEP_LAST),
+            vec![
+                (STEP_1, FrameClock::QUARTER),
+                (STEP_2, FrameClock::HALF),
+                (STEP_3, FrameClock::QUARTER),
+                (FOUR_STEP_LAST, irq),
+            ]
+        );
+        // and it starts over
+        assert_eq!(run(&mut counter, STEP_1), vec![(STEP_1, FrameClock::QUARTER)]);
+    }
+
+    #[test]
+    fn test_four_step_irq_inhibit() {
+        let mut counter = FrameCounter::new();
+        counter.write(0b0100_0000);
+        assert!(counter.irq_inhibited());
+        let clocks = run(&mut counter, FOUR_STEP_LAST);
+        assert_eq!(clocks.last(), Some(&(FOUR_STEP_LAST, FrameClock::HALF)));
+    }
+
+    #[test]
+    fn test_five_step_sequence() {
+        let mut counter = FrameCounter::new();
+        // the mode switch itself clocks everything once
+        assert_eq!(counter.write(0b1000_0000), FrameClock::HALF);
+        assert!(counter.is_five_step());
+        assert_eq!(
+            run(&mut counter, FIVE_STEP_LAST),
+            vec![
+                (STEP_1, FrameClock::QUARTER),
+                (STEP_2, FrameClock::HALF),
+                (STEP_3, FrameClock::QUARTER),
+                (FIVE_STEP_LAST, FrameClock::HALF),
+            ]
+        );
+        assert_eq!(run(&mut counter, STEP_1), vec![(STEP_1, FrameClock::QUARTER)]);
+    }
+}
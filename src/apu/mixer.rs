@@ -0,0 +1,86 @@
+/// # Mixer https://www.nesdev.org/wiki/APU_Mixer
+/// Combines the channel outputs into one sample. Swap it out on the APU to change how the console sounds.
+pub trait Mixer {
+    /// # Arguments
+    ///
+    /// * `pulse1`, `pulse2`, `noise` - Channel outputs, 0-15.
+    /// * `triangle` - Triangle output, 0-15.
+    /// * `dmc` - DMC output level, 0-127.
+    ///
+    /// # Returns
+    ///
+    /// The sample, 0.0 to 1.0.
+    fn mix(&self, pulse1: u8, pulse2: u8, triangle: u8, noise: u8, dmc: u8) -> f32;
+}
+
+/// The hardware's nonlinear mix, from nesdev's lookup tables.
+///
+/// ```text
+///  pulse_table[n] = 95.52 / (8128.0 / n + 100)           n = pulse1 + pulse2
+///  tnd_table[n]   = 163.67 / (24329.0 / n + 100)         n = 3 * triangle + 2 * noise + dmc
+/// ```
+pub struct NonlinearMixer {
+    pulse_table: [f32; 31],
+    tnd_table: [f32; 203],
+}
+
+impl NonlinearMixer {
+    pub fn new() -> Self {
+        let mut mixer = NonlinearMixer { pulse_table: [0.0; 31], tnd_table: [0.0; 203] };
+        // entry 0 stays silent instead of dividing by zero
+        for (n, entry) in mixer.pulse_table.iter_mut().enumerate().skip(1) {
+            *entry = 95.52 / (8128.0 / n as f32 + 100.0);
+        }
+        for (n, entry) in mixer.tnd_table.iter_mut().enumerate().skip(1) {
+            *entry = 163.67 / (24329.0 / n as f32 + 100.0);
+        }
+        mixer
+    }
+}
+
+impl Default for NonlinearMixer {
+    fn default() -> Self {
+        NonlinearMixer::new()
+    }
+}
+
+impl Mixer for NonlinearMixer {
+    fn mix(&self, pulse1: u8, pulse2: u8, triangle: u8, noise: u8, dmc: u8) -> f32 {
+        let pulse = pulse1 as usize + pulse2 as usize;
+        let tnd = 3 * triangle as usize + 2 * noise as usize + dmc as usize;
+        self.pulse_table[pulse] + self.tnd_table[tnd]
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    /// nesdev's linear approximation of the mix.
+    pub struct LinearMixer;
+
+    impl Mixer for LinearMixer {
+        fn mix(&self, pulse1: u8, pulse2: u8, triangle: u8, noise: u8, dmc: u8) -> f32 {
+            0.00752 * (pulse1 + pulse2) as f32 + 0.00851 * triangle as f32 + 0.00494 * noise as f32 + 0.00335 * dmc as f32
+        }
+    }
+
+    #[test]
+    fn test_linear_mixer() {
+        let mixer: Box<dyn Mixer> = Box::new(LinearMixer);
+        let sample = mixer.mix(15, 15, 15, 15, 127);
+        assert!((sample - (0.2256 + 0.12765 + 0.0741 + 0.42545)).abs() < 1e-6);
+        assert_eq!(mixer.mix(0, 0, 0, 0, 0), 0.0);
+    }
+
+    #[test]
+    fn test_nonlinear_mixer() {
+        let mixer = NonlinearMixer::new();
+        assert_eq!(mixer.mix(0, 0, 0, 0, 0), 0.0);
+        // full volume on every channel peaks just under 1
+        let peak = mixer.mix(15, 15, 15, 15, 127);
+        assert!((0.99..=1.0).contains(&peak), "{}", peak);
+        // the curve flattens: doubling the pulses gives less than twice the output
+        assert!(mixer.mix(15, 15, 0, 0, 0) < 2.0 * mixer.mix(15, 0, 0, 0, 0));
+    }
+}
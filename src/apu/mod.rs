@@ -0,0 +1,244 @@
+pub mod audio_buffer;
+pub mod dmc;
+pub mod frame_counter;
+pub mod mixer;
+
+use crate::clock::CPU_CLOCK_HZ;
+use audio_buffer::AudioBuffer;
+use dmc::DmcChannel;
+use frame_counter::FrameCounter;
+use mixer::{Mixer, NonlinearMixer};
+
+const DMC_CONTROL: u16 = 0x4010;
+const DMC_DIRECT_LOAD: u16 = 0x4011;
+const DMC_SAMPLE_ADDRESS: u16 = 0x4012;
+const DMC_SAMPLE_LENGTH: u16 = 0x4013;
+const STATUS: u16 = 0x4015;
+const FRAME_COUNTER: u16 = 0x4017;
+
+/// CPU cycles the memory reader steals for each DMC sample fetch.
+pub const DMC_DMA_STALL_CYCLES: usize = 4;
+
+/// Output sample rate in Hz.
+pub const SAMPLE_RATE: f64 = 44_100.0;
+/// Samples buffered before the oldest are dropped, about 46ms.
+pub const DEFAULT_AUDIO_LATENCY: usize = 2048;
+
+pub struct NesAPU {
+    pub dmc: DmcChannel,
+    pub frame_counter: FrameCounter,
+    pub audio: AudioBuffer,
+    pub mixer: Box<dyn Mixer>,
+    pub generate_samples: bool, // OFF WHILE FAST-FORWARDING, NOTHING PLAYS AUDIO AT THAT SPEED
+    frame_irq: bool,
+    sample_clock: f64, // SAMPLE_RATE ACCUMULATED PER CPU CYCLE, A SAMPLE IS DUE AT CPU_CLOCK_HZ
+    cycles: usize,     // CPU CYCLES TICKED SINCE POWER ON
+}
+
+impl NesAPU {
+    pub fn new() -> Self {
+        NesAPU {
+            dmc: DmcChannel::new(),
+            frame_counter: FrameCounter::new(),
+            audio: AudioBuffer::new(DEFAULT_AUDIO_LATENCY),
+            mixer: Box::new(NonlinearMixer::new()),
+            generate_samples: true,
+            frame_irq: false,
+            sample_clock: 0.0,
+            cycles: 0,
+        }
+    }
+
+    /// Handles a CPU write to one of the APU registers.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The register address ($4000-$4017).
+    /// * `data` - The byte written.
+    pub fn write_register(&mut self, address: u16, data: u8) {
+        match address {
+            DMC_CONTROL => self.dmc.write_control(data),
+            DMC_DIRECT_LOAD => self.dmc.write_direct_load(data),
+            DMC_SAMPLE_ADDRESS => self.dmc.write_sample_address(data),
+            DMC_SAMPLE_LENGTH => self.dmc.write_sample_length(data),
+            STATUS => self.dmc.set_enabled(data & 0b0001_0000 != 0),
+            FRAME_COUNTER => {
+                // no channel has envelopes, sweeps or length counters to clock yet
+                self.frame_counter.write(data);
+                if self.frame_counter.irq_inhibited() {
+                    self.frame_irq = false;
+                }
+            }
+            _ => { /* channel not emulated yet */ }
+        }
+    }
+
+    /// # Status Register ($4015) https://www.nesdev.org/wiki/APU#Status_($4015)
+    ///
+    ///  7 6 5 4 3 2 1 0
+    ///  I F - D N T 2 1
+    ///  | |   +----------- DMC active (bytes remaining > 0)
+    ///  | +--------------- Frame interrupt
+    ///  +----------------- DMC interrupt
+    ///
+    /// Reading acknowledges the frame interrupt, the DMC interrupt stays set.
+    pub fn read_status(&mut self) -> u8 {
+        let status = self.peek_status();
+        self.frame_irq = false;
+        status
+    }
+
+    /// Returns what a read of $4015 would, without acknowledging the frame interrupt.
+    pub fn peek_status(&self) -> u8 {
+        let mut status = 0;
+        if self.dmc.is_active() {
+            status |= 0b0001_0000;
+        }
+        if self.frame_irq {
+            status |= 0b0100_0000;
+        }
+        if self.dmc.irq_pending() {
+            status |= 0b1000_0000;
+        }
+        status
+    }
+
+    /// Advances the APU by one CPU cycle, queueing an output sample whenever one is due
+    /// unless `generate_samples` is off.
+    pub fn tick(&mut self) {
+        self.cycles += 1;
+        self.dmc.tick();
+        if self.frame_counter.tick().irq {
+            self.frame_irq = true;
+        }
+
+        if !self.generate_samples {
+            return;
+        }
+        self.sample_clock += SAMPLE_RATE;
+        if self.sample_clock >= CPU_CLOCK_HZ {
+            self.sample_clock -= CPU_CLOCK_HZ;
+            // the DMC is the only channel so far
+            let sample = self.mixer.mix(0, 0, 0, 0, self.dmc.output_level());
+            self.audio.push_sample(sample);
+        }
+    }
+
+    /// CPU cycles the APU has run since power on.
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    /// Level of the APU's IRQ output.
+    pub fn irq(&self) -> bool {
+        self.frame_irq || self.dmc.irq_pending()
+    }
+}
+
+impl Default for NesAPU {
+    fn default() -> Self {
+        NesAPU::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_status_read_clears_frame_irq() {
+        let mut apu = NesAPU::new();
+        apu.frame_irq = true;
+        assert!(apu.irq());
+
+        // peeking leaves the flag alone
+        assert_eq!(apu.peek_status() & 0b0100_0000, 0b0100_0000);
+        assert_eq!(apu.read_status() & 0b0100_0000, 0b0100_0000);
+        assert_eq!(apu.read_status() & 0b0100_0000, 0);
+        assert!(!apu.irq());
+    }
+
+    #[test]
+    fn test_frame_counter_raises_frame_irq() {
+        let mut apu = NesAPU::new();
+        apu.write_register(FRAME_COUNTER, 0x00);
+        for _ in 0..29828 {
+            apu.tick();
+        }
+        assert!(!apu.irq());
+        apu.tick();
+        assert!(apu.irq());
+        assert_eq!(apu.read_status() & 0b0100_0000, 0b0100_0000);
+        assert!(!apu.irq());
+
+        // setting the inhibit flag acknowledges it too
+        for _ in 0..29829 {
+            apu.tick();
+        }
+        assert!(apu.irq());
+        apu.write_register(FRAME_COUNTER, 0b0100_0000);
+        assert!(!apu.irq());
+    }
+
+    #[test]
+    fn test_five_step_mode_never_raises_frame_irq() {
+        let mut apu = NesAPU::new();
+        apu.write_register(FRAME_COUNTER, 0b1000_0000);
+        for _ in 0..2 * 37281 {
+            apu.tick();
+        }
+        assert!(!apu.irq());
+    }
+
+    #[test]
+    fn test_samples_at_output_rate() {
+        let mut apu = NesAPU::new();
+        apu.audio = AudioBuffer::new(4096);
+        apu.write_register(DMC_DIRECT_LOAD, 0x40);
+        // a frame's worth of CPU cycles
+        for _ in 0..29830 {
+            apu.tick();
+        }
+        assert_eq!(apu.audio.len(), (29830.0 * SAMPLE_RATE / CPU_CLOCK_HZ) as usize);
+
+        let mut out = [0.0; 1];
+        apu.audio.drain(&mut out);
+        assert!(out[0] > 0.0 && out[0] < 1.0);
+
+        // the channels keep running without producing samples
+        apu.generate_samples = false;
+        let queued = apu.audio.len();
+        for _ in 0..29830 {
+            apu.tick();
+        }
+        assert_eq!(apu.audio.len(), queued);
+    }
+
+    #[test]
+    fn test_custom_mixer() {
+        let mut apu = NesAPU::new();
+        apu.mixer = Box::new(mixer::test::LinearMixer);
+        apu.write_register(DMC_DIRECT_LOAD, 100);
+        for _ in 0..100 {
+            apu.tick();
+        }
+
+        let mut out = [0.0; 1];
+        assert_eq!(apu.audio.drain(&mut out), 1);
+        assert!((out[0] - 0.335).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_status_read_keeps_dmc_irq() {
+        let mut apu = NesAPU::new();
+        // 1 byte sample with IRQ enabled
+        apu.write_register(DMC_CONTROL, 0x8f);
+        apu.write_register(DMC_SAMPLE_LENGTH, 0x00);
+        apu.write_register(STATUS, 0b0001_0000);
+        apu.dmc.load_sample(0);
+        assert!(apu.dmc.irq_pending());
+
+        assert_eq!(apu.read_status() & 0b1000_0000, 0b1000_0000);
+        assert_eq!(apu.read_status() & 0b1000_0000, 0b1000_0000);
+    }
+}
@@ -0,0 +1,285 @@
+use crate::cpu::AddressingMode;
+use crate::opcode::{OpCode, CPU_OP_CODES};
+use std::collections::HashMap;
+
+/// Why a line didn't assemble. Lines are numbered from 1.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum AsmError {
+    #[error("line {line}: unknown instruction {mnemonic}")]
+    UnknownInstruction { line: usize, mnemonic: String },
+    #[error("line {line}: {mnemonic} can't take the operand {operand}")]
+    BadOperand { line: usize, mnemonic: String, operand: String },
+    #[error("line {line}: undefined label {label}")]
+    UndefinedLabel { line: usize, label: String },
+    #[error("line {line}: branch target {target:04X} is out of range")]
+    BranchOutOfRange { line: usize, target: u16 },
+}
+
+enum Index {
+    X,
+    Y,
+}
+
+enum Value {
+    Number { value: u16, zero_page: bool },
+    Label(String),
+}
+
+enum Operand {
+    Implied, // NOTHING, OR A FOR THE ACCUMULATOR FORMS
+    Immediate(u8),
+    Address(Value, Option<Index>),
+    Indirect(Value),
+    IndirectX(u8),
+    IndirectY(u8),
+}
+
+struct Instruction {
+    line: usize,
+    address: u16,
+    opcode: &'static OpCode,
+    operand: Operand,
+}
+
+/// # Assembler
+/// Turns 6502 assembly into machine code, one instruction per line, official opcodes only.
+///
+/// ```text
+///  loop:  LDA #$01      ; immediate, $ for hex, % for binary, plain digits for decimal
+///         STA $10       ; two hex digits for zero page, four for absolute
+///         LDA ($20),Y   ; ,X ,Y (zp,X) (zp),Y and JMP (abs)
+///         BNE loop      ; labels work for branches, JMP, JSR and any absolute operand
+/// ```
+///
+/// # Arguments
+///
+/// * `source` - The assembly.
+/// * `origin` - The address the code will be loaded at, labels and branches are resolved against it.
+pub fn assemble(source: &str, origin: u16) -> Result<Vec<u8>, AsmError> {
+    let mut labels = HashMap::new();
+    let mut instructions = Vec::new();
+    let mut address = origin;
+
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let mut text = text.split(';').next().unwrap_or_default().trim();
+        if let Some((label, rest)) = text.split_once(':') {
+            labels.insert(label.trim().to_string(), address);
+            text = rest.trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, operand_text) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let mnemonic = mnemonic.to_ascii_uppercase();
+        if !CPU_OP_CODES.iter().any(|op| op.abbreviation == mnemonic) {
+            return Err(AsmError::UnknownInstruction { line, mnemonic });
+        }
+        let bad_operand = || AsmError::BadOperand { line, mnemonic: mnemonic.clone(), operand: operand_text.trim().to_string() };
+        let operand = parse_operand(operand_text).ok_or_else(bad_operand)?;
+        let opcode = select_opcode(&mnemonic, &operand).ok_or_else(bad_operand)?;
+
+        instructions.push(Instruction { line, address, opcode, operand });
+        address = address.wrapping_add(opcode.length as u16);
+    }
+
+    let mut program = Vec::new();
+    for instruction in instructions {
+        program.push(instruction.opcode.opcode);
+        let value = match &instruction.operand {
+            Operand::Implied => continue,
+            Operand::Immediate(value) | Operand::IndirectX(value) | Operand::IndirectY(value) => *value as u16,
+            Operand::Address(value, _) | Operand::Indirect(value) => match value {
+                Value::Number { value, .. } => *value,
+                Value::Label(label) => *labels
+                    .get(label)
+                    .ok_or_else(|| AsmError::UndefinedLabel { line: instruction.line, label: label.clone() })?,
+            },
+        };
+
+        if is_branch(instruction.opcode) {
+            let next = instruction.address.wrapping_add(2);
+            let offset = value.wrapping_sub(next) as i16;
+            if !(-128..=127).contains(&offset) {
+                return Err(AsmError::BranchOutOfRange { line: instruction.line, target: value });
+            }
+            program.push(offset as u8);
+        } else if instruction.opcode.length == 2 {
+            program.push(value as u8);
+        } else {
+            program.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    Ok(program)
+}
+
+fn is_branch(opcode: &OpCode) -> bool {
+    matches!(opcode.mode, AddressingMode::Relative)
+}
+
+/// Picks the opcode for `mnemonic` that matches the operand's shape, zero page forms first.
+fn select_opcode(mnemonic: &str, operand: &Operand) -> Option<&'static OpCode> {
+    let find = |matches_mode: fn(&AddressingMode) -> bool, length: u8| {
+        CPU_OP_CODES
+            .iter()
+            .find(|op| op.abbreviation == mnemonic && op.length == length && matches_mode(&op.mode))
+    };
+    let none = |mode: &AddressingMode| matches!(mode, AddressingMode::NoneAddressing);
+    let zero_page = |mode: &AddressingMode| matches!(mode, AddressingMode::ZeroPage);
+    let absolute = |mode: &AddressingMode| matches!(mode, AddressingMode::Absolute);
+
+    match operand {
+        Operand::Implied => find(|mode| matches!(mode, AddressingMode::Accumulator), 1).or_else(|| find(none, 1)),
+        Operand::Immediate(_) => find(|mode| matches!(mode, AddressingMode::Immediate), 2),
+        Operand::Address(value, None) => {
+            let direct = if value.is_zero_page() { find(zero_page, 2) } else { None };
+            // JMP and JSR take their address without an addressing mode, branches take a target
+            let relative = |mode: &AddressingMode| matches!(mode, AddressingMode::Relative);
+            direct.or_else(|| find(absolute, 3)).or_else(|| find(none, 3)).or_else(|| find(relative, 2))
+        }
+        Operand::Address(value, Some(Index::X)) => {
+            let direct = if value.is_zero_page() { find(|mode| matches!(mode, AddressingMode::ZeroPage_X), 2) } else { None };
+            direct.or_else(|| find(|mode| matches!(mode, AddressingMode::Absolute_X), 3))
+        }
+        Operand::Address(value, Some(Index::Y)) => {
+            let direct = if value.is_zero_page() { find(|mode| matches!(mode, AddressingMode::ZeroPage_Y), 2) } else { None };
+            direct.or_else(|| find(|mode| matches!(mode, AddressingMode::Absolute_Y), 3))
+        }
+        Operand::Indirect(_) => find(|mode| matches!(mode, AddressingMode::Indirect), 3),
+        Operand::IndirectX(_) => find(|mode| matches!(mode, AddressingMode::Indirect_X), 2),
+        Operand::IndirectY(_) => find(|mode| matches!(mode, AddressingMode::Indirect_Y), 2),
+    }
+}
+
+impl Value {
+    fn is_zero_page(&self) -> bool {
+        matches!(self, Value::Number { zero_page: true, .. })
+    }
+}
+
+fn parse_operand(text: &str) -> Option<Operand> {
+    let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if text.is_empty() || text.eq_ignore_ascii_case("A") {
+        return Some(Operand::Implied);
+    }
+    if let Some(value) = text.strip_prefix('#') {
+        return parse_zero_page(value).map(Operand::Immediate);
+    }
+    if let Some(inner) = text.strip_prefix('(') {
+        if let Some(address) = strip_suffix_ignore_case(inner, ",X)") {
+            return parse_zero_page(address).map(Operand::IndirectX);
+        }
+        if let Some(address) = strip_suffix_ignore_case(inner, "),Y") {
+            return parse_zero_page(address).map(Operand::IndirectY);
+        }
+        return parse_value(inner.strip_suffix(')')?).map(Operand::Indirect);
+    }
+    if let Some(address) = strip_suffix_ignore_case(&text, ",X") {
+        return parse_value(address).map(|value| Operand::Address(value, Some(Index::X)));
+    }
+    if let Some(address) = strip_suffix_ignore_case(&text, ",Y") {
+        return parse_value(address).map(|value| Operand::Address(value, Some(Index::Y)));
+    }
+    parse_value(&text).map(|value| Operand::Address(value, None))
+}
+
+fn strip_suffix_ignore_case<'a>(text: &'a str, suffix: &str) -> Option<&'a str> {
+    let split = text.len().checked_sub(suffix.len())?;
+    let (rest, tail) = (text.get(..split)?, text.get(split..)?);
+    tail.eq_ignore_ascii_case(suffix).then_some(rest)
+}
+
+fn parse_zero_page(text: &str) -> Option<u8> {
+    match parse_value(text)? {
+        Value::Number { value, .. } => u8::try_from(value).ok(),
+        Value::Label(_) => None,
+    }
+}
+
+/// A number, `$` hex with two digits meaning zero page, `%` binary, or decimal. Anything else that
+/// looks like an identifier is a label.
+fn parse_value(text: &str) -> Option<Value> {
+    let (digits, radix) = if let Some(hex) = text.strip_prefix('$') {
+        (hex, 16)
+    } else if let Some(binary) = text.strip_prefix('%') {
+        (binary, 2)
+    } else if text.starts_with(|c: char| c.is_ascii_digit()) {
+        (text, 10)
+    } else {
+        let is_label = text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        return is_label.then(|| Value::Label(text.to_string()));
+    };
+
+    let value = u16::from_str_radix(digits, radix).ok()?;
+    let zero_page = match radix {
+        16 => digits.len() <= 2,
+        _ => value <= 0xff,
+    };
+    Some(Value::Number { value, zero_page })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_addressing_modes() {
+        let source = "
+            LDA #$01
+            STA $10
+            STA $0200
+            LDA $10,X
+            LDX $10,Y
+            LDA $0200,X
+            LDA $10,Y     ; no zero page,Y form for LDA
+            LDA ($20,X)
+            LDA ($20),Y
+            ASL A
+            ASL
+            JMP ($0300)
+            JSR $1234
+            LDA #%00000011
+            LDA #200
+        ";
+        assert_eq!(
+            assemble(source, 0x0600).unwrap(),
+            vec![
+                0xa9, 0x01, 0x85, 0x10, 0x8d, 0x00, 0x02, 0xb5, 0x10, 0xb6, 0x10, 0xbd, 0x00, 0x02, 0xb9, 0x10,
+                0x00, 0xa1, 0x20, 0xb1, 0x20, 0x0a, 0x0a, 0x6c, 0x00, 0x03, 0x20, 0x34, 0x12, 0xa9, 0x03, 0xa9,
+                200,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_labels() {
+        let source = "
+            start:  LDX #$03
+            loop:   DEX
+                    BNE loop
+                    BEQ done
+                    JMP start
+            done:   BRK
+        ";
+        assert_eq!(
+            assemble(source, 0x0600).unwrap(),
+            vec![0xa2, 0x03, 0xca, 0xd0, 0xfd, 0xf0, 0x03, 0x4c, 0x00, 0x06, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            assemble("NOP\nFOO #$01", 0),
+            Err(AsmError::UnknownInstruction { line: 2, mnemonic: String::from("FOO") })
+        );
+        assert_eq!(
+            assemble("STA #$01", 0),
+            Err(AsmError::BadOperand { line: 1, mnemonic: String::from("STA"), operand: String::from("#$01") })
+        );
+        assert_eq!(assemble("JMP nowhere", 0), Err(AsmError::UndefinedLabel { line: 1, label: String::from("nowhere") }));
+        assert_eq!(assemble("BNE $0200", 0), Err(AsmError::BranchOutOfRange { line: 1, target: 0x0200 }));
+    }
+}
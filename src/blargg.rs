@@ -0,0 +1,139 @@
+use crate::{cpu::Memory, nes::NES};
+
+//  $6000      status: $80 running, $81 reset requested, anything else is the final result code
+//  $6001-3    DE B0 61 once the fields below are valid
+//  $6004-     NUL terminated ASCII message
+
+// BLARGG TEST ROM OUTPUT
+const STATUS_ADDRESS: u16 = 0x6000;
+const SIGNATURE_ADDRESS: u16 = 0x6001;
+const SIGNATURE: [u8; 3] = [0xde, 0xb0, 0x61];
+const MESSAGE_ADDRESS: u16 = 0x6004;
+const MESSAGE_END_ADDRESS: u16 = 0x7fff;
+
+const STATUS_RUNNING: u8 = 0x80;
+const STATUS_RESET_REQUESTED: u8 = 0x81;
+/// The ROMs want at least 100ms between asking for a reset and getting it.
+const RESET_DELAY_FRAMES: usize = 6;
+
+/// What a finished test ROM reported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestResult {
+    /// The result code, 0 when every test passed.
+    pub status: u8,
+    pub message: String,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.status == 0
+    }
+}
+
+/// # Blargg Test Runner https://github.com/christopherpow/nes-test-roms/tree/master/instr_test-v5
+/// Runs one of blargg's test ROMs, frame by frame, until it posts a final result in SRAM.
+/// Resets the console when the ROM asks for it.
+/// Returns `None` if the ROM hasn't finished within `max_frames`, or the CPU hit BRK first.
+///
+/// # Arguments
+///
+/// * `nes` - A console with the test ROM inserted.
+/// * `max_frames` - How many frames to give the ROM.
+pub fn run(nes: &mut NES, max_frames: usize) -> Option<TestResult> {
+    let mut reset_countdown = None;
+    for _ in 0..max_frames {
+        if !nes.run_one_frame() {
+            return None;
+        }
+        if !has_signature(nes) {
+            continue;
+        }
+
+        match (nes.cpu.peek(STATUS_ADDRESS), reset_countdown) {
+            (STATUS_RUNNING, _) => {}
+            (STATUS_RESET_REQUESTED, None) => reset_countdown = Some(RESET_DELAY_FRAMES),
+            (STATUS_RESET_REQUESTED, Some(0)) => {
+                nes.reset();
+                reset_countdown = None;
+            }
+            (STATUS_RESET_REQUESTED, Some(frames)) => reset_countdown = Some(frames - 1),
+            (status, _) => {
+                return Some(TestResult { status, message: read_message(nes) });
+            }
+        }
+    }
+    None
+}
+
+/// Whether the ROM has set up its output block yet, before that $6000 holds garbage.
+fn has_signature(nes: &NES) -> bool {
+    SIGNATURE.iter().enumerate().all(|(i, &byte)| nes.cpu.peek(SIGNATURE_ADDRESS + i as u16) == byte)
+}
+
+/// Reads the NUL terminated message from SRAM.
+fn read_message(nes: &NES) -> String {
+    (MESSAGE_ADDRESS..=MESSAGE_END_ADDRESS)
+        .map(|address| nes.cpu.peek(address))
+        .take_while(|&byte| byte != 0)
+        .map(char::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::test;
+
+    /// Builds a ROM following the blargg protocol: it marks itself running, waits 3 frames,
+    /// then writes `message` and the final `status`.
+    fn protocol_rom(status: u8, message: &str) -> NES {
+        let mut prg = vec![0; 0x8000];
+        let program = [
+            0x78, // SEI
+            0xa9, 0x80, 0x8d, 0x00, 0x60, // LDA #$80; STA $6000
+            0xa9, 0xde, 0x8d, 0x01, 0x60, // LDA #$DE; STA $6001
+            0xa9, 0xb0, 0x8d, 0x02, 0x60, // LDA #$B0; STA $6002
+            0xa9, 0x61, 0x8d, 0x03, 0x60, // LDA #$61; STA $6003
+            0xa0, 0x03, // LDY #$03
+            0x2c, 0x02, 0x20, // frame: BIT $2002
+            0x10, 0xfb, // BPL frame
+            0x88, 0xd0, 0xf8, // DEY; BNE frame
+            0xa2, 0x00, // LDX #$00
+            0xbd, 0x00, 0x81, // copy: LDA $8100,X
+            0x9d, 0x04, 0x60, // STA $6004,X
+            0xf0, 0x04, // BEQ done
+            0xe8, 0x4c, 0x21, 0x80, // INX; JMP copy
+            0xa9, status, 0x8d, 0x00, 0x60, // done: LDA #status; STA $6000
+            0x4c, 0x32, 0x80, // spin: JMP spin
+        ];
+        prg[..program.len()].copy_from_slice(&program);
+        prg[0x100..0x100 + message.len()].copy_from_slice(message.as_bytes());
+        prg[0x7ffa..].copy_from_slice(&[0x00, 0x80, 0x00, 0x80, 0x00, 0x80]);
+        NES::from_rom(test::test_rom_with_prg(prg))
+    }
+
+    #[test]
+    fn test_passing_rom() {
+        let mut nes = protocol_rom(0, "Passed\n");
+        // still running after the first frame
+        nes.run_one_frame();
+        assert!(has_signature(&nes));
+        assert_eq!(nes.cpu.peek(STATUS_ADDRESS), STATUS_RUNNING);
+
+        let result = run(&mut nes, 10).expect("the ROM should finish");
+        assert!(result.passed());
+        assert_eq!(result.message, "Passed\n");
+    }
+
+    #[test]
+    fn test_failing_rom() {
+        let result = run(&mut protocol_rom(3, "ADC #n\n\nFailed #3\n"), 10).unwrap();
+        assert!(!result.passed());
+        assert_eq!(result, TestResult { status: 3, message: String::from("ADC #n\n\nFailed #3\n") });
+    }
+
+    #[test]
+    fn test_times_out() {
+        assert_eq!(run(&mut protocol_rom(0, "Passed\n"), 2), None);
+    }
+}
@@ -1,4 +1,14 @@
-use crate::{cartridge::Rom, cpu::Memory};
+use crate::{
+    apu::{audio_buffer::AudioBuffer, mixer::Mixer, NesAPU, DMC_DMA_STALL_CYCLES},
+    cartridge::{Rom, RomError},
+    clock::{Clock, DividerClock},
+    mapper::{self, Mapper, MapperState},
+    cpu::Memory,
+    game_genie::{Cheat, GameGenie, GgError},
+    joypad::Joypad,
+    ppu::NesPPU,
+};
+use std::collections::HashMap;
 
 //  _______________ $10000  _______________
 // | PRG-ROM       |       |               |
@@ -31,35 +41,522 @@ use crate::{cartridge::Rom, cpu::Memory};
 // RICOH 2A03 MEMORY BUS DIAGRAM
 
 const RAM_ADDRESS: u16 = 0x0000;
+const RAM_MIRRORS_ADDRESS_START: u16 = 0x0800;
 const RAM_END_ADDRESS: u16 = 0x1FFF;
 const PPU_REGISTERS_ADDRESS: u16 = 0x2000;
+const PPU_REGISTERS_MIRRORS_START: u16 = 0x2008;
 const PPU_REGISTERS_END_ADDRESS: u16 = 0x3FFF;
+const APU_IO_ADDRESS: u16 = 0x4000;
+const APU_STATUS_ADDRESS: u16 = 0x4015;
+const JOYPAD1_ADDRESS: u16 = 0x4016;
+const JOYPAD2_ADDRESS: u16 = 0x4017;
+const APU_IO_END_ADDRESS: u16 = 0x401F;
+const EXPANSION_ROM_ADDRESS: u16 = 0x4020;
+const EXPANSION_ROM_END_ADDRESS: u16 = 0x5FFF;
+const SRAM_ADDRESS: u16 = 0x6000;
+const SRAM_END_ADDRESS: u16 = 0x7FFF;
+const PRG_ROM_ADDRESS: u16 = 0x8000;
+const PRG_ROM_END_ADDRESS: u16 = 0xFFFF;
+const VECTORS_ADDRESS: u16 = 0xFFFA;
+/// The bits of a controller port read nothing drives. They keep the last byte on the data bus,
+/// which is the high byte of the $4016/$4017 operand, so reads come back as $40 or $41.
+const CONTROLLER_OPEN_BUS_MASK: u8 = 0b1110_0000;
+/// JMP ($FFFC), appended to the boot stub to hand over to the cartridge.
+const JMP_TO_RESET_VECTOR: [u8; 3] = [0x6c, 0xfc, 0xff];
+
+/// The regions of the CPU address space, as laid out in the memory bus diagram above.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MemRegion {
+    /// $0000-$07FF, the 2KB of internal RAM.
+    Ram,
+    /// $0800-$1FFF, mirrors of internal RAM.
+    RamMirror,
+    /// $2000-$2007, the 8 PPU registers.
+    PpuRegister,
+    /// $2008-$3FFF, mirrors of the PPU registers every 8 bytes.
+    PpuRegisterMirror,
+    /// $4000-$401F except the controller ports, APU and I/O registers.
+    ApuIo,
+    /// $4016-$4017, the controller ports.
+    Controller,
+    /// $4020-$5FFF, cartridge expansion area.
+    ExpansionRom,
+    /// $6000-$7FFF, cartridge SRAM.
+    Sram,
+    /// $8000-$FFFF, cartridge PRG-ROM.
+    PrgRom,
+}
+
+/// Labels the region an address falls into, matching the Bus's decode logic.
+///
+/// # Arguments
+///
+/// * `address` - A CPU address.
+pub fn classify_address(address: u16) -> MemRegion {
+    match address {
+        RAM_ADDRESS..RAM_MIRRORS_ADDRESS_START => MemRegion::Ram,
+        RAM_MIRRORS_ADDRESS_START..=RAM_END_ADDRESS => MemRegion::RamMirror,
+        PPU_REGISTERS_ADDRESS..PPU_REGISTERS_MIRRORS_START => MemRegion::PpuRegister,
+        PPU_REGISTERS_MIRRORS_START..=PPU_REGISTERS_END_ADDRESS => MemRegion::PpuRegisterMirror,
+        JOYPAD1_ADDRESS | JOYPAD2_ADDRESS => MemRegion::Controller,
+        APU_IO_ADDRESS..=APU_IO_END_ADDRESS => MemRegion::ApuIo,
+        EXPANSION_ROM_ADDRESS..=EXPANSION_ROM_END_ADDRESS => MemRegion::ExpansionRom,
+        SRAM_ADDRESS..=SRAM_END_ADDRESS => MemRegion::Sram,
+        PRG_ROM_ADDRESS..=PRG_ROM_END_ADDRESS => MemRegion::PrgRom,
+    }
+}
+
+const MEM_REGION_COUNT: usize = 9;
+
+/// Number of CPU reads and writes that hit each region of the address space.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AccessStats {
+    reads: [u64; MEM_REGION_COUNT],
+    writes: [u64; MEM_REGION_COUNT],
+}
+
+impl AccessStats {
+    pub fn reads(&self, region: MemRegion) -> u64 {
+        self.reads[region as usize]
+    }
+
+    pub fn writes(&self, region: MemRegion) -> u64 {
+        self.writes[region as usize]
+    }
+
+    fn record(&mut self, kind: AccessKind, address: u16) {
+        let region = classify_address(address) as usize;
+        match kind {
+            AccessKind::Read => self.reads[region] += 1,
+            AccessKind::Write => self.writes[region] += 1,
+        }
+    }
+}
+
+/// Whether an access was a read or a write.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
 
 pub struct Bus {
     cpu_vram: [u8; 2048],
-    rom: Rom,
+    prg_ram: [u8; 0x2000], // CARTRIDGE SRAM AT $6000-$7FFF
+    cartridge: Rom,
+    mapper: Box<dyn Mapper>,
+    game_genie: GameGenie,
+    pub ppu: NesPPU,
+    apu: NesAPU,
+    pub joypad1: Joypad,
+    pub joypad2: Joypad,
+    pub famicom_mode: bool, // WIRE UP THE FAMICOM MICROPHONE ON $4016
+    pub microphone: bool,   // LEVEL OF THE MICROPHONE ON THE SECOND FAMICOM CONTROLLER
+    unmapped_access_log: Option<Box<dyn FnMut(AccessKind, u16)>>,
+    access_stats: AccessStats,
+    write_callbacks: HashMap<u16, Vec<WriteCallback>>, // ONLY REGISTERED ADDRESSES ARE LOOKED UP
+    boot_stub: Vec<u8>,      // BOOT CODE RIGHT BELOW THE VECTORS, EMPTY FOR NONE
+    boot_stub_mapped: bool,  // THE STUB SHADOWS PRG-ROM UNTIL IT HANDS OVER TO THE CARTRIDGE
+    clock: Box<dyn Clock>,   // PPU AND APU TICKS PER CPU CYCLE
 }
 
+type WriteCallback = Box<dyn FnMut(u8)>;
+
 impl Bus {
     pub fn new(rom: Rom) -> Self {
+        let mapper = mapper::from_rom(&rom);
+        let ppu = NesPPU::new(mapper.chr_view(), mapper.mirroring());
+        // PAL frames are still 262 lines, so even PAL cartridges run on the NTSC clock unless asked otherwise
+        let clock = Box::new(DividerClock::ntsc());
         Bus {
             cpu_vram: [0; 2048],
-            rom,
+            prg_ram: [0; 0x2000],
+            cartridge: rom,
+            mapper,
+            ppu,
+            game_genie: GameGenie::new(),
+            apu: NesAPU::new(),
+            joypad1: Joypad::new(),
+            joypad2: Joypad::new(),
+            famicom_mode: false,
+            microphone: false,
+            unmapped_access_log: None,
+            access_stats: AccessStats::default(),
+            write_callbacks: HashMap::new(),
+            boot_stub: Vec::new(),
+            boot_stub_mapped: false,
+            clock,
+        }
+    }
+
+    /// D5-D7 of a controller port read, left floating at the high byte of the address.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - $4016 or $4017.
+    fn controller_open_bus(address: u16) -> u8 {
+        (address >> 8) as u8 & CONTROLLER_OPEN_BUS_MASK
+    }
+
+    /// On a Famicom, $4016 reads carry the microphone level in bit 2.
+    fn microphone_bit(&self) -> u8 {
+        if self.famicom_mode && self.microphone {
+            0b0000_0100
+        } else {
+            0
+        }
+    }
+
+    /// The cartridge in the slot.
+    pub fn cartridge(&self) -> &Rom {
+        &self.cartridge
+    }
+
+    /// Parses an iNES image and plugs it in.
+    ///
+    /// # Arguments
+    ///
+    /// * `raw` - The raw bytes of an iNES file.
+    pub fn from_ines_bytes(raw: &[u8]) -> Result<Self, RomError> {
+        Ok(Bus::new(Rom::from_bytes(raw)?))
+    }
+
+    /// Swaps the cartridge in the slot.
+    ///
+    /// Follows power-cycle semantics: the previous cartridge's state is dropped and RAM is cleared.
+    /// The SRAM belonged to the old cartridge, so it's cleared too, battery or not.
+    ///
+    /// # Arguments
+    ///
+    /// * `rom` - The cartridge to insert.
+    pub fn insert_cartridge(&mut self, rom: Rom) {
+        self.cartridge = rom;
+        self.prg_ram = [0; 0x2000];
+        self.power_cycle();
+    }
+
+    /// Turns the console off and on again: RAM, PPU, APU and the mapper's registers come back
+    /// as `new` builds them. RAM is zero filled, and so is SRAM unless the cartridge has a battery keeping it.
+    /// The controllers, Game Genie codes, access log, boot stub, clock, audio buffer and mixer are outside the console and stay as they are.
+    pub fn power_cycle(&mut self) {
+        self.mapper = mapper::from_rom(&self.cartridge);
+        self.ppu = NesPPU::new(self.mapper.chr_view(), self.mapper.mirroring());
+        self.cpu_vram = [0; 2048];
+        if !self.cartridge.has_battery {
+            self.prg_ram = [0; 0x2000];
+        }
+        let mut apu = NesAPU::new();
+        std::mem::swap(&mut apu.audio, &mut self.apu.audio);
+        std::mem::swap(&mut apu.mixer, &mut self.apu.mixer);
+        apu.generate_samples = self.apu.generate_samples;
+        self.apu = apu;
+    }
+
+    /// Advances the devices on the bus by the cycles the CPU just spent.
+    ///
+    /// DMC sample fetches steal the bus from the CPU, so the returned count includes
+    /// those stalls on top of `cycles`.
+    ///
+    /// # Arguments
+    ///
+    /// * `cycles` - The number of CPU cycles elapsed.
+    pub fn tick(&mut self, cycles: u8) -> usize {
+        let mut remaining = cycles as usize;
+        let mut elapsed = 0;
+        while remaining > 0 {
+            let ticks = self.clock.cpu_cycle();
+            for _ in 0..ticks.apu_cycles {
+                self.apu.tick();
+            }
+            self.ppu.tick(ticks.ppu_dots);
+            self.clock_mapper_a12();
+            remaining -= 1;
+            elapsed += 1;
+
+            if let Some(address) = self.apu.dmc.pending_fetch() {
+                let data = self.mem_read(address);
+                self.apu.dmc.load_sample(data);
+                remaining += DMC_DMA_STALL_CYCLES;
+            }
+        }
+        elapsed
+    }
+
+    /// Takes the NMI raised by the PPU entering VBlank, if any.
+    pub fn poll_nmi_status(&mut self) -> Option<u8> {
+        self.ppu.poll_nmi_interrupt()
+    }
+
+    /// Level of the shared IRQ line into the CPU.
+    pub fn poll_irq(&self) -> bool {
+        self.apu.irq() || self.mapper.irq()
+    }
+
+    /// Samples the APU produced that haven't been played yet.
+    pub fn audio_buffer(&mut self) -> &mut AudioBuffer {
+        &mut self.apu.audio
+    }
+
+    /// Turns the APU's sample output on or off, e.g. off while fast-forwarding. The channels keep running either way.
+    ///
+    /// # Arguments
+    ///
+    /// * `on` - Whether samples are queued into the audio buffer.
+    pub fn set_generate_samples(&mut self, on: bool) {
+        self.apu.generate_samples = on;
+    }
+
+    /// Installs code to run on reset before the cartridge, like a console boot ROM.
+    /// It is placed right below the vectors at $FFFA and followed by a JMP ($FFFC) into the cartridge.
+    ///
+    /// # Arguments
+    ///
+    /// * `stub` - The machine code, `None` to go straight to the cartridge.
+    pub fn set_boot_stub(&mut self, stub: Option<Vec<u8>>) {
+        self.boot_stub = stub
+            .map(|mut code| {
+                code.extend(JMP_TO_RESET_VECTOR);
+                assert!(code.len() <= (VECTORS_ADDRESS - PRG_ROM_ADDRESS) as usize, "boot stub doesn't fit in PRG-ROM");
+                code
+            })
+            .unwrap_or_default();
+        self.boot_stub_mapped = false;
+    }
+
+    /// Lays the boot stub over PRG-ROM and returns its entry point, or `None` if there is no stub.
+    pub fn map_boot_stub(&mut self) -> Option<u16> {
+        if self.boot_stub.is_empty() {
+            return None;
         }
+        self.boot_stub_mapped = true;
+        Some(self.boot_stub_start())
+    }
+
+    /// Uncovers the PRG-ROM under the boot stub once it has handed over to the cartridge.
+    pub fn unmap_boot_stub(&mut self) {
+        self.boot_stub_mapped = false;
+    }
+
+    fn boot_stub_start(&self) -> u16 {
+        VECTORS_ADDRESS - self.boot_stub.len() as u16
     }
 
-    fn read_prg_rom(&self, mut address: u16) -> u8 {
-        address -= 0x8000;
-        if self.rom.prg_rom.len() == 0x4000 && address >= 0x4000 {
-            //mirror if needed
-            address = address % 0x4000;
+    /// Replaces the clock driving the PPU and APU, e.g. with `DividerClock::pal()` to opt into PAL's dot ratio.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - The new clock, `DividerClock::ntsc` is the default.
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// The clock driving the PPU and APU.
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    /// Replaces how the APU mixes its channels into samples.
+    ///
+    /// # Arguments
+    ///
+    /// * `mixer` - The new mixer, `NonlinearMixer` is the default.
+    pub fn set_mixer(&mut self, mixer: Box<dyn Mixer>) {
+        self.apu.mixer = mixer;
+    }
+
+    /// Forwards A12 rising edges seen by the PPU to the mapper.
+    fn clock_mapper_a12(&mut self) {
+        for _ in 0..self.ppu.take_a12_rises() {
+            self.mapper.clock_a12();
+        }
+    }
+
+    /// Re-syncs the PPU with the mapper after its banking or mirroring registers were written.
+    fn sync_mapper(&mut self) {
+        // CHR-RAM lives in the PPU and has no banks to follow
+        if !self.ppu.chr_ram {
+            self.ppu.chr_rom = self.mapper.chr_view();
+        }
+        self.ppu.mirroring = self.mapper.mirroring();
+    }
+
+    /// The 2KB of internal CPU RAM.
+    pub fn ram(&self) -> &[u8] {
+        &self.cpu_vram
+    }
+
+    /// Overwrites the internal CPU RAM, e.g. when loading a save state.
+    ///
+    /// # Arguments
+    ///
+    /// * `ram` - Exactly 2KB of RAM contents.
+    pub fn load_ram(&mut self, ram: &[u8]) {
+        self.cpu_vram.copy_from_slice(ram);
+    }
+
+    /// The mapper's registers, for a save state.
+    pub fn mapper_state(&self) -> MapperState {
+        self.mapper.save_state()
+    }
+
+    /// Puts the mapper's registers back, e.g. when loading a save state, and re-banks the PPU's CHR to match.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - A snapshot taken by `mapper_state`.
+    pub fn load_mapper_state(&mut self, state: &MapperState) {
+        self.mapper.load_state(state);
+        self.sync_mapper();
+    }
+
+    /// The 8KB of cartridge SRAM at $6000-$7FFF.
+    pub fn sram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    /// Overwrites the cartridge SRAM, e.g. when loading a save state or a battery save.
+    ///
+    /// # Arguments
+    ///
+    /// * `sram` - Exactly 8KB of SRAM contents.
+    pub fn load_sram(&mut self, sram: &[u8]) {
+        self.prg_ram.copy_from_slice(sram);
+    }
+
+    /// Sets `len` bytes starting at `start` to `value`, e.g. to set up a test or poke a cheat.
+    /// Only RAM (mirrors included) and SRAM are written, registers and the mapper are left alone.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - The first address, the range wraps around at $FFFF.
+    /// * `len` - How many addresses to cover.
+    /// * `value` - The byte to write.
+    pub fn fill(&mut self, start: u16, len: u16, value: u8) {
+        for offset in 0..len {
+            let address = start.wrapping_add(offset);
+            match address {
+                RAM_ADDRESS..=RAM_END_ADDRESS => self.cpu_vram[(address & 0b11111111111) as usize] = value,
+                SRAM_ADDRESS..=SRAM_END_ADDRESS => self.prg_ram[(address - SRAM_ADDRESS) as usize] = value,
+                _ => {}
+            }
+        }
+    }
+
+    /// Every RAM and SRAM address holding `value`, in order. RAM mirrors are left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The byte to look for.
+    pub fn search(&self, value: u8) -> Vec<u16> {
+        let ram = (RAM_ADDRESS..RAM_MIRRORS_ADDRESS_START).zip(self.cpu_vram.iter());
+        let sram = (SRAM_ADDRESS..=SRAM_END_ADDRESS).zip(self.prg_ram.iter());
+        ram.chain(sram)
+            .filter(|(_, &byte)| byte == value)
+            .map(|(address, _)| address)
+            .collect()
+    }
+
+    /// Activates a Game Genie code, patching PRG-ROM reads at the code's address.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - A 6 or 8 letter Game Genie code.
+    pub fn add_code(&mut self, code: &str) -> Result<(), GgError> {
+        self.game_genie.add_code(code)
+    }
+
+    /// The Game Genie codes entered so far, enabled or not.
+    pub fn cheats(&self) -> &[Cheat] {
+        self.game_genie.cheats()
+    }
+
+    /// Turns a Game Genie code on or off, keeping it in the list. Panics if there's no code at `index`.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Position of the code in `cheats`.
+    /// * `enabled` - Whether it patches reads.
+    pub fn toggle_cheat(&mut self, index: usize, enabled: bool) {
+        self.game_genie.set_enabled(index, enabled);
+    }
+
+    /// Removes every Game Genie code.
+    pub fn clear_cheats(&mut self) {
+        self.game_genie.clear();
+    }
+
+    /// Installs a hook called for every CPU read or write to an address nothing is mapped at.
+    /// Without one, such accesses are silently ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `log` - Called with the kind of access and the address.
+    pub fn set_unmapped_access_log<F>(&mut self, log: F)
+    where
+        F: FnMut(AccessKind, u16) + 'static,
+    {
+        self.unmapped_access_log = Some(Box::new(log));
+    }
+
+    /// Registers `cb` to be called with the value of every CPU write to `addr`, e.g. to watch a PPU or APU register.
+    /// Writes through a mirror of `addr` don't count. Several callbacks may watch the same address.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The exact address to watch.
+    /// * `cb` - Called with the byte written, before the write takes effect.
+    pub fn on_write(&mut self, addr: u16, cb: WriteCallback) {
+        self.write_callbacks.entry(addr).or_default().push(cb);
+    }
+
+    /// Reads and writes per region since construction or the last `reset_access_stats`.
+    /// Side-effect free peeks aren't counted.
+    pub fn access_stats(&self) -> AccessStats {
+        self.access_stats.clone()
+    }
+
+    pub fn reset_access_stats(&mut self) {
+        self.access_stats = AccessStats::default();
+    }
+
+    fn log_unmapped_access(&mut self, kind: AccessKind, address: u16) {
+        if let Some(log) = self.unmapped_access_log.as_mut() {
+            log(kind, address);
         }
-        self.rom.prg_rom[address as usize]
     }
 }
 
 impl Memory for Bus {
-    fn mem_read(&self, address: u16) -> u8 {
+    fn mem_read(&mut self, address: u16) -> u8 {
+        self.access_stats.record(AccessKind::Read, address);
+        match address {
+            PPU_REGISTERS_ADDRESS ..= PPU_REGISTERS_END_ADDRESS => {
+                let mirror_bus_address = address & 0b00100000_00000111;
+                let data = self.ppu.read_register(mirror_bus_address);
+                self.clock_mapper_a12();
+                data
+            }
+            JOYPAD1_ADDRESS => Bus::controller_open_bus(address) | self.joypad1.read() | self.microphone_bit(),
+            JOYPAD2_ADDRESS => Bus::controller_open_bus(address) | self.joypad2.read(),
+            APU_STATUS_ADDRESS => self.apu.read_status(),
+            RAM_ADDRESS ..= RAM_END_ADDRESS | SRAM_ADDRESS..=SRAM_END_ADDRESS | PRG_ROM_ADDRESS..=PRG_ROM_END_ADDRESS => {
+                self.peek(address)
+            }
+            EXPANSION_ROM_ADDRESS..=EXPANSION_ROM_END_ADDRESS => match self.mapper.read_exp(address) {
+                Some(data) => data,
+                None => {
+                    self.log_unmapped_access(AccessKind::Read, address);
+                    0
+                }
+            },
+            _ => {
+                self.log_unmapped_access(AccessKind::Read, address);
+                0
+            }
+        }
+    }
+
+    fn peek(&self, address: u16) -> u8 {
         match address {
             RAM_ADDRESS ..= RAM_END_ADDRESS => {
                 let mirror_bus_address = address & 0b00000111_11111111;
@@ -67,18 +564,28 @@ impl Memory for Bus {
             }
             PPU_REGISTERS_ADDRESS ..= PPU_REGISTERS_END_ADDRESS => {
                 let mirror_bus_address = address & 0b00100000_00000111;
-                todo!("PPU NOT SUPPORTED YET")
+                self.ppu.peek(mirror_bus_address)
             }
-            0x8000..=0xFFFF => self.read_prg_rom(address),
-            _ => {
-                println!("Ignoring memory address as {:?}", address);
-                0
+            SRAM_ADDRESS..=SRAM_END_ADDRESS => self.prg_ram[(address - SRAM_ADDRESS) as usize],
+            PRG_ROM_ADDRESS..VECTORS_ADDRESS if self.boot_stub_mapped && address >= self.boot_stub_start() => {
+                self.boot_stub[(address - self.boot_stub_start()) as usize]
             }
-            
+            PRG_ROM_ADDRESS..=PRG_ROM_END_ADDRESS => self.game_genie.patch(address, self.mapper.read_prg(address)),
+            APU_STATUS_ADDRESS => self.apu.peek_status(),
+            JOYPAD1_ADDRESS => Bus::controller_open_bus(address) | self.joypad1.peek() | self.microphone_bit(),
+            JOYPAD2_ADDRESS => Bus::controller_open_bus(address) | self.joypad2.peek(),
+            EXPANSION_ROM_ADDRESS..=EXPANSION_ROM_END_ADDRESS => self.mapper.read_exp(address).unwrap_or(0),
+            _ => 0,
         }
     }
 
     fn mem_write(&mut self, address: u16, data: u8) {
+        self.access_stats.record(AccessKind::Write, address);
+        if let Some(callbacks) = self.write_callbacks.get_mut(&address) {
+            for callback in callbacks.iter_mut() {
+                callback(data);
+            }
+        }
         match address {
             RAM_ADDRESS ..= RAM_END_ADDRESS => {
                 let mirror_bus_address = address & 0b11111111111;
@@ -86,22 +593,51 @@ impl Memory for Bus {
             }
             PPU_REGISTERS_ADDRESS ..= PPU_REGISTERS_END_ADDRESS => {
                 let mirror_bus_address = address & 0b00100000_00000111;
-                todo!("PPU NOT SUPPORTED YET");
+                self.ppu.write_register(mirror_bus_address, data);
+                self.clock_mapper_a12();
             }
-            0x8000..=0xFFFF => {
-                panic!("Attempt to write to Cartridge ROM space")
+            SRAM_ADDRESS..=SRAM_END_ADDRESS => self.prg_ram[(address - SRAM_ADDRESS) as usize] = data,
+            PRG_ROM_ADDRESS..=PRG_ROM_END_ADDRESS => {
+                self.mapper.write_prg(address, data);
+                self.sync_mapper();
             }
-            _ => {
-                println!("Ignoring memory write-access attempt at {:?}", address);
+            APU_IO_ADDRESS..JOYPAD1_ADDRESS | JOYPAD2_ADDRESS => self.apu.write_register(address, data),
+            JOYPAD1_ADDRESS => {
+                // the strobe line is shared by both controller ports
+                self.joypad1.write(data);
+                self.joypad2.write(data);
             }
+            EXPANSION_ROM_ADDRESS..=EXPANSION_ROM_END_ADDRESS => {
+                if self.mapper.write_exp(address, data) {
+                    self.sync_mapper();
+                } else {
+                    self.log_unmapped_access(AccessKind::Write, address);
+                }
+            }
+            _ => self.log_unmapped_access(AccessKind::Write, address),
         }
     }
+
+    fn tick(&mut self, cycles: u8) -> usize {
+        Bus::tick(self, cycles)
+    }
+
+    fn poll_nmi(&mut self) -> bool {
+        self.poll_nmi_status().is_some()
+    }
+
+    fn poll_irq(&self) -> bool {
+        Bus::poll_irq(self)
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::cartridge::test;
+    use crate::cartridge::{test, Mirroring, Region};
+    use crate::joypad::JoypadButton;
+    use std::cell::RefCell;
+    use std::rc::Rc;
 
     #[test]
     fn test_mem_read_write_to_ram() {
@@ -109,4 +645,420 @@ mod test {
         bus.mem_write(0x01, 0x55);
         assert_eq!(bus.mem_read(0x01), 0x55);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_game_genie_six_letter_code_patches_prg_read() {
+        let mut bus = Bus::new(test::test_rom());
+        assert_eq!(bus.mem_read(0xd1dd), 0x01);
+
+        bus.add_code("GOSSIP").unwrap();
+        assert_eq!(bus.mem_read(0xd1dd), 0x14);
+        assert_eq!(bus.mem_read(0xd1de), 0x01);
+    }
+
+    #[test]
+    fn test_game_genie_eight_letter_code_checks_compare() {
+        // ZEXPYGLA patches $94A7 to $02 only while the ROM holds $03
+        let mut bus = Bus::new(test::test_rom_with_program(&[0x03], 0x94a7));
+        bus.add_code("ZEXPYGLA").unwrap();
+        assert_eq!(bus.mem_read(0x94a7), 0x02);
+
+        let mut bus = Bus::new(test::test_rom());
+        bus.add_code("ZEXPYGLA").unwrap();
+        assert_eq!(bus.mem_read(0x94a7), 0x01);
+    }
+
+    #[test]
+    fn test_classify_address() {
+        assert_eq!(classify_address(0x0000), MemRegion::Ram);
+        assert_eq!(classify_address(0x07ff), MemRegion::Ram);
+        assert_eq!(classify_address(0x0801), MemRegion::RamMirror);
+        assert_eq!(classify_address(0x1fff), MemRegion::RamMirror);
+        assert_eq!(classify_address(0x2002), MemRegion::PpuRegister);
+        assert_eq!(classify_address(0x2008), MemRegion::PpuRegisterMirror);
+        assert_eq!(classify_address(0x3fff), MemRegion::PpuRegisterMirror);
+        assert_eq!(classify_address(0x4000), MemRegion::ApuIo);
+        assert_eq!(classify_address(0x4015), MemRegion::ApuIo);
+        assert_eq!(classify_address(0x4016), MemRegion::Controller);
+        assert_eq!(classify_address(0x4017), MemRegion::Controller);
+        assert_eq!(classify_address(0x401f), MemRegion::ApuIo);
+        assert_eq!(classify_address(0x4020), MemRegion::ExpansionRom);
+        assert_eq!(classify_address(0x6000), MemRegion::Sram);
+        assert_eq!(classify_address(0x7fff), MemRegion::Sram);
+        assert_eq!(classify_address(0x8000), MemRegion::PrgRom);
+        assert_eq!(classify_address(0xfffc), MemRegion::PrgRom);
+    }
+
+    #[test]
+    fn test_ppustatus_read_through_bus() {
+        let mut bus = Bus::new(test::test_rom());
+        bus.ppu.status.set_vblank_status(true);
+        bus.mem_write(0x2006, 0x21);
+
+        // peeking leaves the flag and the write toggle alone
+        assert_eq!(bus.peek(0x2002) & 0x80, 0x80);
+        // $3FFA mirrors $2002
+        assert_eq!(bus.mem_read(0x3ffa) & 0x80, 0x80);
+        assert_eq!(bus.mem_read(0x2002) & 0x80, 0);
+
+        // the toggle was reset, so this pair starts with the high byte again
+        bus.mem_write(0x2006, 0x23);
+        bus.mem_write(0x2006, 0x05);
+        assert_eq!(bus.ppu.addr.get(), 0x2305);
+    }
+
+    #[test]
+    fn test_unmapped_access_log() {
+        let mut bus = Bus::new(test::test_rom());
+        // silent by default
+        assert_eq!(bus.mem_read(0x5000), 0);
+
+        let accesses = Rc::new(RefCell::new(vec![]));
+        let recorder = accesses.clone();
+        bus.set_unmapped_access_log(move |kind, address| recorder.borrow_mut().push((kind, address)));
+
+        assert_eq!(bus.mem_read(0x4018), 0);
+        bus.mem_write(0x4020, 0x12);
+        // mapped accesses and peeks aren't reported
+        bus.mem_write(0x0010, 0x34);
+        bus.mem_read(0x0010);
+        bus.peek(0x4018);
+
+        assert_eq!(*accesses.borrow(), vec![(AccessKind::Read, 0x4018), (AccessKind::Write, 0x4020)]);
+    }
+
+    #[test]
+    fn test_on_write() {
+        let mut bus = Bus::new(test::test_rom());
+        let written = Rc::new(RefCell::new(vec![]));
+        let recorder = written.clone();
+        bus.on_write(0x2000, Box::new(move |data| recorder.borrow_mut().push(data)));
+
+        bus.mem_write(0x2000, 0x80);
+        // neither other registers nor mirrors of $2000
+        bus.mem_write(0x2001, 0x1e);
+        bus.mem_write(0x2008, 0x90);
+        bus.mem_write(0x2000, 0x00);
+
+        assert_eq!(*written.borrow(), vec![0x80, 0x00]);
+        // and the write still went through
+        assert!(!bus.ppu.ctrl.generate_vblank_nmi());
+    }
+
+    #[test]
+    fn test_sram() {
+        let mut bus = Bus::new(test::test_rom());
+        bus.mem_write(0x6000, 0x80);
+        bus.mem_write(0x7fff, 0x42);
+        assert_eq!(bus.mem_read(0x6000), 0x80);
+        assert_eq!(bus.peek(0x7fff), 0x42);
+        // no mirroring into RAM
+        assert_eq!(bus.mem_read(0x0000), 0);
+
+        bus.power_cycle();
+        assert_eq!(bus.mem_read(0x6000), 0);
+    }
+
+    #[test]
+    fn test_fill_and_search() {
+        let mut bus = Bus::new(test::test_rom());
+        bus.fill(0x0300, 0x10, 0xab);
+        // through the mirrors, and across the registers into SRAM
+        bus.fill(0x0810, 2, 0xab);
+        bus.fill(0x5ffe, 4, 0xab);
+
+        let mut expected: Vec<u16> = (0x0300..0x0310).collect();
+        expected.splice(0..0, [0x0010, 0x0011]);
+        expected.extend([0x6000, 0x6001]);
+        assert_eq!(bus.search(0xab), expected);
+        assert_eq!(bus.peek(0x0310), 0);
+    }
+
+    #[test]
+    fn test_mmc3_irq_clocked_by_ppu_a12() {
+        let mut bus = Bus::new(test::test_rom_with_mapper(4));
+        bus.mem_write(0xc000, 2); // latch
+        bus.mem_write(0xc001, 0); // reload
+        bus.mem_write(0xe001, 0); // enable
+
+        // each pattern fetch from $1xxx after one from $0xxx is a rising edge of A12
+        let scanline = |bus: &mut Bus| {
+            for address in [0x0000u16, 0x1000] {
+                bus.mem_write(0x2006, (address >> 8) as u8);
+                bus.mem_write(0x2006, (address & 0xff) as u8);
+                bus.mem_read(0x2007);
+            }
+        };
+
+        scanline(&mut bus);
+        scanline(&mut bus);
+        assert!(!bus.poll_irq());
+        scanline(&mut bus);
+        assert!(bus.poll_irq());
+
+        bus.mem_write(0xe000, 0);
+        assert!(!bus.poll_irq());
+    }
+
+    #[test]
+    fn test_mmc3_mirroring_reaches_ppu() {
+        let mut bus = Bus::new(test::test_rom_with_mapper(4));
+        bus.mem_write(0xa000, 1);
+        assert_eq!(bus.ppu.mirroring, Mirroring::HORIZONTAL);
+    }
+
+    /// A board with a single register at $5000 in the expansion area.
+    struct ExpansionMapper {
+        register: u8,
+    }
+
+    impl Mapper for ExpansionMapper {
+        fn read_prg(&self, _address: u16) -> u8 {
+            0
+        }
+
+        fn write_prg(&mut self, _address: u16, _data: u8) {}
+
+        fn chr_view(&self) -> Vec<u8> {
+            vec![0; 0x2000]
+        }
+
+        fn mirroring(&self) -> Mirroring {
+            Mirroring::VERTICAL
+        }
+
+        fn read_exp(&self, address: u16) -> Option<u8> {
+            (address == 0x5000).then_some(self.register)
+        }
+
+        fn write_exp(&mut self, address: u16, data: u8) -> bool {
+            if address == 0x5000 {
+                self.register = data;
+            }
+            address == 0x5000
+        }
+    }
+
+    #[test]
+    fn test_expansion_area_routed_to_mapper() {
+        let mut bus = Bus::new(test::test_rom());
+        bus.mapper = Box::new(ExpansionMapper { register: 0 });
+        let log = Rc::new(RefCell::new(vec![]));
+        let sink = log.clone();
+        bus.set_unmapped_access_log(move |kind, address| sink.borrow_mut().push((kind, address)));
+
+        bus.mem_write(0x5000, 0x42);
+        assert_eq!(bus.mem_read(0x5000), 0x42);
+        assert_eq!(bus.peek(0x5000), 0x42);
+        assert!(log.borrow().is_empty());
+
+        // the rest of the area stays open
+        bus.mem_write(0x4020, 0x01);
+        assert_eq!(bus.mem_read(0x5fff), 0);
+        assert_eq!(*log.borrow(), vec![(AccessKind::Write, 0x4020), (AccessKind::Read, 0x5fff)]);
+    }
+
+    #[test]
+    fn test_access_stats_count_ram_and_prg() {
+        // LDA $10; STA $0811; LDA $8000; BRK
+        let rom = test::test_rom_with_program(&[0xa5, 0x10, 0x8d, 0x11, 0x08, 0xad, 0x00, 0x80, 0x00], 0x8000);
+        let mut cpu = crate::cpu::CPU::new(Bus::new(rom));
+        cpu.reset();
+        cpu.bus.reset_access_stats();
+        cpu.run();
+
+        let stats = cpu.bus.access_stats();
+        // 9 instruction bytes fetched plus the LDA $8000 data read
+        assert_eq!(stats.reads(MemRegion::PrgRom), 9 + 1);
+        assert_eq!(stats.writes(MemRegion::PrgRom), 0);
+        assert_eq!(stats.reads(MemRegion::Ram), 1);
+        assert_eq!(stats.writes(MemRegion::Ram), 0);
+        // $0811 is a mirror
+        assert_eq!(stats.writes(MemRegion::RamMirror), 1);
+        assert_eq!(stats.reads(MemRegion::PpuRegister), 0);
+
+        // peeks are free
+        cpu.bus.peek(0x0010);
+        assert_eq!(cpu.bus.access_stats().reads(MemRegion::Ram), 1);
+    }
+
+    #[test]
+    fn test_clock_drives_ppu_and_apu() {
+        // LDA #$01; NOP x5
+        let prog = [0xa9, 0x01, 0xea, 0xea, 0xea, 0xea, 0xea];
+        // a PAL header alone doesn't change the clock
+        let mut rom = test::test_rom_with_program(&prog, 0x8000);
+        rom.region = Region::PAL;
+        let mut cpu = crate::cpu::CPU::new(Bus::new(rom));
+        cpu.reset();
+
+        let (cycles, dot, apu) = (cpu.cycles, cpu.bus.ppu.frame_dot(), cpu.bus.apu.cycles());
+        cpu.step();
+        let ran = cpu.cycles - cycles;
+        assert_eq!(ran, 2);
+        assert_eq!(cpu.bus.ppu.frame_dot() - dot, ran * 3);
+        assert_eq!(cpu.bus.apu.cycles() - apu, ran);
+
+        // PAL runs 16 dots per 5 CPU cycles
+        let mut cpu = crate::cpu::CPU::new(Bus::new(test::test_rom_with_program(&prog, 0x8000)));
+        cpu.bus.set_clock(Box::new(DividerClock::pal()));
+        cpu.reset();
+
+        let (cycles, dot, apu) = (cpu.cycles, cpu.bus.ppu.frame_dot(), cpu.bus.apu.cycles());
+        for _ in 0..5 {
+            cpu.step();
+        }
+        let ran = cpu.cycles - cycles;
+        assert_eq!(ran, 10);
+        assert_eq!(cpu.bus.ppu.frame_dot() - dot, ran * 16 / 5);
+        assert_eq!(cpu.bus.apu.cycles() - apu, ran);
+    }
+
+    #[test]
+    fn test_famicom_microphone_bit() {
+        let mut bus = Bus::new(test::test_rom());
+        bus.joypad1.set_button_pressed_status(JoypadButton::BUTTON_A, true);
+        bus.microphone = true;
+        // ignored on an NES
+        assert_eq!(bus.peek(0x4016), 0x40 | 0b001);
+
+        bus.famicom_mode = true;
+        bus.mem_write(0x4016, 1);
+        bus.mem_write(0x4016, 0);
+        assert_eq!(bus.mem_read(0x4016), 0x40 | 0b101);
+        // B isn't held, the microphone is still there
+        assert_eq!(bus.mem_read(0x4016), 0x40 | 0b100);
+        assert_eq!(bus.mem_read(0x4017) & 0b100, 0);
+
+        bus.microphone = false;
+        assert_eq!(bus.mem_read(0x4016), 0x40);
+    }
+
+    #[test]
+    fn test_controller_reads_after_eight_buttons() {
+        let mut bus = Bus::new(test::test_rom());
+        bus.joypad1.set_button_pressed_status(JoypadButton::BUTTON_A, true);
+        bus.joypad1.set_button_pressed_status(JoypadButton::RIGHT, true);
+        bus.mem_write(0x4016, 1);
+        bus.mem_write(0x4016, 0);
+
+        let reads: Vec<u8> = (0..9).map(|_| bus.mem_read(0x4016)).collect();
+        // the upper bits float at the $40 of the address
+        assert_eq!(reads, vec![0x41, 0x40, 0x40, 0x40, 0x40, 0x40, 0x40, 0x41, 0x41]);
+        assert_eq!(bus.mem_read(0x4016), 0x41);
+        assert_eq!(bus.peek(0x4016), 0x41);
+        // nothing held on the second controller, but it's done after 8 reads as well
+        let reads: Vec<u8> = (0..9).map(|_| bus.mem_read(0x4017)).collect();
+        assert_eq!(reads[..8], [0x40; 8]);
+        assert_eq!(reads[8], 0x41);
+    }
+
+    #[test]
+    fn test_from_ines_bytes() {
+        let mut raw = vec![0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x00, 00, 00, 00, 00, 00, 00, 00, 00, 00];
+        let mut prg = vec![0; 0x4000];
+        prg[0x0123] = 0x5a;
+        raw.extend(prg);
+        raw.extend(vec![0; 0x2000]);
+
+        let mut bus = Bus::from_ines_bytes(&raw).unwrap();
+        assert_eq!(bus.mem_read(0x8123), 0x5a);
+        // 16KB of PRG shows up twice
+        assert_eq!(bus.mem_read(0xc123), 0x5a);
+
+        assert_eq!(Bus::from_ines_bytes(&raw[..4]).err(), Some(RomError::Truncated { expected: 16, got: 4 }));
+    }
+
+    #[test]
+    fn test_power_cycle_resets_mapper_registers() {
+        let mut bus = Bus::new(test::test_rom_with_mapper(4));
+        // the header asks for horizontal mirroring
+        bus.mem_write(0xa000, 0);
+        assert_eq!(bus.ppu.mirroring, Mirroring::VERTICAL);
+        bus.mem_write(0x0010, 0x55);
+        bus.power_cycle();
+        assert_eq!(bus.ppu.mirroring, Mirroring::HORIZONTAL);
+        assert_eq!(bus.mem_read(0x0010), 0);
+    }
+
+    #[test]
+    fn test_nrom_ignores_rom_writes() {
+        let mut bus = Bus::new(test::test_rom_with_program(&[0xea], 0x8000));
+        bus.mem_write(0x8000, 0x55);
+        bus.mem_write(0xfffc, 0x12);
+        assert_eq!(bus.mem_read(0x8000), 0xea);
+        assert_eq!(bus.mem_read(0xfffc), 0x00);
+
+        // so does the reset vector CPU::load writes
+        let mut cpu = crate::cpu::CPU::new(bus);
+        cpu.load(vec![0xea]);
+        assert_eq!(cpu.reset_vector(), 0x8000);
+    }
+
+    #[test]
+    fn test_chr_ram_survives_bank_writes() {
+        let mut rom = test::test_rom_with_mapper(4);
+        rom.chr_rom = vec![];
+        let mut bus = Bus::new(rom);
+        bus.mem_write(0x2006, 0x00);
+        bus.mem_write(0x2006, 0x10);
+        bus.mem_write(0x2007, 0x66);
+
+        bus.mem_write(0x8000, 0x00);
+        bus.mem_write(0x8001, 0x02);
+        assert_eq!(bus.ppu.chr_rom[0x0010], 0x66);
+    }
+
+    #[test]
+    fn test_battery_sram_survives_power_cycle() {
+        let mut rom = test::test_rom();
+        rom.has_battery = true;
+        let mut bus = Bus::new(rom);
+        bus.mem_write(0x6000, 0x55);
+        bus.power_cycle();
+        assert_eq!(bus.mem_read(0x6000), 0x55);
+
+        // a new cartridge doesn't inherit it
+        let mut rom = test::test_rom();
+        rom.has_battery = true;
+        bus.insert_cartridge(rom);
+        assert_eq!(bus.mem_read(0x6000), 0);
+
+        let mut bus = Bus::new(test::test_rom());
+        bus.mem_write(0x6000, 0x55);
+        bus.power_cycle();
+        assert_eq!(bus.mem_read(0x6000), 0);
+    }
+
+    #[test]
+    fn test_dmc_fetch_stalls_cpu() {
+        let mut bus = Bus::new(test::test_rom_with_program(&[0xaa, 0xbb], 0xc000));
+        bus.mem_write(0x4012, 0x00);
+        bus.mem_write(0x4013, 0x00);
+        bus.mem_write(0x4015, 0b0001_0000);
+        assert_eq!(bus.mem_read(0x4015), 0b0001_0000);
+
+        // the first cycle triggers the fetch of the single sample byte
+        assert_eq!(bus.tick(2), 2 + DMC_DMA_STALL_CYCLES);
+        assert_eq!(bus.mem_read(0x4015), 0);
+        // the sample is exhausted, nothing more to fetch
+        assert_eq!(bus.tick(2), 2);
+    }
+
+    #[test]
+    fn test_dmc_irq_drives_irq_line() {
+        let mut bus = Bus::new(test::test_rom());
+        bus.mem_write(0x4010, 0b1000_1111);
+        bus.mem_write(0x4013, 0x00);
+        bus.mem_write(0x4015, 0b0001_0000);
+        assert!(!bus.poll_irq());
+
+        bus.tick(1);
+        assert!(bus.poll_irq());
+        assert_eq!(bus.mem_read(0x4015), 0b1000_0000);
+
+        // writing $4015 acknowledges the DMC interrupt
+        bus.mem_write(0x4015, 0);
+        assert!(!bus.poll_irq());
+    }
+}
@@ -1,59 +1,221 @@
+use crate::{checksum, mapper};
+
 const NES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];
 const PRG_ROM_PAGE_SIZE: usize = 16384;
 const CHR_ROM_PAGE_SIZE: usize = 8192;
+const PRG_RAM_PAGE_SIZE: usize = 8192;
+const INES_HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
 
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Mirroring{
     VERTICAL,
     HORIZONTAL,
     FOUR_SCREEN,
 }
 
-#[derive(Debug)]
-pub struct Rom {
-    pub prg_rom: Vec<u8>,
-    pub chr_rom: Vec<u8>,
+/// TV system the cartridge was made for.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Region {
+    NTSC,
+    PAL,
+}
+
+/// Errors produced while loading an iNES image.
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum RomError {
+    /// The first four bytes are not `NES<EOF>`.
+    #[error("File is not in proper iNES file format")]
+    BadMagic,
+    /// The header asks for a board no mapper is implemented for.
+    #[error("Mapper {0} is not supported")]
+    UnsupportedMapper(u8),
+    /// The image ends before a section it declares.
+    #[error("File is truncated: expected {expected} bytes, got {got}")]
+    Truncated { expected: usize, got: usize },
+    /// The image is bigger than the space it's loaded into.
+    #[error("Image is too large: at most {limit} bytes fit, got {got}")]
+    TooLarge { limit: usize, got: usize },
+    /// The header is in the NES 2.0 format, which isn't parsed.
+    #[error("iNES 2.0 format is not supported")]
+    UnsupportedNes2Feature,
+}
+
+//  0-3   NES<EOF>
+//  4     PRG-ROM size in 16KB units
+//  5     CHR-ROM size in 8KB units
+//  6     Flags 6: mapper low nibble | four screen | trainer | battery | mirroring
+//  7     Flags 7: mapper high nibble | iNES version (bits 2-3)
+//  8     PRG-RAM size in 8KB units (0 infers 8KB)
+//  9     Flags 9: TV system (bit 0, 1 = PAL)
+//  10-15 unused padding
+
+// iNES HEADER LAYOUT
+
+/// The parsed 16 byte iNES header.
+#[derive(Debug, PartialEq)]
+pub struct INesHeader {
+    /// Number of 16KB PRG-ROM banks.
+    pub prg_banks: u8,
+    /// Number of 8KB CHR-ROM banks (0 means the cartridge uses CHR-RAM).
+    pub chr_banks: u8,
+    /// The iNES mapper number.
     pub mapper: u8,
+    /// Nametable mirroring wired on the cartridge.
     pub mirroring: Mirroring,
+    /// Whether the cartridge has battery backed PRG-RAM at $6000-$7FFF.
+    pub has_battery: bool,
+    /// Whether a 512 byte trainer sits between the header and PRG-ROM.
+    pub has_trainer: bool,
+    /// Whether the header is in the NES 2.0 format.
+    pub is_nes2: bool,
+    /// PRG-RAM size in bytes.
+    pub prg_ram_size: usize,
+    /// TV system, which most dumps leave at NTSC.
+    pub region: Region,
 }
 
-impl Rom {
-    pub fn new(raw: &Vec<u8>) -> Result<Rom, String> {
-        if &raw[0..4] != NES_TAG {
-            return Err("File is not in proper iNES file format".to_string());
+impl INesHeader {
+    /// Parses the iNES header at the start of `raw`.
+    ///
+    /// # Arguments
+    ///
+    /// * `raw` - The raw bytes of an iNES file, only the first 16 are inspected.
+    pub fn parse(raw: &[u8]) -> Result<INesHeader, RomError> {
+        if raw.len() < INES_HEADER_SIZE {
+            return Err(RomError::Truncated { expected: INES_HEADER_SIZE, got: raw.len() });
         }
- 
-        let mapper = (raw[7] & 0b1111_0000) | (raw[6] >> 4);
- 
-        let ines_ver = (raw[7] >> 2) & 0b11;
-        if ines_ver != 0 {
-            return Err("iNES 2.0 format is not supported".to_string());
+        if raw[0..4] != NES_TAG {
+            return Err(RomError::BadMagic);
         }
- 
+
         let four_screen = raw[6] & 0b1000 != 0;
         let vertical_mirroring = raw[6] & 0b1 != 0;
-        let screen_mirroring = match (four_screen, vertical_mirroring) {
+        let mirroring = match (four_screen, vertical_mirroring) {
             (true, _) => Mirroring::FOUR_SCREEN,
             (false, true) => Mirroring::VERTICAL,
             (false, false) => Mirroring::HORIZONTAL,
         };
+
+        Ok(INesHeader {
+            prg_banks: raw[4],
+            chr_banks: raw[5],
+            mapper: (raw[7] & 0b1111_0000) | (raw[6] >> 4),
+            mirroring,
+            has_battery: raw[6] & 0b10 != 0,
+            has_trainer: raw[6] & 0b100 != 0,
+            is_nes2: (raw[7] >> 2) & 0b11 == 0b10,
+            prg_ram_size: raw[8].max(1) as usize * PRG_RAM_PAGE_SIZE,
+            region: if raw[9] & 0b1 != 0 { Region::PAL } else { Region::NTSC },
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Rom {
+    pub prg_rom: Vec<u8>,
+    pub chr_rom: Vec<u8>,
+    pub mapper: u8,
+    pub mirroring: Mirroring,
+    pub has_battery: bool,
+    pub region: Region,
+}
+
+/// A summary of a cartridge for frontends to display.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CartridgeInfo {
+    pub mapper: u8,
+    /// Common board name of the mapper, e.g. "MMC3".
+    pub mapper_name: &'static str,
+    pub mirroring: Mirroring,
+    pub has_battery: bool,
+    /// PRG-ROM size in bytes.
+    pub prg_rom_size: usize,
+    /// CHR-ROM size in bytes, 0 for boards with CHR-RAM.
+    pub chr_rom_size: usize,
+    pub region: Region,
+}
+
+impl Rom {
+    pub fn new(raw: &Vec<u8>) -> Result<Rom, RomError> {
+        Rom::from_bytes(raw)
+    }
+
+    /// Parses a whole iNES image. Boards without a mapper implementation are refused.
+    ///
+    /// # Arguments
+    ///
+    /// * `raw` - The raw bytes of an iNES file, header included.
+    pub fn from_bytes(raw: &[u8]) -> Result<Rom, RomError> {
+        let header = INesHeader::parse(raw)?;
+
+        if header.is_nes2 {
+            return Err(RomError::UnsupportedNes2Feature);
+        }
+        if !mapper::is_supported(header.mapper) {
+            return Err(RomError::UnsupportedMapper(header.mapper));
+        }
  
-        let prg_rom_size = raw[4] as usize * PRG_ROM_PAGE_SIZE;
-        let chr_rom_size = raw[5] as usize * CHR_ROM_PAGE_SIZE;
- 
-        let skip_trainer = raw[6] & 0b100 != 0;
+        let prg_rom_size = header.prg_banks as usize * PRG_ROM_PAGE_SIZE;
+        let chr_rom_size = header.chr_banks as usize * CHR_ROM_PAGE_SIZE;
  
-        let prg_rom_start = 16 + if skip_trainer { 512 } else { 0 };
+        let prg_rom_start = INES_HEADER_SIZE + if header.has_trainer { TRAINER_SIZE } else { 0 };
         let chr_rom_start = prg_rom_start + prg_rom_size;
+        if raw.len() < chr_rom_start + chr_rom_size {
+            return Err(RomError::Truncated { expected: chr_rom_start + chr_rom_size, got: raw.len() });
+        }
  
         Ok(Rom {
             prg_rom: raw[prg_rom_start..(prg_rom_start + prg_rom_size)].to_vec(),
             chr_rom: raw[chr_rom_start..(chr_rom_start + chr_rom_size)].to_vec(),
-            mapper,
-            mirroring: screen_mirroring,
+            mapper: header.mapper,
+            mirroring: header.mirroring,
+            has_battery: header.has_battery,
+            region: header.region,
         })
     }
+
+    /// Summarizes the cartridge: mapper, wiring and sizes.
+    pub fn info(&self) -> CartridgeInfo {
+        CartridgeInfo {
+            mapper: self.mapper,
+            mapper_name: mapper::name(self.mapper),
+            mirroring: self.mirroring,
+            has_battery: self.has_battery,
+            prg_rom_size: self.prg_rom.len(),
+            chr_rom_size: self.chr_rom.len(),
+            region: self.region,
+        }
+    }
+
+    ////// IDENTIFICATION
+
+    /// CRC-32 of the PRG-ROM.
+    pub fn prg_crc32(&self) -> u32 {
+        checksum::crc32(&self.prg_rom)
+    }
+
+    /// CRC-32 of the CHR-ROM.
+    pub fn chr_crc32(&self) -> u32 {
+        checksum::crc32(&self.chr_rom)
+    }
+
+    /// CRC-32 of PRG-ROM followed by CHR-ROM, i.e. the file without header or trainer.
+    /// This is the value ROM databases list.
+    pub fn rom_crc32(&self) -> u32 {
+        checksum::crc32(&self.headerless())
+    }
+
+    /// SHA-1 of PRG-ROM followed by CHR-ROM, without header or trainer.
+    pub fn ines_sha1(&self) -> [u8; 20] {
+        checksum::sha1(&self.headerless())
+    }
+
+    fn headerless(&self) -> Vec<u8> {
+        [self.prg_rom.as_slice(), self.chr_rom.as_slice()].concat()
+    }
 }
 
 #[cfg(test)]
@@ -88,7 +250,7 @@ pub mod test {
     pub fn test_rom() -> Rom {
         let test_rom = create_rom(TestRom {
             header: vec![
-                0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x31, 00, 00, 00, 00, 00, 00, 00, 00, 00,
+                0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x01, 00, 00, 00, 00, 00, 00, 00, 00, 00,
             ],
             trainer: None,
             pgp_rom: vec![1; 2 * PRG_ROM_PAGE_SIZE],
@@ -98,11 +260,50 @@ pub mod test {
         Rom::new(&test_rom).unwrap()
     }
 
+    /// Builds a two bank NROM image with `program` placed at `start` and the reset vector pointing at it.
+    pub fn test_rom_with_program(program: &[u8], start: u16) -> Rom {
+        let mut pgp_rom = vec![0; 2 * PRG_ROM_PAGE_SIZE];
+        let offset = (start - 0x8000) as usize;
+        pgp_rom[offset..offset + program.len()].copy_from_slice(program);
+        pgp_rom[0x7FFC] = (start & 0xff) as u8;
+        pgp_rom[0x7FFD] = (start >> 8) as u8;
+
+        test_rom_with_prg(pgp_rom)
+    }
+
+    /// Builds an NROM image around a full 32KB PRG-ROM, vectors included.
+    pub fn test_rom_with_prg(pgp_rom: Vec<u8>) -> Rom {
+        let test_rom = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x00, 00, 00, 00, 00, 00, 00, 00, 00, 00,
+            ],
+            trainer: None,
+            pgp_rom,
+            chr_rom: vec![0; CHR_ROM_PAGE_SIZE],
+        });
+
+        Rom::new(&test_rom).unwrap()
+    }
+
+    /// Builds a 32KB PRG / 8KB CHR image declaring the given mapper number.
+    pub fn test_rom_with_mapper(mapper: u8) -> Rom {
+        let test_rom = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, (mapper & 0x0f) << 4, mapper & 0xf0, 00, 00, 00, 00, 00, 00, 00, 00,
+            ],
+            trainer: None,
+            pgp_rom: vec![0; 2 * PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![0; CHR_ROM_PAGE_SIZE],
+        });
+
+        Rom::new(&test_rom).unwrap()
+    }
+
     #[test]
     fn test() {
         let test_rom = create_rom(TestRom {
             header: vec![
-                0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x31, 00, 00, 00, 00, 00, 00, 00, 00, 00,
+                0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x41, 00, 00, 00, 00, 00, 00, 00, 00, 00,
             ],
             trainer: None,
             pgp_rom: vec![1; 2 * PRG_ROM_PAGE_SIZE],
@@ -113,10 +314,25 @@ pub mod test {
 
         assert_eq!(rom.chr_rom, vec!(2; 1 * CHR_ROM_PAGE_SIZE));
         assert_eq!(rom.prg_rom, vec!(1; 2 * PRG_ROM_PAGE_SIZE));
-        assert_eq!(rom.mapper, 3);
+        assert_eq!(rom.mapper, 4);
         assert_eq!(rom.mirroring, Mirroring::VERTICAL);
     }
 
+    #[test]
+    fn test_rom_checksums() {
+        let rom = test_rom();
+        assert_eq!(rom.prg_crc32(), 0xAB91_DAE5);
+        assert_eq!(rom.chr_crc32(), 0x2B0F_AF01);
+        assert_eq!(rom.rom_crc32(), 0x9012_89B3);
+        assert_eq!(
+            rom.ines_sha1(),
+            [
+                0x6e, 0x82, 0xd6, 0xbd, 0x77, 0x88, 0xe0, 0x6d, 0x64, 0x9f, 0xac, 0x76, 0xdd, 0x89,
+                0xb9, 0x23, 0xdb, 0xd5, 0x93, 0xd6,
+            ]
+        );
+    }
+
     #[test]
     fn test_with_trainer() {
         let test_rom = create_rom(TestRom {
@@ -127,7 +343,7 @@ pub mod test {
                 0x1A,
                 0x02,
                 0x01,
-                0x31 | 0b100,
+                0x41 | 0b100,
                 00,
                 00,
                 00,
@@ -147,10 +363,80 @@ pub mod test {
 
         assert_eq!(rom.chr_rom, vec!(2; 1 * CHR_ROM_PAGE_SIZE));
         assert_eq!(rom.prg_rom, vec!(1; 2 * PRG_ROM_PAGE_SIZE));
-        assert_eq!(rom.mapper, 3);
+        assert_eq!(rom.mapper, 4);
         assert_eq!(rom.mirroring, Mirroring::VERTICAL);
     }
 
+    #[test]
+    fn test_parse_header_fields() {
+        let header = INesHeader::parse(&[
+            0x4E, 0x45, 0x53, 0x1A, 0x04, 0x02, 0x1B, 0x40, 0x02, 00, 00, 00, 00, 00, 00, 00,
+        ])
+        .unwrap();
+
+        assert_eq!(header.prg_banks, 4);
+        assert_eq!(header.chr_banks, 2);
+        assert_eq!(header.mapper, 0x41);
+        assert_eq!(header.mirroring, Mirroring::FOUR_SCREEN);
+        assert!(header.has_battery);
+        assert!(!header.has_trainer);
+        assert!(!header.is_nes2);
+        assert_eq!(header.prg_ram_size, 2 * PRG_RAM_PAGE_SIZE);
+        assert_eq!(header.region, Region::NTSC);
+    }
+
+    #[test]
+    fn test_parse_header_trainer_and_defaults() {
+        let header = INesHeader::parse(&[
+            0x4E, 0x45, 0x53, 0x1A, 0x01, 0x00, 0b0000_0100, 0x08, 00, 00, 00, 00, 00, 00, 00, 00,
+        ])
+        .unwrap();
+
+        assert_eq!(header.chr_banks, 0);
+        assert_eq!(header.mapper, 0);
+        assert_eq!(header.mirroring, Mirroring::HORIZONTAL);
+        assert!(!header.has_battery);
+        assert!(header.has_trainer);
+        assert!(header.is_nes2);
+        // a zero PRG-RAM size infers 8KB
+        assert_eq!(header.prg_ram_size, PRG_RAM_PAGE_SIZE);
+    }
+
+    #[test]
+    fn test_parse_header_errors() {
+        assert_eq!(
+            INesHeader::parse(&[0x4E, 0x45, 0x53]),
+            Err(RomError::Truncated { expected: 16, got: 3 })
+        );
+        assert_eq!(INesHeader::parse(&[0; 16]), Err(RomError::BadMagic));
+    }
+
+    #[test]
+    fn test_from_bytes_truncated_image() {
+        let mut raw = vec![0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x00, 00, 00, 00, 00, 00, 00, 00, 00, 00];
+        raw.extend(vec![0; PRG_ROM_PAGE_SIZE]);
+        assert_eq!(
+            Rom::from_bytes(&raw).unwrap_err(),
+            RomError::Truncated { expected: 16 + PRG_ROM_PAGE_SIZE + CHR_ROM_PAGE_SIZE, got: raw.len() }
+        );
+    }
+
+    #[test]
+    fn test_unsupported_mapper() {
+        // mapper 1 (MMC1)
+        let test_rom = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x10, 00, 00, 00, 00, 00, 00, 00, 00, 00,
+            ],
+            trainer: None,
+            pgp_rom: vec![0; PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![0; CHR_ROM_PAGE_SIZE],
+        });
+        let err = Rom::new(&test_rom).unwrap_err();
+        assert_eq!(err, RomError::UnsupportedMapper(1));
+        assert_eq!(err.to_string(), "Mapper 1 is not supported");
+    }
+
     #[test]
     fn test_nes2_is_not_supported() {
         let test_rom = create_rom(TestRom {
@@ -164,7 +450,23 @@ pub mod test {
         let rom = Rom::new(&test_rom);
         match rom {
             Result::Ok(_) => assert!(false, "should not load rom"),
-            Result::Err(str) => assert_eq!(str, "iNES 2.0 format is not supported"),
+            Result::Err(err) => {
+                assert_eq!(err, RomError::UnsupportedNes2Feature);
+                assert_eq!(err.to_string(), "iNES 2.0 format is not supported");
+            }
         }
     }
+
+    #[test]
+    fn test_bad_magic() {
+        let test_rom = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x00, 0x01, 0x01, 0x00, 00, 00, 00, 00, 00, 00, 00, 00, 00,
+            ],
+            trainer: None,
+            pgp_rom: vec![1; 1 * PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![2; 1 * CHR_ROM_PAGE_SIZE],
+        });
+        assert_eq!(Rom::new(&test_rom).unwrap_err(), RomError::BadMagic);
+    }
 }
\ No newline at end of file
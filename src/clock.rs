@@ -0,0 +1,118 @@
+use crate::cartridge::Region;
+
+//  NTSC  21.477272 MHz master   CPU = master / 12   PPU = master / 4   3 dots per CPU cycle
+//  PAL   26.601712 MHz master   CPU = master / 16   PPU = master / 5   3.2 dots per CPU cycle
+//  The APU runs off the CPU clock on both.
+
+/// NTSC CPU clock: the 21.477272 MHz master clock divided by 12.
+pub const CPU_CLOCK_HZ: f64 = 1_789_773.0;
+
+// MASTER CLOCK DIVIDERS
+const NTSC_CPU_DIVIDER: u32 = 12;
+const NTSC_PPU_DIVIDER: u32 = 4;
+const PAL_CPU_DIVIDER: u32 = 16;
+const PAL_PPU_DIVIDER: u32 = 5;
+
+/// What the other chips run during one CPU cycle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ticks {
+    pub ppu_dots: u8,
+    pub apu_cycles: u8,
+}
+
+/// # Clock
+/// The single source of timing on the bus. The CPU spends cycles, the clock says how far the PPU and APU
+/// move along in each of them, so the ratios live here instead of wherever the chips get ticked.
+pub trait Clock {
+    /// Advances by one CPU cycle.
+    fn cpu_cycle(&mut self) -> Ticks;
+
+    /// CPU cycles it takes from now for the PPU to run `dots` more dots, rounded up.
+    ///
+    /// # Arguments
+    ///
+    /// * `dots` - PPU dots to wait for.
+    fn cpu_cycles_for_dots(&self, dots: usize) -> usize;
+}
+
+/// Derives every chip's clock from the master crystal through its divider, like the console does.
+/// When the PPU divider doesn't go evenly into the CPU's, as on PAL, the leftover master cycles carry over.
+pub struct DividerClock {
+    cpu_divider: u32,
+    ppu_divider: u32,
+    ppu_phase: u32, // MASTER CYCLES INTO THE CURRENT PPU DOT
+}
+
+impl DividerClock {
+    pub fn ntsc() -> Self {
+        DividerClock { cpu_divider: NTSC_CPU_DIVIDER, ppu_divider: NTSC_PPU_DIVIDER, ppu_phase: 0 }
+    }
+
+    pub fn pal() -> Self {
+        DividerClock { cpu_divider: PAL_CPU_DIVIDER, ppu_divider: PAL_PPU_DIVIDER, ppu_phase: 0 }
+    }
+
+    /// # Arguments
+    ///
+    /// * `region` - The TV system the cartridge was made for.
+    pub fn for_region(region: Region) -> Self {
+        match region {
+            Region::NTSC => DividerClock::ntsc(),
+            Region::PAL => DividerClock::pal(),
+        }
+    }
+}
+
+impl Clock for DividerClock {
+    fn cpu_cycle(&mut self) -> Ticks {
+        self.ppu_phase += self.cpu_divider;
+        let ppu_dots = self.ppu_phase / self.ppu_divider;
+        self.ppu_phase %= self.ppu_divider;
+        Ticks { ppu_dots: ppu_dots as u8, apu_cycles: 1 }
+    }
+
+    fn cpu_cycles_for_dots(&self, dots: usize) -> usize {
+        let master = dots * self.ppu_divider as usize;
+        master.saturating_sub(self.ppu_phase as usize).div_ceil(self.cpu_divider as usize)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ntsc_ratio() {
+        let mut clock = DividerClock::for_region(Region::NTSC);
+        for _ in 0..10 {
+            assert_eq!(clock.cpu_cycle(), Ticks { ppu_dots: 3, apu_cycles: 1 });
+        }
+    }
+
+    #[test]
+    fn test_pal_ratio() {
+        let mut clock = DividerClock::for_region(Region::PAL);
+        // 16 dots every 5 CPU cycles
+        let dots: Vec<u8> = (0..10).map(|_| clock.cpu_cycle().ppu_dots).collect();
+        assert_eq!(dots, vec![3, 3, 3, 3, 4, 3, 3, 3, 3, 4]);
+    }
+
+    #[test]
+    fn test_cpu_cycles_for_dots() {
+        let mut clock = DividerClock::ntsc();
+        assert_eq!(clock.cpu_cycles_for_dots(3), 1);
+        assert_eq!(clock.cpu_cycles_for_dots(4), 2);
+        clock.cpu_cycle();
+        assert_eq!(clock.cpu_cycles_for_dots(341), 114);
+
+        let mut clock = DividerClock::pal();
+        assert_eq!(clock.cpu_cycles_for_dots(16), 5);
+        assert_eq!(clock.cpu_cycles_for_dots(17), 6);
+        // 4 cycles in, 12 dots have run and the 13th is 4/5 done
+        for _ in 0..4 {
+            clock.cpu_cycle();
+        }
+        assert_eq!(clock.cpu_cycles_for_dots(4), 1);
+        assert_eq!(clock.cpu_cycles_for_dots(5), 2);
+    }
+}
@@ -1,9 +1,19 @@
 use core::panic;
 use std::collections::HashMap;
-use crate::{bus::Bus, opcode};
+use std::ops::Range;
+use crate::{asm, bus::Bus, cartridge::Rom, opcode::{self, OpCode}, trace};
 
 const STACK: u16 = 0x0100;
 const STACK_RESET: u8 = 0xfd;
+const RESET_VECTOR: u16 = 0xFFFC;
+/// Where `eval` and `load_asm` put programs, and how long `eval` lets one run.
+const EVAL_START: u16 = 0x0600;
+const EVAL_MAX_CYCLES: usize = 1_000_000;
+const JSR_OPCODE: u8 = 0x20;
+/// Internal RAM and its mirrors, where `trap_on_ram_execution` stops the CPU.
+const RAM_END: u16 = 0x1FFF;
+/// How long `step_over` waits for a subroutine to return.
+const STEP_OVER_MAX_CYCLES: usize = 10_000_000;
 
 bitflags! {
     /// # Status Register (P) http://wiki.nesdev.com/w/index.php/Status_flags
@@ -18,7 +28,8 @@ bitflags! {
     ///  | +--------------- Overflow Flag
     ///  +----------------- Negative Flag
     ///
-    #[derive(Clone)]
+    #[derive(Clone, Debug, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct CpuFlags: u8 {
         const CARRY             = 0b00000001;
         const ZERO              = 0b00000010;
@@ -32,18 +43,104 @@ bitflags! {
 }
 
 
-pub struct CPU {
+pub struct CPU<B: Memory = Bus> {
     pub register_a: u8,           // CPU (A)CCUMULATOR REGISTER
     pub register_x: u8,           // OFFSET REGISTERS
     pub register_y: u8,
     pub status: CpuFlags,             // PROCESSOR STATUS FLAG REGISTER
     pub program_counter: u16,   // CURRENT POSITION IN PROGRAM
     pub stack_pointer: u8,      // STACK LOCATION
+    pub cycles: usize,          // CPU CYCLES ELAPSED SINCE POWER ON
+    instructions_executed: u64, // INSTRUCTIONS DECODED SINCE THE LAST RESET
+    jumped: bool,               // THE CURRENT INSTRUCTION LOADED THE PC ITSELF
+    irq_inhibit: bool,          // I FLAG AS SEEN BY THE END OF INSTRUCTION IRQ POLL
     memory: [u8; 0xFFFF],       // GENERIC REPRESENTATION OF NES MEMORY -> {ROM + RAM + IO MEMORY MAP}
-    pub bus: Bus,
+    pub bus: B,
+    pub jam_behavior: JamBehavior,
+    pub allow_unofficial: bool, // EXECUTE THE UNDOCUMENTED OPCODES
+    pub trap_on_ram_execution: bool, // STOP WHEN THE PC WANDERS INTO RAM, USUALLY A WILD JUMP
+    pub trace_filter: Option<Range<u16>>, // run_with_trace ONLY LOGS INSTRUCTIONS AT THESE ADDRESSES
+    halted: Option<RunOutcome>, // SET ONCE A KIL OPCODE OR AN ERROR STOPS THE CPU
+    instruction_hook: Option<InstructionHook<B>>,
+    write_log: Option<Vec<MemoryWrite>>, // COLLECTS WRITES WHILE step_with_writes RUNS
 }
 
-#[derive(Debug)]
+type InstructionHook<B> = Box<dyn FnMut(&mut CPU<B>, &OpCode)>;
+
+/// Snapshot of the CPU registers, as stored in a save state.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CpuState {
+    pub register_a: u8,
+    pub register_x: u8,
+    pub register_y: u8,
+    pub status: CpuFlags,
+    pub program_counter: u16,
+    pub stack_pointer: u8,
+    pub cycles: usize,
+}
+
+/// Why a run loop handed control back.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunOutcome {
+    /// BRK was executed.
+    Break,
+    /// A KIL/JAM opcode locked up the CPU, only a reset gets it going again.
+    Jammed { opcode: u8, pc: u16 },
+    /// The CPU refused to execute an instruction, it stays stopped until a reset.
+    Error(CpuError),
+}
+
+/// How a debugger step ended.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepResult {
+    /// The instruction, or for a JSR the whole subroutine, completed.
+    Completed,
+    /// The subroutine was still running when the cycle budget ran out.
+    TimedOut,
+    /// BRK, a jam, or a refused opcode stopped the CPU first.
+    Stopped(RunOutcome),
+}
+
+/// A byte written by the CPU, as logged by `step_with_writes`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryWrite {
+    pub address: u16,
+    pub old: u8, // WHAT peek RETURNED RIGHT BEFORE THE WRITE
+    pub new: u8,
+}
+
+/// Instructions the CPU was configured not to execute.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CpuError {
+    /// An unofficial opcode was fetched while `allow_unofficial` is off.
+    UnofficialOpcodeDisabled { opcode: u8, pc: u16 },
+    /// The PC entered RAM ($0000-$1FFF) while `trap_on_ram_execution` is on.
+    ExecutingData { pc: u16 },
+}
+
+impl std::fmt::Display for CpuError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CpuError::UnofficialOpcodeDisabled { opcode, pc } => {
+                write!(f, "Unofficial opcode {:02X} at {:04X} is disabled", opcode, pc)
+            }
+            CpuError::ExecutingData { pc } => write!(f, "Executing RAM at {:04X}", pc),
+        }
+    }
+}
+
+/// What the KIL/JAM opcodes ($02, $12, ... $F2) do.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum JamBehavior {
+    /// Halt the CPU, as the hardware does.
+    #[default]
+    Jam,
+    /// Skip over them like a one byte NOP.
+    Nop,
+}
+
+#[derive(Debug, PartialEq)]
 #[allow(non_camel_case_types)]
 pub enum AddressingMode {
     Immediate,
@@ -55,12 +152,46 @@ pub enum AddressingMode {
     Absolute_Y,
     Indirect_X,
     Indirect_Y,
+    Accumulator, // ASL/LSR/ROL/ROR A
+    Relative,    // BRANCHES, SIGNED OFFSET FROM THE NEXT INSTRUCTION
+    Indirect,    // JMP ($nnnn), POINTER DOESN'T CROSS PAGES
     NoneAddressing,
 }
 
+mod interrupt {
+    /// Describes how the CPU enters an interrupt handler.
+    #[derive(PartialEq, Eq)]
+    pub(super) struct Interrupt {
+        pub(super) vector_addr: u16,
+        pub(super) b_flag_mask: u8,
+        pub(super) cpu_cycles: u8,
+    }
+
+    pub(super) const NMI: Interrupt = Interrupt {
+        vector_addr: 0xFFFA,
+        b_flag_mask: 0b00100000,
+        cpu_cycles: 7,
+    };
+
+    pub(super) const IRQ: Interrupt = Interrupt {
+        vector_addr: 0xFFFE,
+        b_flag_mask: 0b00100000,
+        cpu_cycles: 7,
+    };
+}
+
+/// Returns true if the two addresses live on different 256-byte pages.
+fn page_cross(address1: u16, address2: u16) -> bool {
+    address1 & 0xFF00 != address2 & 0xFF00
+}
+
 //////MEMORY FUNCTIONS
 pub trait Memory{
-    fn mem_read(&self, address: u16) -> u8; 
+    fn mem_read(&mut self, address: u16) -> u8; 
+
+    /// Reads a byte the way `mem_read` would, but without the side effects some I/O registers have on read.
+    /// Used by tracing and debugging tools.
+    fn peek(&self, address: u16) -> u8;
 
     fn mem_write(&mut self, address: u16, data: u8);
     
@@ -74,12 +205,19 @@ pub trait Memory{
     /// # Returns
     ///
     /// * `u16` - The  16-bit word read from the memory.
-    fn mem_read_u16(&self, position: u16) -> u16 {
+    fn mem_read_u16(&mut self, position: u16) -> u16 {
         let lo = self.mem_read(position) as u16;
-        let hi = self.mem_read(position + 1) as u16;
+        let hi = self.mem_read(position.wrapping_add(1)) as u16;
         (hi << 8) | (lo as u16)
     }
 
+    /// Side effect free counterpart of `mem_read_u16`.
+    fn peek_u16(&self, position: u16) -> u16 {
+        let lo = self.peek(position) as u16;
+        let hi = self.peek(position.wrapping_add(1)) as u16;
+        (hi << 8) | lo
+    }
+
     /// Writes a  16-bit word to the memory at the specified address.
     ///
     /// # Arguments
@@ -92,29 +230,113 @@ pub trait Memory{
         self.mem_write(position, lo);
         self.mem_write(position + 1, hi);
     }
+
+    ////// SYSTEM HOOKS
+    /// Lets whatever sits on the bus run for `cycles` CPU cycles.
+    /// Returns the cycles that actually elapsed, which is more if the bus stalled the CPU.
+    ///
+    /// # Arguments
+    ///
+    /// * `cycles` - The number of cycles spent by the current instruction.
+    fn tick(&mut self, cycles: u8) -> usize {
+        cycles as usize
+    }
+
+    /// Whether a device pulled the NMI line since the last poll. Polling acknowledges it.
+    fn poll_nmi(&mut self) -> bool {
+        false
+    }
+
+    /// Whether a device is holding the IRQ line low.
+    fn poll_irq(&self) -> bool {
+        false
+    }
+}
+
+/// # Flat Memory
+/// 64KB of plain RAM with nothing mapped into it, for running the 6502 core outside the NES.
+/// Devices never tick and interrupts never fire.
+pub struct FlatMemory {
+    data: Vec<u8>,
+}
+
+impl FlatMemory {
+    pub fn new() -> Self {
+        FlatMemory { data: vec![0; 0x10000] }
+    }
+
+    /// Copies `program` into memory at `address` and points the reset vector at it.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Where the first byte goes.
+    /// * `program` - The machine code to copy.
+    pub fn load(&mut self, address: u16, program: &[u8]) {
+        let start = address as usize;
+        self.data[start..start + program.len()].copy_from_slice(program);
+        self.mem_write_u16(0xFFFC, address);
+    }
+}
+
+impl Default for FlatMemory {
+    fn default() -> Self {
+        FlatMemory::new()
+    }
+}
+
+impl Memory for FlatMemory {
+    fn mem_read(&mut self, address: u16) -> u8 {
+        self.data[address as usize]
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.data[address as usize]
+    }
+
+    fn mem_write(&mut self, address: u16, data: u8) {
+        self.data[address as usize] = data;
+    }
 }
 
-impl Memory for CPU {
-    fn mem_read(&self, addr: u16) -> u8 {
+/// Runs a snippet of machine code on a bare 6502 and returns the registers it ends with.
+/// The program is loaded at $0600 in `FlatMemory` and runs until BRK, a jam, or a million cycles.
+///
+/// # Arguments
+///
+/// * `program` - The machine code, usually ending in BRK.
+pub fn eval(program: Vec<u8>) -> CpuState {
+    let mut memory = FlatMemory::new();
+    memory.load(EVAL_START, &program);
+    let mut cpu = CPU::new(memory);
+    cpu.reset();
+    while cpu.cycles < EVAL_MAX_CYCLES && cpu.step() {}
+    cpu.save_state()
+}
+
+impl<B: Memory> Memory for CPU<B> {
+    fn mem_read(&mut self, addr: u16) -> u8 {
         self.bus.mem_read(addr)
     }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.bus.peek(addr)
+    }
  
     fn mem_write(&mut self, addr: u16, data: u8) {
+        if let Some(log) = self.write_log.as_mut() {
+            log.push(MemoryWrite { address: addr, old: self.bus.peek(addr), new: data });
+        }
         self.bus.mem_write(addr, data)
     }
-    fn mem_read_u16(&self, pos: u16) -> u16 {
+    fn mem_read_u16(&mut self, pos: u16) -> u16 {
         self.bus.mem_read_u16(pos)
     }
-  
-    fn mem_write_u16(&mut self, pos: u16, data: u16) {
-        self.bus.mem_write_u16(pos, data)
-    }
 }
 
-impl CPU {
+impl<B: Memory> CPU<B> {
     //////CONSTRUCTOR
 
-    pub fn new(bus: Bus) -> Self {
+    pub fn new(bus: B) -> Self {
         CPU { 
             register_a: 0,
             register_x: 0,
@@ -122,57 +344,74 @@ impl CPU {
             status: CpuFlags::from_bits_truncate(0b100100),
             program_counter: 0,
             stack_pointer: STACK_RESET,
+            cycles: 0,
+            instructions_executed: 0,
+            jumped: false,
+            irq_inhibit: true,
             memory: [0; 0xFFFF],
             bus: bus,
+            jam_behavior: JamBehavior::default(),
+            allow_unofficial: true,
+            trap_on_ram_execution: false,
+            trace_filter: None,
+            halted: None,
+            instruction_hook: None,
+            write_log: None,
         }
     }
 
     ////// ADDRESSNG MODE
     pub fn get_absolute_address(&self, mode: &AddressingMode, addr: u16) -> u16 {
         match mode {
-            AddressingMode::ZeroPage => self.mem_read(addr) as u16,
+            AddressingMode::ZeroPage => self.peek(addr) as u16,
 
-            AddressingMode::Absolute => self.mem_read_u16(addr),
+            AddressingMode::Absolute => self.peek_u16(addr),
 
             AddressingMode::ZeroPage_X => {
-                let pos = self.mem_read(addr);
+                let pos = self.peek(addr);
                 let address = pos.wrapping_add(self.register_x) as u16;
-                addr
+                address
             }
             AddressingMode::ZeroPage_Y => {
-                let pos = self.mem_read(addr);
+                let pos = self.peek(addr);
                 let address = pos.wrapping_add(self.register_y) as u16;
-                addr
+                address
             }
 
             AddressingMode::Absolute_X => {
-                let base = self.mem_read_u16(addr);
+                let base = self.peek_u16(addr);
                 let address = base.wrapping_add(self.register_x as u16);
-                addr
+                address
             }
             AddressingMode::Absolute_Y => {
-                let base = self.mem_read_u16(addr);
+                let base = self.peek_u16(addr);
                 let address = base.wrapping_add(self.register_y as u16);
-                addr
+                address
             }
 
             AddressingMode::Indirect_X => {
-                let base = self.mem_read(addr);
+                let base = self.peek(addr);
 
                 let ptr: u8 = (base as u8).wrapping_add(self.register_x);
-                let lo = self.mem_read(ptr as u16);
-                let hi = self.mem_read(ptr.wrapping_add(1) as u16);
+                let lo = self.peek(ptr as u16);
+                let hi = self.peek(ptr.wrapping_add(1) as u16);
                 (hi as u16) << 8 | (lo as u16)
             }
             AddressingMode::Indirect_Y => {
-                let base = self.mem_read(addr);
+                let base = self.peek(addr);
 
-                let lo = self.mem_read(base as u16);
-                let hi = self.mem_read((base as u8).wrapping_add(1) as u16);
+                let lo = self.peek(base as u16);
+                let hi = self.peek((base as u8).wrapping_add(1) as u16);
                 let deref_base = (hi as u16) << 8 | (lo as u16);
                 let deref = deref_base.wrapping_add(self.register_y as u16);
                 deref
             }
+            AddressingMode::Indirect => {
+                let pointer = self.peek_u16(addr);
+                let lo = self.peek(pointer);
+                let hi = self.peek(Self::indirect_high_byte_address(pointer));
+                (hi as u16) << 8 | (lo as u16)
+            }
 
             _ => {
                 panic!("mode {:?} is not supported", mode);
@@ -180,42 +419,64 @@ impl CPU {
         }
     }
 
+    /// # Resolve Operand Address
+    /// Returns the effective address the instruction at the program counter would access,
+    /// computed from the current registers without touching the bus.
+    /// Implied, accumulator, branches and jumps (JMP, JSR) resolve to `None`.
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode_byte` - The opcode of the instruction at the program counter.
+    pub fn resolve_operand_address(&self, opcode_byte: u8) -> Option<u16> {
+        let opcode = opcode::OPCODE_MAP.get(&opcode_byte)?;
+        let operand = self.program_counter.wrapping_add(1);
+        match opcode.mode {
+            AddressingMode::NoneAddressing
+            | AddressingMode::Accumulator
+            | AddressingMode::Relative
+            | AddressingMode::Indirect => None,
+            AddressingMode::Immediate => Some(operand),
+            _ => Some(self.get_absolute_address(&opcode.mode, operand)),
+        }
+    }
+
     /// # Get Operand Address
-    /// Based on which addressing mode is engaged, modify cpu register values
-    fn get_operand_address(&mut self, mode: &AddressingMode) -> u16 {
+    /// Based on which addressing mode is engaged, modify cpu register values.
+    /// Also reports whether indexing crossed a page boundary, which costs read instructions an extra cycle.
+    fn get_operand_address(&mut self, mode: &AddressingMode) -> (u16, bool) {
         match mode {
-            AddressingMode::Immediate => self.program_counter,
+            AddressingMode::Immediate => (self.program_counter, false),
 
-            AddressingMode::ZeroPage => self.mem_read(self.program_counter) as u16,
+            AddressingMode::ZeroPage => (self.mem_read(self.program_counter) as u16, false),
 
-            AddressingMode::Absolute => self.mem_read_u16(self.program_counter),
+            AddressingMode::Absolute => (self.mem_read_u16(self.program_counter), false),
 
             AddressingMode::ZeroPage_X => {
                 let position = self.mem_read(self.program_counter);
                 let address = position.wrapping_add(self.register_x) as u16;
-                address
+                (address, false)
             }
             AddressingMode::ZeroPage_Y => {
                 let position = self.mem_read(self.program_counter);
                 let address = position.wrapping_add(self.register_y) as u16;
-                address
+                (address, false)
             },
             AddressingMode::Absolute_X => {
                 let base = self.mem_read_u16(self.program_counter);
                 let address = base.wrapping_add(self.register_x as u16);
-                address
+                (address, self.page_cross_dummy_read(base, address))
             },
             AddressingMode::Absolute_Y => {
                 let base = self.mem_read_u16(self.program_counter);
                 let address = base.wrapping_add(self.register_y as u16);
-                address
+                (address, self.page_cross_dummy_read(base, address))
             },
             AddressingMode::Indirect_X => {
                 let base = self.mem_read(self.program_counter);
                 let pointer: u8 = (base as u8).wrapping_add(self.register_x);
                 let low = self.mem_read(pointer as u16);
                 let high = self.mem_read(pointer.wrapping_add(1) as u16);
-                (high as u16) << 8 | (low as u16)
+                ((high as u16) << 8 | (low as u16), false)
             },
             AddressingMode::Indirect_Y => {
                 let base = self.mem_read(self.program_counter);
@@ -223,22 +484,85 @@ impl CPU {
                 let high = self.mem_read((base as u8).wrapping_add(1) as u16);
                 let deref_base = (high as u16) << 8 | (low as u16);
                 let deref = deref_base.wrapping_add(self.register_y as u16);
-                deref
+                (deref, self.page_cross_dummy_read(deref_base, deref))
+            },
+            AddressingMode::Indirect => {
+                let pointer = self.mem_read_u16(self.program_counter);
+                let lo = self.mem_read(pointer);
+                let hi = self.mem_read(Self::indirect_high_byte_address(pointer));
+                ((hi as u16) << 8 | (lo as u16), false)
             },
-            AddressingMode::NoneAddressing => {
+            AddressingMode::Accumulator => {
+                panic!("accumulator mode operates on register A, it has no operand address");
+            },
+            AddressingMode::Relative | AddressingMode::NoneAddressing => {
                 panic!("mode {:?} is not supported", mode);
             },
         }
     }
 
+    /// Stores the result of a read-modify-write instruction (ASL, LSR, ROL, ROR, INC, DEC and their
+    /// unofficial combinations). The 6502 writes the unmodified value back while it works out the new one,
+    /// so the bus sees two writes, which I/O registers like PPUDATA act on both times.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The operand address.
+    /// * `old` - The value read.
+    /// * `new` - The result.
+    fn read_modify_write(&mut self, address: u16, old: u8, new: u8) {
+        self.mem_write(address, old);
+        self.mem_write(address, new);
+    }
+
+    /// Like `get_operand_address`, for stores and read-modify-write instructions. They can't undo a write to the
+    /// wrong address, so they always wait for the high byte fix up and spend the dummy read at the un-fixed
+    /// address, page crossed or not. Only loads skip it when no carry was needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - The instruction's addressing mode.
+    fn get_write_operand_address(&mut self, mode: &AddressingMode) -> u16 {
+        let (address, page_cross) = self.get_operand_address(mode);
+        let indexed = matches!(
+            mode,
+            AddressingMode::Absolute_X | AddressingMode::Absolute_Y | AddressingMode::Indirect_Y
+        );
+        if indexed && !page_cross {
+            // without a carry the un-fixed address is the real one
+            self.mem_read(address);
+        }
+        address
+    }
+
+    /// The 6502 adds the index to the low byte first and only fixes the high byte up on the next cycle.
+    /// When that carry is needed, the bus sees a dummy read at the un-fixed address before the real one,
+    /// which is observable on I/O registers with read side effects.
+    /// Returns whether a page was crossed.
+    ///
+    /// # Arguments
+    ///
+    /// * `base` - The address before indexing.
+    /// * `address` - The indexed, fixed up address.
+    fn page_cross_dummy_read(&mut self, base: u16, address: u16) -> bool {
+        if !page_cross(base, address) {
+            return false;
+        }
+        self.mem_read((base & 0xFF00) | (address & 0x00FF));
+        true
+    }
+
     //////OPCODE FUNCTIONS
     /// # Add With Carry 
     /// From: https://www.nesdev.org/obelisk-6502-guide/reference.html#ADC.
     /// This instruction adds the contents of a memory location to the accumulator together with the carry bit. 
     /// If overflow occurs the carry bit is set, this enables multiple byte addition to be performed.
     fn adc(&mut self, mode: &AddressingMode) {
-        let address = self.get_operand_address(mode);
+        let (address, page_cross) = self.get_operand_address(mode);
         let value = self.mem_read(address);
+        if page_cross {
+            self.tick(1);
+        }
         self.add_to_register_a(value);
     }
 
@@ -246,8 +570,11 @@ impl CPU {
     /// From: https://www.nesdev.org/obelisk-6502-guide/reference.html#AND.
     /// A logical AND is performed, bit by bit, on the accumulator contents using the contents of a byte of memory.
     fn and(&mut self, mode: &AddressingMode) {
-        let address = self.get_operand_address(mode);
+        let (address, page_cross) = self.get_operand_address(mode);
         let value = self.mem_read(address);
+        if page_cross {
+            self.tick(1);
+        }
         self.set_register_a(value & self.register_a);
     }
  
@@ -258,9 +585,14 @@ impl CPU {
     /// If only bit 5 is 1: set V, clear C.
     /// If only bit 6 is 1: set C and V.
     /// Status flags: N,V,Z,C
+    /// With the decimal flag set the BCD variant is used instead, see `arr_decimal`.
     fn arr(&mut self, mode: &AddressingMode) {
-        let address = self.get_operand_address(mode);
+        let (address, _) = self.get_operand_address(mode);
         let data = self.mem_read(address);
+        if self.status.contains(CpuFlags::DECIMAL_MODE) {
+            self.arr_decimal(data);
+            return;
+        }
         self.and_with_register_a(data);
         self.ror_accumulator();
         let result = self.register_a;
@@ -282,21 +614,49 @@ impl CPU {
         self.update_zero_and_negative_flags(result);
     }
 
+    /// # And Rotate Right (Decimal Mode)
+    /// From: http://www.oxyron.de/html/opcodes02.html (NMOS 6510 Unintended Opcodes, ARR in decimal mode).
+    /// N and Z are taken from the rotated value before any fixup, V is set if bit 6 changed during the rotate.
+    /// Each nibble of the AND result is then BCD adjusted: the low nibble gets +6 if it (plus its low bit) exceeds 5,
+    /// and the high nibble gets +6 (and sets C) if it (plus its low bit) exceeds 5, otherwise C is cleared.
+    fn arr_decimal(&mut self, data: u8) {
+        let and = self.register_a & data;
+        let carry_in = if self.status.contains(CpuFlags::CARRY) { 0b1000_0000 } else { 0 };
+        let mut result = (and >> 1) | carry_in;
+
+        self.update_zero_and_negative_flags(result);
+        self.status.set(CpuFlags::OVERFLOW, (and ^ result) & 0b0100_0000 != 0);
+
+        if (and & 0x0f) + (and & 0x01) > 0x05 {
+            result = (result & 0xf0) | (result.wrapping_add(0x06) & 0x0f);
+        }
+
+        if (and & 0xf0) as u16 + (and & 0x10) as u16 > 0x50 {
+            result = (result & 0x0f) | (result.wrapping_add(0x60) & 0xf0);
+            self.set_carry_flag();
+        } else {
+            self.clear_carry_flag();
+        }
+
+        self.register_a = result;
+    }
+
     /// # Arithmetic Shift Left
     /// From: https://www.nesdev.org/obelisk-6502-guide/reference.html#ASL.
     /// This operation shifts all the bits of the accumulator or memory contents one bit left. 
     /// Bit 0 is set to 0 and bit 7 is placed in the carry flag. 
     /// The effect of this operation is to multiply the memory contents by 2 (ignoring 2's complement considerations), setting the carry if the result will not fit in 8 bits.
     fn asl(&mut self, mode: &AddressingMode) -> u8{
-        let address = self.get_operand_address(mode);
-        let mut data = self.mem_read(address);
+        let address = self.get_write_operand_address(mode);
+        let old = self.mem_read(address);
+        let mut data = old;
         if data >> 7 == 1 {
             self.set_carry_flag();
         } else {
             self.clear_carry_flag()
         }
         data = data << 1;
-        self.mem_write(address, data);
+        self.read_modify_write(address, old, data);
         self.update_zero_and_negative_flags(data);
         data
     }
@@ -313,7 +673,7 @@ impl CPU {
     }
 
     fn asx(&mut self, mode: &AddressingMode) {
-        let address = self.get_operand_address(mode);
+        let (address, _) = self.get_operand_address(mode);
         let data = self.mem_read(address);
         let x_and_a = self.register_x & self.register_a;
         let result = x_and_a.wrapping_sub(data);
@@ -325,18 +685,30 @@ impl CPU {
 
         self.register_x = result;
     }
+    /// Loads the PC from a control flow instruction, which keeps `execute` from
+    /// stepping over the operand bytes afterwards, even when the target is the next byte.
+    fn jump_to(&mut self, address: u16) {
+        self.program_counter = address;
+        self.jumped = true;
+    }
+
     /// # Generic Branch Function
     /// Covers all branch functions starting with: https://www.nesdev.org/obelisk-6502-guide/reference.html#BCC.
-    /// If a certain condition is met, branch program to a new location
+    /// If a certain condition is met, branch program to a new location.
+    /// A taken branch costs 1 extra cycle, and 1 more if the target is on a different page than the next instruction.
     fn branch(&mut self, condition: bool) {
         if condition {
+            self.tick(1);
+
             let jump: i8 = self.mem_read(self.program_counter) as i8;
-            let jump_address = self
-                .program_counter
-                .wrapping_add(1)
-                .wrapping_add(jump as u16);
+            let next_instruction = self.program_counter.wrapping_add(1);
+            let jump_address = next_instruction.wrapping_add(jump as u16);
 
-            self.program_counter = jump_address;
+            if page_cross(next_instruction, jump_address) {
+                self.tick(1);
+            }
+
+            self.jump_to(jump_address);
         }
     }
     
@@ -346,7 +718,7 @@ impl CPU {
     /// The mask pattern in A is ANDed with the value in memory to set or clear the zero flag, but the result is not kept. 
     /// Bits 7 and 6 of the value from memory are copied into the N and V flags.
     fn bit(&mut self, mode: &AddressingMode) {
-        let address = self.get_operand_address(mode);
+        let (address, _) = self.get_operand_address(mode);
         let data = self.mem_read(address);
         let and = self.register_a & data;
         if and == 0 {
@@ -385,8 +757,11 @@ impl CPU {
     /// Covers all compary functions including: https://www.nesdev.org/obelisk-6502-guide/reference.html#CMP.
     /// This instruction compares the contents of the given memory location with another memory held value and sets the zero and carry flags as appropriate.
     fn compare(&mut self, mode: &AddressingMode, compare_with: u8) {
-        let address = self.get_operand_address(mode);
+        let (address, page_cross) = self.get_operand_address(mode);
         let data = self.mem_read(address);
+        if page_cross {
+            self.tick(1);
+        }
         if data <= compare_with {
             self.status.insert(CpuFlags::CARRY);
         } else {
@@ -399,10 +774,11 @@ impl CPU {
     /// # Dec + CmP
     /// Subtract 1 from memory (without borrow).
     fn dcp(&mut self, mode: &AddressingMode) {
-        let address = self.get_operand_address(mode);
-        let mut data = self.mem_read(address);
+        let address = self.get_write_operand_address(mode);
+        let old = self.mem_read(address);
+        let mut data = old;
         data = data.wrapping_sub(1);
-        self.mem_write(address, data);
+        self.read_modify_write(address, old, data);
         // self._update_zero_and_negative_flags(data);
         if data <= self.register_a {
             self.status.insert(CpuFlags::CARRY);
@@ -414,10 +790,11 @@ impl CPU {
     /// From: https://www.nesdev.org/obelisk-6502-guide/reference.html#DEC.
     /// Subtracts one from the value held at a specified memory location setting the zero and negative flags as appropriate.
     fn dec(&mut self, mode: &AddressingMode) -> u8 {
-        let address = self.get_operand_address(mode);
-        let mut data = self.mem_read(address);
+        let address = self.get_write_operand_address(mode);
+        let old = self.mem_read(address);
+        let mut data = old;
         data = data.wrapping_sub(1);
-        self.mem_write(address, data);
+        self.read_modify_write(address, old, data);
         self.update_zero_and_negative_flags(data);
         data
     }
@@ -436,17 +813,21 @@ impl CPU {
     /// # XOR
     /// An exclusive OR is performed, bit by bit, on the accumulator contents using the contents of a byte of memory.
     fn eor(&mut self, mode: &AddressingMode) {
-        let address = self.get_operand_address(mode);
+        let (address, page_cross) = self.get_operand_address(mode);
         let data = self.mem_read(address);
+        if page_cross {
+            self.tick(1);
+        }
         self.mem_write(address, data ^ self.register_a);  // lol i never knew `^` was the xor op
     }
 
     /// # Increment
     fn inc(&mut self, mode: &AddressingMode) -> u8 {
-        let address = self.get_operand_address(mode);
-        let mut data = self.mem_read(address);
+        let address = self.get_write_operand_address(mode);
+        let old = self.mem_read(address);
+        let mut data = old;
         data = data.wrapping_add(1);
-        self.mem_write(address, data);
+        self.read_modify_write(address, old, data);
         self.update_zero_and_negative_flags(data);
         data
     }
@@ -465,37 +846,37 @@ impl CPU {
 
     /// # Jump
     /// Sets the program counter to the address specified by the operand.
-    fn jmp(&mut self){
-        let mem_address = self.mem_read_u16(self.program_counter);
-        // let indirect_ref = self.mem_read_u16(mem_address);
-        //6502 bug mode with with page boundary:
-        //  if address $3000 contains $40, $30FF contains $80, and $3100 contains $50,
-        // the result of JMP ($30FF) will be a transfer of control to $4080 rather than $5080 as you intended
-        // i.e. the 6502 took the low byte of the address from $30FF and the high byte from $3000
-
-        let indirect_ref = if mem_address & 0x00FF == 0x00FF {
-            let lo = self.mem_read(mem_address);
-            let hi = self.mem_read(mem_address & 0xFF00);
-            (hi as u16) << 8 | (lo as u16)
-        } else {
-            self.mem_read_u16(mem_address)
-        };
+    fn jmp(&mut self, mode: &AddressingMode) {
+        let (target, _) = self.get_operand_address(mode);
+        self.jump_to(target);
+    }
 
-        self.program_counter = indirect_ref;
+    /// Where JMP ($nnnn) reads the high byte of its target from. The 6502 never carries into the
+    /// pointer's high byte: if $3000 contains $40, $30FF contains $80 and $3100 contains $50,
+    /// JMP ($30FF) goes to $4080 rather than $5080, the high byte comes from $3000.
+    ///
+    /// # Arguments
+    ///
+    /// * `pointer` - The operand of the JMP, where the low byte is read from.
+    fn indirect_high_byte_address(pointer: u16) -> u16 {
+        (pointer & 0xFF00) | (pointer as u8).wrapping_add(1) as u16
     }
 
     /// # Jump to SubRoutine 
     /// The JSR instruction pushes the address (minus one) of the return point on to the stack and then sets the program counter to the target memory address.
     fn jsr(&mut self) {
-        self.stack_push_u16(self.program_counter + 2 - 1);
+        self.stack_push_u16(self.program_counter.wrapping_add(2).wrapping_sub(1));
         let target_address = self.mem_read_u16(self.program_counter);
-        self.program_counter = target_address
+        self.jump_to(target_address);
     }
 
     /// # Load Data (into) Accumulator
     fn lda(&mut self, mode: &AddressingMode){
-        let address = self.get_operand_address(mode);
+        let (address, page_cross) = self.get_operand_address(mode);
         let value = self.mem_read(address);
+        if page_cross {
+            self.tick(1);
+        }
 
         self.register_a = value;
         self.update_zero_and_negative_flags(self.register_a);
@@ -503,8 +884,11 @@ impl CPU {
 
     /// # Load Data into X register
     fn ldx(&mut self, mode: &AddressingMode) {
-        let address = self.get_operand_address(mode);
+        let (address, page_cross) = self.get_operand_address(mode);
         let value = self.mem_read(address);
+        if page_cross {
+            self.tick(1);
+        }
 
         self.register_x = value;
         self.update_zero_and_negative_flags(self.register_x);
@@ -512,8 +896,11 @@ impl CPU {
 
     /// # Load Y Register
     fn ldy(&mut self, mode: &AddressingMode) {
-        let address = self.get_operand_address(mode);
+        let (address, page_cross) = self.get_operand_address(mode);
         let value = self.mem_read(address);
+        if page_cross {
+            self.tick(1);
+        }
 
         self.register_y = value;
         self.update_zero_and_negative_flags(self.register_y);
@@ -524,15 +911,16 @@ impl CPU {
     /// The bit that was in bit 0 is shifted into the carry flag. 
     /// Bit 7 is set to zero.
     fn lsr(&mut self, mode: &AddressingMode) -> u8 {
-        let address = self.get_operand_address(mode);
-        let mut data = self.mem_read(address);
+        let address = self.get_write_operand_address(mode);
+        let old = self.mem_read(address);
+        let mut data = old;
         if data & 1 == 1 {
             self.set_carry_flag();
         } else {
             self.clear_carry_flag();
         }
         data = data >> 1;
-        self.mem_write(address, data);
+        self.read_modify_write(address, old, data);
         self.update_zero_and_negative_flags(data);
         data
     }
@@ -551,8 +939,11 @@ impl CPU {
     /// # Logical Inclusive Or
     /// An inclusive OR is performed, bit by bit, on the accumulator contents using the contents of a byte of memory.
     fn ora(&mut self, mode: &AddressingMode){
-        let address = self.get_operand_address(mode);
+        let (address, page_cross) = self.get_operand_address(mode);
         let data = self.mem_read(address);
+        if page_cross {
+            self.tick(1);
+        }
         self.set_register_a(self.register_a | data);
     } 
 
@@ -591,8 +982,9 @@ impl CPU {
     /// Move each of the bits in either A or M one place to the left. 
     /// Bit 0 is filled with the current value of the carry flag whilst the old bit 7 becomes the new carry flag value.
     fn rol(&mut self, mode: &AddressingMode) -> u8 {
-        let address = self.get_operand_address(mode);
-        let mut data = self.mem_read(address);
+        let address = self.get_write_operand_address(mode);
+        let old = self.mem_read(address);
+        let mut data = old;
         let previous_carry_flag_set = self.status.contains(CpuFlags::CARRY);
 
         if data >> 7 == 1 {
@@ -604,7 +996,7 @@ impl CPU {
         if previous_carry_flag_set {
             data = data | 1;
         }
-        self.mem_write(address, data);
+        self.read_modify_write(address, old, data);
         self.update_zero_and_negative_flags(data);
         data
     }
@@ -628,8 +1020,9 @@ impl CPU {
 
     /// # Rotate Right
     fn ror(&mut self, mode: &AddressingMode) -> u8 {
-        let address = self.get_operand_address(mode);
-        let mut data = self.mem_read(address);
+        let address = self.get_write_operand_address(mode);
+        let old = self.mem_read(address);
+        let mut data = old;
         let previous_carry_value_set = self.status.contains(CpuFlags::CARRY);
 
         if data & 1 == 1 {
@@ -641,7 +1034,7 @@ impl CPU {
         if previous_carry_value_set {
             data = data | 0b10000000;
         }
-        self.mem_write(address, data);
+        self.read_modify_write(address, old, data);
         self.update_zero_and_negative_flags(data);
         data
     }
@@ -669,20 +1062,25 @@ impl CPU {
         self.status.remove(CpuFlags::BREAK);
         self.status.insert(CpuFlags::BREAK2);
 
-        self.program_counter = self.stack_pop_u16();
+        let return_address = self.stack_pop_u16();
+        self.jump_to(return_address);
     }
 
     /// # Return from Subroutine
     fn rts(&mut self) {
-        self.program_counter = self.stack_pop_u16() + 1;
+        let return_address = self.stack_pop_u16().wrapping_add(1);
+        self.jump_to(return_address);
     }
 
     /// # Subtract with Carry
     /// This instruction subtracts the contents of a memory location to the accumulator together with the not of the carry bit. 
     /// If overflow occurs the carry bit is clear, this enables multiple byte subtraction to be performed.
     fn sbc(&mut self, mode: &AddressingMode) {
-        let address = self.get_operand_address(mode);
+        let (address, page_cross) = self.get_operand_address(mode);
         let data = self.mem_read(address);
+        if page_cross {
+            self.tick(1);
+        }
         self.add_to_register_a(((data as i8).wrapping_neg().wrapping_sub(1)) as u8);
         
     }
@@ -705,19 +1103,19 @@ impl CPU {
 
     /// # Store Accumulator
     fn sta(&mut self, mode: &AddressingMode) {
-        let address = self.get_operand_address(mode);
+        let address = self.get_write_operand_address(mode);
         self.mem_write(address, self.register_a);
     }
 
     /// # Store X Register
     fn stx(&mut self, mode: &AddressingMode) {
-        let address = self.get_operand_address(mode);
+        let address = self.get_write_operand_address(mode);
         self.mem_write(address, self.register_x);
     }
 
     /// # Store Y Register
     fn sty(&mut self, mode: &AddressingMode) {
-        let address = self.get_operand_address(mode);
+        let address = self.get_write_operand_address(mode);
         self.mem_write(address, self.register_y);
     }
 
@@ -871,6 +1269,32 @@ impl CPU {
         self.set_register_a(data | self.register_a);
     }
 
+    /// Advances the CPU cycle counter.
+    ///
+    /// # Arguments
+    ///
+    /// * `cycles` - The number of cycles spent by the current instruction.
+    fn tick(&mut self, cycles: u8) {
+        self.cycles += self.bus.tick(cycles);
+    }
+
+    /// # Hardware Interrupt
+    /// Pushes the program counter and status (with the B flag clear) onto the stack,
+    /// disables interrupts and jumps through the interrupt's vector.
+    /// The decimal flag is left alone like on the NMOS 6502, the 65C02 is the one that clears it.
+    fn interrupt(&mut self, interrupt: interrupt::Interrupt) {
+        self.stack_push_u16(self.program_counter);
+        let mut flags = self.status.clone();
+        flags.set(CpuFlags::BREAK, interrupt.b_flag_mask & 0b010000 != 0);
+        flags.set(CpuFlags::BREAK2, interrupt.b_flag_mask & 0b100000 != 0);
+
+        self.stack_push(flags.bits());
+        self.status.insert(CpuFlags::INTERRUPT_DISABLE);
+
+        self.tick(interrupt.cpu_cycles);
+        self.program_counter = self.mem_read_u16(interrupt.vector_addr);
+    }
+
     ////// STATE MANAGEMENT
     /// Loads a program into memory starting at address  0x8000.
     ///
@@ -898,501 +1322,1754 @@ impl CPU {
     pub fn load_and_run(&mut self, program: Vec<u8>){
         self.load(program);
         self.reset();
-        self.run()
+        self.run();
+    }
+
+    /// Assembles `source` and writes it to memory at $0600, with the program counter pointing at it.
+    /// Returns the length of the machine code.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The assembly, see `asm::assemble` for the syntax.
+    ///
+    /// # Panics
+    ///
+    /// If the source doesn't assemble, this is meant for tests.
+    pub fn load_asm(&mut self, source: &str) -> usize {
+        let program = asm::assemble(source, EVAL_START).unwrap_or_else(|err| panic!("{}", err));
+        for (offset, byte) in program.iter().enumerate() {
+            self.mem_write(EVAL_START.wrapping_add(offset as u16), *byte);
+        }
+        self.program_counter = EVAL_START;
+        program.len()
+    }
+
+    /// Captures the registers into a `CpuState`.
+    pub fn save_state(&self) -> CpuState {
+        CpuState {
+            register_a: self.register_a,
+            register_x: self.register_x,
+            register_y: self.register_y,
+            status: self.status.clone(),
+            program_counter: self.program_counter,
+            stack_pointer: self.stack_pointer,
+            cycles: self.cycles,
+        }
+    }
+
+    /// Restores the registers from a `CpuState`.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - A snapshot taken by `save_state`.
+    pub fn load_state(&mut self, state: &CpuState) {
+        self.register_a = state.register_a;
+        self.register_x = state.register_x;
+        self.register_y = state.register_y;
+        self.status = state.status.clone();
+        self.program_counter = state.program_counter;
+        self.stack_pointer = state.stack_pointer;
+        self.cycles = state.cycles;
+        self.irq_inhibit = self.status.contains(CpuFlags::INTERRUPT_DISABLE);
     }
 
     pub fn reset(&mut self) {
         self.register_a = 0;
         self.register_x = 0;
-        self.status = CpuFlags::ZERO;
+        // reset masks IRQs until the program is ready for them
+        self.status = CpuFlags::ZERO | CpuFlags::INTERRUPT_DISABLE;
+        self.irq_inhibit = true;
+        self.instructions_executed = 0;
+        self.halted = None;
+
+        self.program_counter = self.mem_read_u16(RESET_VECTOR);
+    }
+
+    /// Where reset jumps to, read from $FFFC/$FFFD without side effects.
+    pub fn reset_vector(&self) -> u16 {
+        self.peek_u16(RESET_VECTOR)
+    }
+
+    /// Where an NMI jumps to, read from $FFFA/$FFFB without side effects.
+    pub fn nmi_vector(&self) -> u16 {
+        self.peek_u16(interrupt::NMI.vector_addr)
+    }
 
-        self.program_counter = self.mem_read_u16(0xFFFC);
+    /// Where an IRQ or BRK jumps to, read from $FFFE/$FFFF without side effects.
+    pub fn irq_vector(&self) -> u16 {
+        self.peek_u16(interrupt::IRQ.vector_addr)
+    }
+
+    /// Whether a KIL/JAM opcode has halted the CPU since the last reset.
+    pub fn is_jammed(&self) -> bool {
+        matches!(self.halted, Some(RunOutcome::Jammed { .. }))
+    }
+
+    fn outcome(&self) -> RunOutcome {
+        self.halted.unwrap_or(RunOutcome::Break)
+    }
+
+    /// Number of instructions decoded since the last reset, BRK included.
+    pub fn instructions_executed(&self) -> u64 {
+        self.instructions_executed
     }
 
     ////// CPU INTERPRETER
 
-    pub fn run(&mut self) {
-        self.run_with_callback(|_| {});
+    pub fn run(&mut self) -> RunOutcome {
+        self.run_with_callback(|_| {})
     }
 
-    /// # CPU CYCLE IMPLEMENTATION
-    /// Fetch next instruction from cpu memory. 
-    /// Decode instruction.
-    /// Execute instruction.
-    /// Repeat.
-    pub fn run_with_callback<F>(&mut self, mut callback: F)
+    /// Runs until BRK like `run`, without calling back into a closure between instructions.
+    /// Meant for benchmarks and fast-forwarding.
+    pub fn run_fast(&mut self) -> RunOutcome {
+        let opcodes: &HashMap<u8, &'static opcode::OpCode> = &opcode::OPCODE_MAP;
+
+        loop {
+            if !self.execute(opcodes) {
+                return self.outcome();
+            }
+            self.poll_interrupts();
+        }
+    }
+
+    /// Runs until BRK or a jam, calling `callback` before each instruction.
+    /// Interrupts are serviced between the instruction and the next callback.
+    pub fn run_with_callback<F>(&mut self, mut callback: F) -> RunOutcome
     where
-        F: FnMut(&mut CPU),
+        F: FnMut(&mut CPU<B>),
     {
         let ref opcodes: HashMap<u8, &'static opcode::OpCode> = *opcode::OPCODE_MAP;
 
         loop {
             callback(self);
-            ///// FETCH
-            let code = self.mem_read(self.program_counter);
-            self.program_counter += 1;
-            // preserves place in memory for reference
-            let program_state = self.program_counter;
-            let opcode = opcodes.get(&code).expect(&format!("OpCode {:?} is not recognized", code));
-            ///// DECODE
-            match code {
-                ///// EXECUTE
-                /* ADC */
-                0x69 |  0x65 |  0x75 |  0x6d |  0x7d |  0x79 |  0x61 |  0x71 => {
-                    self.adc(&opcode.mode);
-                },
-
-                /* AND */
-                0x29 |  0x25 |  0x35 |  0x2d |  0x3d |  0x39 |  0x21 |  0x31 => {
-                    self.and(&opcode.mode);
-                },
-
-                /*ASL*/ 0x0a => self.asl_accumulator(),
-
-                /* ASL */
-                0x06 |  0x16 |  0x0e |  0x1e => {
-                    self.asl(&opcode.mode);
-                },
-
-                /* BCC */
-                0x90 => {
-                    self.branch(!self.status.contains(CpuFlags::CARRY));
-                },
-
-                /* BCS */
-                0xb0 => {
-                    self.branch(self.status.contains(CpuFlags::CARRY));
-                },
-
-                /* BEQ */
-                0xf0 => {
-                    self.branch(self.status.contains(CpuFlags::ZERO));
-                },
-
-                /* BIT */
-                0x24 |  0x2c => {
-                    self.bit(&opcode.mode);
-                },
-
-                /* BMI */
-                0x30 => {
-                    self.branch(self.status.contains(CpuFlags::NEGATIVE));
-                },
-
-                /* BNE */
-                0xd0 => {
-                    self.branch(!self.status.contains(CpuFlags::ZERO));
-                },
-
-                /* BPL */
-                0x10 => {
-                    self.branch(!self.status.contains(CpuFlags::NEGATIVE));
-                },
-
-                /* BRK */
-                0x00 => return,
-
-                /* BVC */
-                0x50 => {
-                    self.branch(!self.status.contains(CpuFlags::OVERFLOW));
-                },
-
-                /* BVS */
-                0x70 => {
-                    self.branch(self.status.contains(CpuFlags::OVERFLOW));
-                },
-
-                /* CLC */
-                0x18 => self.clc(),
-
-                /* CLD */
-                0xd8 => self.cld(),
-
-                /* CLI */
-                0x58 => self.cli(),
-
-                /* CLV */
-                0xb8 => self.clv(),
-
-                /* CMP */
-                0xc9 |  0xc5 |  0xd5 |  0xcd |  0xdd |  0xd9 |  0xc1 |  0xd1 => {
-                    self.compare(&opcode.mode, self.register_a);
-                },
-
-                /* CPX */
-                0xe0 |  0xe4 |  0xec => self.compare(&opcode.mode, self.register_x),
-
-                /* CPY */
-                0xc0 |  0xc4 |  0xcc => {
-                    self.compare(&opcode.mode, self.register_y);
-                },
-
-                /* DEC */
-                0xc6 |  0xd6 |  0xce |  0xde => {
-                    self.dec(&opcode.mode);
-                },
-
-                /* DEX */
-                0xca => {
-                    self.dex();
-                },
-
-                /* DEY */
-                0x88 => {
-                    self.dey();
-                },
-
-                /* EOR */
-                0x49 |  0x45 |  0x55 |  0x4d |  0x5d |  0x59 |  0x41 |  0x51 => {
-                    self.eor(&opcode.mode);
-                },
-
-                /* INC */
-                0xe6 |  0xf6 |  0xee |  0xfe => {
-                    self.inc(&opcode.mode);
-                },
-
-                /* INX */
-                0xe8 => self.inx(),
-
-                /* INY */
-                0xc8 => self.iny(),
-
-                /* JMP Absolute */
-                0x4c => {
-                    let mem_address = self.mem_read_u16(self.program_counter);
-                    self.program_counter = mem_address;
-                },
-
-                /* JMP Indirect */
-                0x6c => self.jmp(),
-
-                /* JSR */
-                0x20 => self.jsr(),
-
-                /* LDA */
-                0xa9 |  0xa5 |  0xb5 |  0xad |  0xbd |  0xb9 |  0xa1 |  0xb1 => {
-                    self.lda(&opcode.mode);
-                },
-
-                /* LDX */
-                0xa2 |  0xa6 |  0xb6 |  0xae |  0xbe => {
-                    self.ldx(&opcode.mode);
-                },
-
-                /* LDY */
-                0xa0 |  0xa4 |  0xb4 |  0xac |  0xbc => {
-                    self.ldy(&opcode.mode);
-                },
-
-                /* LSR */ 0x4a => self.lsr_accumulator(),
-
-                /* LSR */
-                0x46 |  0x56 |  0x4e |  0x5e => {
-                    self.lsr(&opcode.mode);
-                },
-                /* ORA */
-                0x09 |  0x05 |  0x15 |  0x0d |  0x1d |  0x19 |  0x01 |  0x11 => {
-                    self.ora(&opcode.mode);
-                },
-                /* PHA */
-                0x48 => self.pha(),
-
-                /* PHP */
-                0x08 => {
-                    self.php();
-                },
-
-                /* PLA */
-                0x68 => {
-                    self.pla();
-                },
-
-                /* PLP */
-                0x28 => {
-                    self.plp();
-                },
-
-                /*ROL*/ 0x2a => self.rol_accumulator(),
-
-                /* ROL */
-                0x26 |   0x36 |   0x2e |   0x3e => {
-                    self.rol(&opcode.mode);
-                },
-
-                /* ROR */ 0x6a => self.ror_accumulator(),
-
-                /* ROR */
-                0x66 |   0x76 |   0x6e |   0x7e => {
-                    self.ror(&opcode.mode);
-                },
-
-                /* RTI */
-                0x40 => {
-                    self.rti();
-                },
-
-                /* RTS */
-                0x60 => {
-                    self.rts()
-                },
-
-                /* SBC */
-                0xe9 |   0xe5 |   0xf5 |   0xed |   0xfd |   0xf9 |   0xe1 |   0xf1 => {
-                    self.sbc(&opcode.mode);
-                },
-
-                /* SEC */
-                0x38 => self.sec(),
-
-                /* SED */
-                0xf8 => self.sed(),
-
-                /* SEI */
-                0x78 => self.sei(),
-
-                /* STA */
-                0x85 |   0x95 |   0x8d |   0x9d |   0x99 |   0x81 |   0x91 => {
-                    self.sta(&opcode.mode);
-                },
-
-                /* STX */
-                0x86 |   0x96 |   0x8e => {
-                    self.stx(&opcode.mode)
-                },
-
-                /* STY */
-                0x84 |   0x94 |   0x8c => {
-                    self.sty(&opcode.mode)
-                },
-
-                /* TAX */
-                0xaa => self.tax(),
-
-                /* TAY */
-                0xa8 => {
-                    self.tay()
-                },
-
-                /* TSX */
-                0xba => {
-                    self.tsx()
-                },
-
-                /* TXA */
-                0x8a => {
-                    self.txa()
-                },
-
-                /* TXS */
-                0x9a => {
-                    self.txs()
-                },
-
-                /* TYA */
-                0x98 => {
-                    self.tya()
-                },
-
-                /* NOP */
-                0xea => {
-                    // do nothing
-                },
-
-                ////// UNOFFICIAL OPCODES
-
-                /* DCP */
-                0xc7 | 0xd7 | 0xCF | 0xdF | 0xdb | 0xd3 | 0xc3 => {
-                    self.dcp(&opcode.mode);
-                }
+            if !self.execute(opcodes) {
+                return self.outcome();
+            }
+            self.poll_interrupts();
+        }
+    }
 
-                /* RLA */
-                0x27 | 0x37 | 0x2F | 0x3F | 0x3b | 0x33 | 0x23 => {
-                    let data = self.rol(&opcode.mode);
-                    self.and_with_register_a(data);
+    /// Executes a single instruction, then services any interrupt that became pending.
+    /// Returns false once BRK is reached or while the CPU is jammed or stopped on an error.
+    pub fn step(&mut self) -> bool {
+        if !self.execute(&opcode::OPCODE_MAP) {
+            return false;
+        }
+        self.poll_interrupts();
+        true
+    }
+
+    /// Like `step`, also returning every byte the instruction wrote, in order, to find out who wrote what.
+    /// Interrupt entry pushes count as part of the instruction they followed.
+    pub fn step_with_writes(&mut self) -> (bool, Vec<MemoryWrite>) {
+        self.write_log = Some(vec![]);
+        let stepped = self.step();
+        (stepped, self.write_log.take().unwrap_or_default())
+    }
+
+    /// Like `step`, except a JSR runs until its subroutine returns to the instruction after it.
+    /// Recursive calls back to the same return address are told apart by the stack pointer.
+    pub fn step_over(&mut self) -> StepResult {
+        let stepped = if self.peek(self.program_counter) == JSR_OPCODE {
+            let return_pc = self.program_counter.wrapping_add(3);
+            let stack_pointer = self.stack_pointer;
+            let cycle_limit = self.cycles + STEP_OVER_MAX_CYCLES;
+            loop {
+                if !self.step() {
+                    break false;
                 }
-
-                /* SLO */ 
-                0x07 | 0x17 | 0x0F | 0x1f | 0x1b | 0x03 | 0x13 => {
-                    let data = self.asl(&opcode.mode);
-                    self.or_with_register_a(data);
+                if self.program_counter == return_pc && self.stack_pointer == stack_pointer {
+                    break true;
                 }
-
-                /* SRE */ 
-                0x47 | 0x57 | 0x4F | 0x5f | 0x5b | 0x43 | 0x53 => {
-                    let data = self.lsr(&opcode.mode);
-                    self.xor_with_register_a(data);
+                if self.cycles >= cycle_limit {
+                    return StepResult::TimedOut;
                 }
+            }
+        } else {
+            self.step()
+        };
 
-                /* SKB */
-                0x80 | 0x82 | 0x89 | 0xc2 | 0xe2 => {
-                    /* 2 byte NOP (immediate ) */
-                    
-                }
+        if stepped {
+            StepResult::Completed
+        } else {
+            StepResult::Stopped(self.outcome())
+        }
+    }
 
-                /* AXS */
-                0xCB => {
-                    self.asx(&opcode.mode);
-                }
+    /// Steps until the program counter reaches `target_pc`, stopping before the instruction there.
+    /// Returns false if BRK was hit or the cycle budget ran out first.
+    ///
+    /// # Arguments
+    ///
+    /// * `target_pc` - Address of the instruction to stop at.
+    /// * `max_cycles` - How many CPU cycles to run at most.
+    pub fn run_to(&mut self, target_pc: u16, max_cycles: usize) -> bool {
+        let cycle_limit = self.cycles + max_cycles;
+        while self.program_counter != target_pc {
+            if self.cycles >= cycle_limit || !self.step() {
+                return false;
+            }
+        }
+        true
+    }
 
-                /* ARR */
-                0x6B => {
-                    self.arr(&opcode.mode);
-                }
+    /// Registers `hook` to run just before every instruction executes.
+    /// Replaces any previously registered hook.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - Called with the CPU, program counter already past the opcode byte, and the decoded instruction.
+    pub fn set_instruction_hook<F>(&mut self, hook: F)
+    where
+        F: FnMut(&mut CPU<B>, &OpCode) + 'static,
+    {
+        self.instruction_hook = Some(Box::new(hook));
+    }
 
-                /* unofficial SBC */
-                0xeb => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    let data = self.mem_read(addr);
-                    self.sub_from_register_a(data);
-                }
+    /// Removes the instruction hook, if any.
+    pub fn clear_instruction_hook(&mut self) {
+        self.instruction_hook = None;
+    }
 
-                /* ANC */
-                0x0b | 0x2b => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    let data = self.mem_read(addr);
-                    self.and_with_register_a(data);
-                    if self.status.contains(CpuFlags::NEGATIVE) {
-                        self.status.insert(CpuFlags::CARRY);
-                    } else {
-                        self.status.remove(CpuFlags::CARRY);
-                    }
-                }
+    /// # Interrupt Polling
+    /// Runs at the end of every instruction. Services a pending NMI, or a pending IRQ
+    /// unless the I flag was set when the instruction polled it.
+    ///
+    /// The 6502 polls before the last cycle of an instruction, so CLI, SEI and PLP
+    /// change the I flag too late for their own poll and the change only shows one
+    /// instruction later.
+    fn poll_interrupts(&mut self) {
+        if self.bus.poll_nmi() {
+            self.interrupt(interrupt::NMI);
+        } else if self.bus.poll_irq() && !self.irq_inhibit {
+            self.interrupt(interrupt::IRQ);
+        }
+    }
 
-                /* ALR */
-                0x4b => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    let data = self.mem_read(addr);
-                    self.and_with_register_a(data);
-                    self.lsr_accumulator();
-                }
+    /// # CPU CYCLE IMPLEMENTATION
+    /// Fetch next instruction from cpu memory. 
+    /// Decode instruction.
+    /// Execute instruction.
+    /// Returns false once BRK is reached or the CPU is jammed or stopped on an error.
+    fn execute(&mut self, opcodes: &HashMap<u8, &'static opcode::OpCode>) -> bool {
+        if self.halted.is_some() {
+            return false;
+        }
+        if self.trap_on_ram_execution && self.program_counter <= RAM_END {
+            self.halted = Some(RunOutcome::Error(CpuError::ExecutingData { pc: self.program_counter }));
+            return false;
+        }
+        ///// FETCH
+        let code = self.mem_read(self.program_counter);
+        self.program_counter = self.program_counter.wrapping_add(1);
+        let opcode = opcodes.get(&code).unwrap_or_else(|| panic!("OpCode {:?} is not recognized", code));
+        // unofficial mnemonics are marked with a leading *
+        if !self.allow_unofficial && opcode.abbreviation.starts_with('*') {
+            let pc = self.program_counter.wrapping_sub(1);
+            self.program_counter = pc;
+            self.halted = Some(RunOutcome::Error(CpuError::UnofficialOpcodeDisabled { opcode: code, pc }));
+            return false;
+        }
+        self.instructions_executed += 1;
+        // the hook is taken out for the call so it can borrow the CPU mutably
+        if let Some(mut hook) = self.instruction_hook.take() {
+            hook(self, opcode);
+            if self.instruction_hook.is_none() {
+                self.instruction_hook = Some(hook);
+            }
+        }
+        self.jumped = false;
+        let irq_inhibit = self.status.contains(CpuFlags::INTERRUPT_DISABLE);
+        ///// DECODE
+        match code {
+            ///// EXECUTE
+            /* ADC */
+            0x69 |  0x65 |  0x75 |  0x6d |  0x7d |  0x79 |  0x61 |  0x71 => {
+                self.adc(&opcode.mode);
+            },
 
+            /* AND */
+            0x29 |  0x25 |  0x35 |  0x2d |  0x3d |  0x39 |  0x21 |  0x31 => {
+                self.and(&opcode.mode);
+            },
 
-                /* NOP read */
-                0x04 | 0x44 | 0x64 | 0x14 | 0x34 | 0x54 | 0x74 | 0xd4 | 0xf4 | 0x0c | 0x1c
-                | 0x3c | 0x5c | 0x7c | 0xdc | 0xfc => {
-                    /* read and then do nothing? i guess */
-                    let addr = self.get_operand_address(&opcode.mode);
-                    let _data = self.mem_read(addr);
-                }
+            /*ASL*/ 0x0a => self.asl_accumulator(),
 
-                /* RRA */
-                0x67 | 0x77 | 0x6f | 0x7f | 0x7b | 0x63 | 0x73 => {
-                    let data = self.ror(&opcode.mode);
-                    self.add_to_register_a(data);
-                }
+            /* ASL */
+            0x06 |  0x16 |  0x0e |  0x1e => {
+                self.asl(&opcode.mode);
+            },
 
-                /* ISB */
-                0xe7 | 0xf7 | 0xef | 0xff | 0xfb | 0xe3 | 0xf3 => {
-                    let data = self.inc(&opcode.mode);
-                    self.sub_from_register_a(data);
-                }
+            /* BCC */
+            0x90 => {
+                self.branch(!self.status.contains(CpuFlags::CARRY));
+            },
+
+            /* BCS */
+            0xb0 => {
+                self.branch(self.status.contains(CpuFlags::CARRY));
+            },
 
-                /* NOPs */
-                0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xb2 | 0xd2
-                | 0xf2 => { /* do nothing */ }
+            /* BEQ */
+            0xf0 => {
+                self.branch(self.status.contains(CpuFlags::ZERO));
+            },
 
-                0x1a | 0x3a | 0x5a | 0x7a | 0xda | 0xfa => { /* do nothing */ }
-                // sure are a lot of unofficial opcodes that are useless
+            /* BIT */
+            0x24 |  0x2c => {
+                self.bit(&opcode.mode);
+            },
 
-                /* LAX */
-                0xa7 | 0xb7 | 0xaf | 0xbf | 0xa3 | 0xb3 => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    let data = self.mem_read(addr);
-                    self.set_register_a(data);
-                    self.register_x = self.register_a;
-                }
+            /* BMI */
+            0x30 => {
+                self.branch(self.status.contains(CpuFlags::NEGATIVE));
+            },
 
-                /* SAX */
-                0x87 | 0x97 | 0x8f | 0x83 => {
-                    let data = self.register_a & self.register_x;
-                    let addr = self.get_operand_address(&opcode.mode);
-                    self.mem_write(addr, data);
-                }
+            /* BNE */
+            0xd0 => {
+                self.branch(!self.status.contains(CpuFlags::ZERO));
+            },
 
-                /* LXA */
-                0xab => {
-                    self.lda(&opcode.mode);
-                    self.tax();
-                }
+            /* BPL */
+            0x10 => {
+                self.branch(!self.status.contains(CpuFlags::NEGATIVE));
+            },
 
-                /* XAA */
-                0x8b => {
-                    self.register_a = self.register_x;
-                    self.update_zero_and_negative_flags(self.register_a);
-                    let addr = self.get_operand_address(&opcode.mode);
-                    let data = self.mem_read(addr);
-                    self.and_with_register_a(data);
-                }
+            /* BRK */
+            0x00 => return false,
 
-                /* LAS */
-                0xbb => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    let mut data = self.mem_read(addr);
-                    data = data & self.stack_pointer;
-                    self.register_a = data;
-                    self.register_x = data;
-                    self.stack_pointer = data;
-                    self.update_zero_and_negative_flags(data);
-                }
+            /* BVC */
+            0x50 => {
+                self.branch(!self.status.contains(CpuFlags::OVERFLOW));
+            },
+
+            /* BVS */
+            0x70 => {
+                self.branch(self.status.contains(CpuFlags::OVERFLOW));
+            },
+
+            /* CLC */
+            0x18 => self.clc(),
+
+            /* CLD */
+            0xd8 => self.cld(),
+
+            /* CLI */
+            0x58 => self.cli(),
+
+            /* CLV */
+            0xb8 => self.clv(),
+
+            /* CMP */
+            0xc9 |  0xc5 |  0xd5 |  0xcd |  0xdd |  0xd9 |  0xc1 |  0xd1 => {
+                self.compare(&opcode.mode, self.register_a);
+            },
+
+            /* CPX */
+            0xe0 |  0xe4 |  0xec => self.compare(&opcode.mode, self.register_x),
+
+            /* CPY */
+            0xc0 |  0xc4 |  0xcc => {
+                self.compare(&opcode.mode, self.register_y);
+            },
+
+            /* DEC */
+            0xc6 |  0xd6 |  0xce |  0xde => {
+                self.dec(&opcode.mode);
+            },
+
+            /* DEX */
+            0xca => {
+                self.dex();
+            },
+
+            /* DEY */
+            0x88 => {
+                self.dey();
+            },
+
+            /* EOR */
+            0x49 |  0x45 |  0x55 |  0x4d |  0x5d |  0x59 |  0x41 |  0x51 => {
+                self.eor(&opcode.mode);
+            },
+
+            /* INC */
+            0xe6 |  0xf6 |  0xee |  0xfe => {
+                self.inc(&opcode.mode);
+            },
+
+            /* INX */
+            0xe8 => self.inx(),
+
+            /* INY */
+            0xc8 => self.iny(),
+
+            /* JMP Absolute */
+            0x4c => {
+                let mem_address = self.mem_read_u16(self.program_counter);
+                self.jump_to(mem_address);
+            },
+
+            /* JMP Indirect */
+            0x6c => self.jmp(&opcode.mode),
+
+            /* JSR */
+            0x20 => self.jsr(),
+
+            /* LDA */
+            0xa9 |  0xa5 |  0xb5 |  0xad |  0xbd |  0xb9 |  0xa1 |  0xb1 => {
+                self.lda(&opcode.mode);
+            },
 
-                /* TAS */
-                0x9b => {
-                    let data = self.register_a & self.register_x;
-                    self.stack_pointer = data;
-                    let mem_address =
-                        self.mem_read_u16(self.program_counter) + self.register_y as u16;
+            /* LDX */
+            0xa2 |  0xa6 |  0xb6 |  0xae |  0xbe => {
+                self.ldx(&opcode.mode);
+            },
+
+            /* LDY */
+            0xa0 |  0xa4 |  0xb4 |  0xac |  0xbc => {
+                self.ldy(&opcode.mode);
+            },
+
+            /* LSR */ 0x4a => self.lsr_accumulator(),
+
+            /* LSR */
+            0x46 |  0x56 |  0x4e |  0x5e => {
+                self.lsr(&opcode.mode);
+            },
+            /* ORA */
+            0x09 |  0x05 |  0x15 |  0x0d |  0x1d |  0x19 |  0x01 |  0x11 => {
+                self.ora(&opcode.mode);
+            },
+            /* PHA */
+            0x48 => self.pha(),
+
+            /* PHP */
+            0x08 => {
+                self.php();
+            },
+
+            /* PLA */
+            0x68 => {
+                self.pla();
+            },
+
+            /* PLP */
+            0x28 => {
+                self.plp();
+            },
+
+            /*ROL*/ 0x2a => self.rol_accumulator(),
+
+            /* ROL */
+            0x26 |   0x36 |   0x2e |   0x3e => {
+                self.rol(&opcode.mode);
+            },
+
+            /* ROR */ 0x6a => self.ror_accumulator(),
+
+            /* ROR */
+            0x66 |   0x76 |   0x6e |   0x7e => {
+                self.ror(&opcode.mode);
+            },
+
+            /* RTI */
+            0x40 => {
+                self.rti();
+            },
+
+            /* RTS */
+            0x60 => {
+                self.rts()
+            },
+
+            /* SBC */
+            0xe9 |   0xe5 |   0xf5 |   0xed |   0xfd |   0xf9 |   0xe1 |   0xf1 => {
+                self.sbc(&opcode.mode);
+            },
+
+            /* SEC */
+            0x38 => self.sec(),
+
+            /* SED */
+            0xf8 => self.sed(),
+
+            /* SEI */
+            0x78 => self.sei(),
+
+            /* STA */
+            0x85 |   0x95 |   0x8d |   0x9d |   0x99 |   0x81 |   0x91 => {
+                self.sta(&opcode.mode);
+            },
+
+            /* STX */
+            0x86 |   0x96 |   0x8e => {
+                self.stx(&opcode.mode)
+            },
+
+            /* STY */
+            0x84 |   0x94 |   0x8c => {
+                self.sty(&opcode.mode)
+            },
+
+            /* TAX */
+            0xaa => self.tax(),
+
+            /* TAY */
+            0xa8 => {
+                self.tay()
+            },
+
+            /* TSX */
+            0xba => {
+                self.tsx()
+            },
+
+            /* TXA */
+            0x8a => {
+                self.txa()
+            },
+
+            /* TXS */
+            0x9a => {
+                self.txs()
+            },
+
+            /* TYA */
+            0x98 => {
+                self.tya()
+            },
+
+            /* NOP */
+            0xea => {
+                // do nothing
+            },
+
+            ////// UNOFFICIAL OPCODES
+
+            /* DCP */
+            0xc7 | 0xd7 | 0xCF | 0xdF | 0xdb | 0xd3 | 0xc3 => {
+                self.dcp(&opcode.mode);
+            }
+
+            /* RLA */
+            0x27 | 0x37 | 0x2F | 0x3F | 0x3b | 0x33 | 0x23 => {
+                let data = self.rol(&opcode.mode);
+                self.and_with_register_a(data);
+            }
+
+            /* SLO */ 
+            0x07 | 0x17 | 0x0F | 0x1f | 0x1b | 0x03 | 0x13 => {
+                let data = self.asl(&opcode.mode);
+                self.or_with_register_a(data);
+            }
 
-                    let data = ((mem_address >> 8) as u8 + 1) & self.stack_pointer;
-                    self.mem_write(mem_address, data)
+            /* SRE */ 
+            0x47 | 0x57 | 0x4F | 0x5f | 0x5b | 0x43 | 0x53 => {
+                let data = self.lsr(&opcode.mode);
+                self.xor_with_register_a(data);
+            }
+
+            /* SKB */
+            0x80 | 0x82 | 0x89 | 0xc2 | 0xe2 => {
+                /* 2 byte NOP (immediate ) */
+                
+            }
+
+            /* AXS */
+            0xCB => {
+                self.asx(&opcode.mode);
+            }
+
+            /* ARR */
+            0x6B => {
+                self.arr(&opcode.mode);
+            }
+
+            /* unofficial SBC */
+            0xeb => {
+                let (addr, _) = self.get_operand_address(&opcode.mode);
+                let data = self.mem_read(addr);
+                self.sub_from_register_a(data);
+            }
+
+            /* ANC */
+            0x0b | 0x2b => {
+                let (addr, _) = self.get_operand_address(&opcode.mode);
+                let data = self.mem_read(addr);
+                self.and_with_register_a(data);
+                if self.status.contains(CpuFlags::NEGATIVE) {
+                    self.status.insert(CpuFlags::CARRY);
+                } else {
+                    self.status.remove(CpuFlags::CARRY);
                 }
+            }
+
+            /* ALR */
+            0x4b => {
+                let (addr, _) = self.get_operand_address(&opcode.mode);
+                let data = self.mem_read(addr);
+                self.and_with_register_a(data);
+                self.lsr_accumulator();
+            }
+
 
-                /* AHX  Indirect Y */
-                0x93 => {
-                    let pos: u8 = self.mem_read(self.program_counter);
-                    let mem_address = self.mem_read_u16(pos as u16) + self.register_y as u16;
-                    let data = self.register_a & self.register_x & (mem_address >> 8) as u8;
-                    self.mem_write(mem_address, data)
+            /* NOP read */
+            0x04 | 0x44 | 0x64 | 0x14 | 0x34 | 0x54 | 0x74 | 0xd4 | 0xf4 | 0x0c | 0x1c
+            | 0x3c | 0x5c | 0x7c | 0xdc | 0xfc => {
+                /* read and then do nothing? i guess */
+                let (addr, page_cross) = self.get_operand_address(&opcode.mode);
+                let _data = self.mem_read(addr);
+                if page_cross {
+                    self.tick(1);
                 }
+            }
+
+            /* RRA */
+            0x67 | 0x77 | 0x6f | 0x7f | 0x7b | 0x63 | 0x73 => {
+                let data = self.ror(&opcode.mode);
+                self.add_to_register_a(data);
+            }
 
-                /* AHX Absolute Y*/
-                0x9f => {
-                    let mem_address =
-                        self.mem_read_u16(self.program_counter) + self.register_y as u16;
+            /* ISB */
+            0xe7 | 0xf7 | 0xef | 0xff | 0xfb | 0xe3 | 0xf3 => {
+                let data = self.inc(&opcode.mode);
+                self.sub_from_register_a(data);
+            }
 
-                    let data = self.register_a & self.register_x & (mem_address >> 8) as u8;
-                    self.mem_write(mem_address, data)
+            /* KIL */
+            0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xb2 | 0xd2
+            | 0xf2 => {
+                if self.jam_behavior == JamBehavior::Jam {
+                    // the CPU stops fetching and sits on the opcode
+                    let pc = self.program_counter.wrapping_sub(1);
+                    self.program_counter = pc;
+                    self.halted = Some(RunOutcome::Jammed { opcode: code, pc });
+                    return false;
                 }
+            }
+
+            /* NOPs */
+            0x1a | 0x3a | 0x5a | 0x7a | 0xda | 0xfa => { /* do nothing */ }
+            // sure are a lot of unofficial opcodes that are useless
 
-                /* SHX */
-                0x9e => {
-                    let mem_address =
-                        self.mem_read_u16(self.program_counter) + self.register_y as u16;
-                    let data = self.register_x & ((mem_address >> 8) as u8 + 1);
-                    self.mem_write(mem_address, data)
+            /* LAX */
+            0xa7 | 0xb7 | 0xaf | 0xbf | 0xa3 | 0xb3 => {
+                let (addr, page_cross) = self.get_operand_address(&opcode.mode);
+                let data = self.mem_read(addr);
+                if page_cross {
+                    self.tick(1);
                 }
+                self.set_register_a(data);
+                self.register_x = self.register_a;
+            }
+
+            /* SAX */
+            0x87 | 0x97 | 0x8f | 0x83 => {
+                let data = self.register_a & self.register_x;
+                let addr = self.get_write_operand_address(&opcode.mode);
+                self.mem_write(addr, data);
+            }
+
+            /* LXA */
+            0xab => {
+                self.lda(&opcode.mode);
+                self.tax();
+            }
+
+            /* XAA */
+            0x8b => {
+                self.register_a = self.register_x;
+                self.update_zero_and_negative_flags(self.register_a);
+                let (addr, _) = self.get_operand_address(&opcode.mode);
+                let data = self.mem_read(addr);
+                self.and_with_register_a(data);
+            }
 
-                /* SHY */
-                0x9c => {
-                    let mem_address =
-                        self.mem_read_u16(self.program_counter) + self.register_x as u16;
-                    let data = self.register_y & ((mem_address >> 8) as u8 + 1);
-                    self.mem_write(mem_address, data)
+            /* LAS */
+            0xbb => {
+                let (addr, page_cross) = self.get_operand_address(&opcode.mode);
+                let mut data = self.mem_read(addr);
+                if page_cross {
+                    self.tick(1);
                 }
+                data = data & self.stack_pointer;
+                self.register_a = data;
+                self.register_x = data;
+                self.stack_pointer = data;
+                self.update_zero_and_negative_flags(data);
+            }
+
+            /* TAS */
+            0x9b => {
+                let data = self.register_a & self.register_x;
+                self.stack_pointer = data;
+                let mem_address =
+                    self.mem_read_u16(self.program_counter).wrapping_add(self.register_y as u16);
+
+                let data = ((mem_address >> 8) as u8).wrapping_add(1) & self.stack_pointer;
+                self.mem_write(mem_address, data)
+            }
+
+            /* AHX  Indirect Y */
+            0x93 => {
+                let pos: u8 = self.mem_read(self.program_counter);
+                let mem_address = self.mem_read_u16(pos as u16).wrapping_add(self.register_y as u16);
+                let data = self.register_a & self.register_x & (mem_address >> 8) as u8;
+                self.mem_write(mem_address, data)
+            }
+
+            /* AHX Absolute Y*/
+            0x9f => {
+                let mem_address =
+                    self.mem_read_u16(self.program_counter).wrapping_add(self.register_y as u16);
 
-                _ => todo!()
+                let data = self.register_a & self.register_x & (mem_address >> 8) as u8;
+                self.mem_write(mem_address, data)
+            }
+
+            /* SHX */
+            0x9e => {
+                let mem_address =
+                    self.mem_read_u16(self.program_counter).wrapping_add(self.register_y as u16);
+                let data = self.register_x & ((mem_address >> 8) as u8).wrapping_add(1);
+                self.mem_write(mem_address, data)
+            }
+
+            /* SHY */
+            0x9c => {
+                let mem_address =
+                    self.mem_read_u16(self.program_counter).wrapping_add(self.register_x as u16);
+                let data = self.register_y & ((mem_address >> 8) as u8).wrapping_add(1);
+                self.mem_write(mem_address, data)
             }
-            if program_state == self.program_counter {
-                self.program_counter += (opcode.length - 1) as u16;
-            }    ///// REPEAT
         }
+        self.tick(opcode.cycles);
+
+        // step over the operand bytes, unless the instruction went somewhere else
+        if !self.jumped {
+            self.program_counter = self.program_counter.wrapping_add((opcode.length - 1) as u16);
+        }
+
+        // CLI, SEI and PLP update I after the poll, so it still sees the old flag
+        self.irq_inhibit = match code {
+            0x58 | 0x78 | 0x28 => irq_inhibit,
+            _ => self.status.contains(CpuFlags::INTERRUPT_DISABLE),
+        };
+        true
+    }
+}
+
+impl CPU<Bus> {
+    /// Hot-swaps the cartridge and resets the CPU so it starts executing from the new cartridge's reset vector.
+    ///
+    /// # Arguments
+    ///
+    /// * `rom` - The cartridge to insert.
+    pub fn insert_cartridge(&mut self, rom: Rom) {
+        self.bus.insert_cartridge(rom);
+        self.reset();
+    }
+
+    /// Powers the whole console off and on: the registers go back to their construction values,
+    /// every device on the bus is rebuilt, then the CPU starts from the reset vector.
+    /// Unlike `reset`, nothing survives from before.
+    pub fn power_cycle(&mut self) {
+        self.register_a = 0;
+        self.register_x = 0;
+        self.register_y = 0;
+        self.status = CpuFlags::from_bits_truncate(0b100100);
+        self.program_counter = 0;
+        self.stack_pointer = STACK_RESET;
+        self.cycles = 0;
+        self.irq_inhibit = true;
+        self.memory = [0; 0xFFFF];
+        self.bus.power_cycle();
+        self.reset();
+    }
+
+    /// Runs until BRK or a jam like `run_with_callback`, handing `sink` a nestest style trace line
+    /// for every instruction whose address falls in `trace_filter`, or for all of them without a filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - Receives each trace line, before its instruction executes.
+    pub fn run_with_trace<F>(&mut self, mut sink: F) -> RunOutcome
+    where
+        F: FnMut(String),
+    {
+        self.run_with_callback(|cpu| {
+            let traced = cpu.trace_filter.as_ref().is_none_or(|range| range.contains(&cpu.program_counter));
+            if traced {
+                sink(trace::trace(cpu));
+            }
+        })
     }
 }
 
 #[cfg(test)]
 mod test {
-    // use super::*;
-    // use crate::cartridge::test;
+    use super::*;
+    use crate::cartridge::test;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Builds a CPU with `program` placed in RAM at 0x0600 and the program counter pointing at it.
+    fn cpu_with_program(program: &[u8]) -> CPU {
+        let mut bus = Bus::new(test::test_rom());
+        for (offset, byte) in program.iter().enumerate() {
+            bus.mem_write(0x0600 + offset as u16, *byte);
+        }
+        let mut cpu = CPU::new(bus);
+        cpu.program_counter = 0x0600;
+        cpu
+    }
+
+    #[test]
+    fn test_page_cross_penalty_for_every_indexed_opcode() {
+        // only read instructions pay for the page cross, stores and read-modify-write ops always take the fixed count
+        let reads = ["ADC", "AND", "CMP", "EOR", "LDA", "LDX", "LDY", "ORA", "SBC", "*LAX", "*NOP", "*LAS"];
+
+        for op in opcode::CPU_OP_CODES.iter() {
+            let program = match op.mode {
+                // $02FF + 1 lands on $0300
+                AddressingMode::Absolute_X | AddressingMode::Absolute_Y => vec![op.opcode, 0xff, 0x02, 0x00],
+                // ($10) = $02FF, + 1 lands on $0300
+                AddressingMode::Indirect_Y => vec![op.opcode, 0x10, 0x00],
+                _ => continue,
+            };
+
+            let mut cpu = cpu_with_program(&program);
+            cpu.mem_write_u16(0x10, 0x02ff);
+            cpu.register_x = 1;
+            cpu.register_y = 1;
+            cpu.run();
+
+            let expected = if reads.contains(&op.abbreviation) { op.cycles + 1 } else { op.cycles };
+            assert_eq!(
+                cpu.cycles,
+                expected as usize,
+                "{} ({:02x}) in {:?} took the wrong number of cycles on a page cross",
+                op.abbreviation,
+                op.opcode,
+                op.mode
+            );
+        }
+    }
+
+    #[test]
+    fn test_unstable_store_opcode_cycles() {
+        // indexed stores always take the extra cycle, whether or not a page is crossed
+        let cases: [(&[u8], usize); 5] = [
+            (&[0x9b, 0x00, 0x02, 0x00], 5), // TAS $0200,Y
+            (&[0x93, 0x10, 0x00], 6),       // AHX ($10),Y
+            (&[0x9f, 0x00, 0x02, 0x00], 5), // AHX $0200,Y
+            (&[0x9e, 0x00, 0x02, 0x00], 5), // SHX $0200,Y
+            (&[0x9c, 0x00, 0x02, 0x00], 5), // SHY $0200,X
+        ];
+
+        for (program, cycles) in cases {
+            let mut cpu = cpu_with_program(program);
+            cpu.mem_write_u16(0x10, 0x0200);
+            cpu.register_x = 1;
+            cpu.register_y = 1;
+            cpu.run();
+            assert_eq!(cpu.cycles, cycles, "opcode {:02x}", program[0]);
+        }
+    }
+
+    #[test]
+    fn test_run_fast_matches_run() {
+        // LDY #$0A; LDX #$00; loop: INX; STX $0200; BNE loop; DEY; BNE loop; BRK
+        let program = [0xa0, 0x0a, 0xa2, 0x00, 0xe8, 0x8e, 0x00, 0x02, 0xd0, 0xfa, 0x88, 0xd0, 0xf7, 0x00];
+
+        let mut slow = cpu_with_program(&program);
+        slow.run();
+        let mut fast = cpu_with_program(&program);
+        fast.run_fast();
+
+        assert_eq!(fast.save_state(), slow.save_state());
+        assert_eq!(fast.bus.ram(), slow.bus.ram());
+        assert_eq!(fast.program_counter, 0x060e);
+    }
+
+    #[test]
+    fn test_unofficial_nops_consume_operands_only() {
+        for op in opcode::CPU_OP_CODES.iter().filter(|op| op.abbreviation == "*NOP") {
+            let mut program = vec![op.opcode];
+            program.extend_from_slice(&[0x10, 0x02][..op.length as usize - 1]);
+            program.push(0x00);
+
+            let mut cpu = cpu_with_program(&program);
+            cpu.register_a = 0x11;
+            cpu.register_x = 0x22;
+            cpu.register_y = 0x33;
+            cpu.status = CpuFlags::from_bits_truncate(0b1100_0101);
+            cpu.run();
+
+            // BRK right after the operand bytes was reached
+            assert_eq!(cpu.program_counter, 0x0600 + op.length as u16 + 1, "{:02x}", op.opcode);
+            assert_eq!((cpu.register_a, cpu.register_x, cpu.register_y), (0x11, 0x22, 0x33), "{:02x}", op.opcode);
+            assert_eq!(cpu.status.bits(), 0b1100_0101, "{:02x}", op.opcode);
+            assert_eq!(cpu.stack_pointer, STACK_RESET, "{:02x}", op.opcode);
+        }
+    }
+
+    #[test]
+    fn test_kil_jams_cpu() {
+        // INX; KIL; INX; BRK
+        let mut cpu = cpu_with_program(&[0xe8, 0x02, 0xe8, 0x00]);
+        assert_eq!(cpu.run(), RunOutcome::Jammed { opcode: 0x02, pc: 0x0601 });
+        assert!(cpu.is_jammed());
+        assert_eq!(cpu.register_x, 1);
+        assert_eq!(cpu.program_counter, 0x0601);
+
+        // stays stuck until reset
+        assert!(!cpu.step());
+        assert_eq!(cpu.register_x, 1);
+        cpu.reset();
+        assert!(!cpu.is_jammed());
+    }
+
+    #[test]
+    fn test_unofficial_opcodes_can_be_disabled() {
+        // LAX $10; BRK
+        let program = [0xa7, 0x10, 0x00];
+
+        let mut cpu = cpu_with_program(&program);
+        cpu.mem_write(0x10, 0x5a);
+        cpu.allow_unofficial = false;
+        assert_eq!(
+            cpu.run(),
+            RunOutcome::Error(CpuError::UnofficialOpcodeDisabled { opcode: 0xa7, pc: 0x0600 })
+        );
+        assert_eq!((cpu.register_a, cpu.register_x), (0, 0));
+        assert_eq!(cpu.cycles, 0);
+        assert!(!cpu.is_jammed());
+
+        let mut cpu = cpu_with_program(&program);
+        cpu.mem_write(0x10, 0x5a);
+        assert_eq!(cpu.run(), RunOutcome::Break);
+        assert_eq!((cpu.register_a, cpu.register_x), (0x5a, 0x5a));
+    }
+
+    #[test]
+    fn test_trap_on_ram_execution() {
+        let mut memory = FlatMemory::new();
+        // LDA #$01; JMP $0300, which holds LDA #$02; BRK
+        memory.load(0x8000, &[0xa9, 0x01, 0x4c, 0x00, 0x03]);
+        memory.mem_write(0x0300, 0xa9);
+        memory.mem_write(0x0301, 0x02);
+
+        let mut cpu = CPU::new(memory);
+        cpu.reset();
+        cpu.trap_on_ram_execution = true;
+        assert_eq!(cpu.run(), RunOutcome::Error(CpuError::ExecutingData { pc: 0x0300 }));
+        assert_eq!(cpu.register_a, 0x01);
+        assert!(!cpu.step());
+
+        // off by default
+        let mut cpu = CPU::new(cpu.bus);
+        cpu.reset();
+        assert_eq!(cpu.run(), RunOutcome::Break);
+        assert_eq!(cpu.register_a, 0x02);
+    }
+
+    #[test]
+    fn test_runs_on_flat_memory() {
+        let mut memory = FlatMemory::new();
+        memory.load(0x0200, &[
+            0xa2, 0x05, // LDX #$05
+            0x18, // loop: CLC
+            0xa5, 0x10, 0x69, 0x03, 0x85, 0x10, // LDA $10; ADC #$03; STA $10
+            0xca, 0xd0, 0xf6, // DEX; BNE loop
+            0x8d, 0x00, 0x40, // STA $4000, plain RAM here
+            0x00, // BRK
+        ]);
+        let mut cpu: CPU<FlatMemory> = CPU::new(memory);
+        cpu.reset();
+        assert_eq!(cpu.program_counter, 0x0200);
+
+        assert_eq!(cpu.run(), RunOutcome::Break);
+        assert_eq!(cpu.mem_read(0x10), 15);
+        assert_eq!(cpu.bus.peek(0x4000), 15);
+        // 2 + 5 * (2 + 3 + 2 + 3 + 2 + 3) - 1 + 4, the last BNE isn't taken
+        assert_eq!(cpu.cycles, 80);
+    }
+
+    /// Flat memory that logs every side-effecting read.
+    struct RecordingMemory {
+        memory: FlatMemory,
+        reads: Vec<u16>,
+    }
+
+    impl Memory for RecordingMemory {
+        fn mem_read(&mut self, address: u16) -> u8 {
+            self.reads.push(address);
+            self.memory.mem_read(address)
+        }
+
+        fn peek(&self, address: u16) -> u8 {
+            self.memory.peek(address)
+        }
+
+        fn mem_write(&mut self, address: u16, data: u8) {
+            self.memory.mem_write(address, data)
+        }
+    }
+
+    #[test]
+    fn test_inc_dec_on_ppu_register_write_twice() {
+        // INC $2007; DEC $2007
+        let mut cpu = cpu_with_program(&[0xee, 0x07, 0x20, 0xce, 0x07, 0x20]);
+        let writes = Rc::new(RefCell::new(vec![]));
+        let log = writes.clone();
+        cpu.bus.on_write(0x2007, Box::new(move |data| log.borrow_mut().push(data)));
+        cpu.mem_write(0x2006, 0x24);
+        cpu.mem_write(0x2006, 0x00);
+
+        // the read returns the empty read buffer, then the old value and the result are both written
+        assert!(cpu.step());
+        assert_eq!(*writes.borrow(), vec![0x00, 0x01]);
+        assert!(cpu.step());
+        assert_eq!(*writes.borrow(), vec![0x00, 0x01, 0x00, 0xff]);
+        // each of the reads and writes stepped the VRAM address
+        let ppu = &cpu.bus.ppu;
+        assert_eq!(ppu.vram[ppu.mirror_vram_addr(0x2402) as usize], 0x01);
+        assert_eq!(ppu.vram[ppu.mirror_vram_addr(0x2405) as usize], 0xff);
+    }
+
+    #[test]
+    fn test_page_crossing_read_does_dummy_read() {
+        let mut memory = FlatMemory::new();
+        memory.load(0x0200, &[
+            0xa2, 0x01, // LDX #$01
+            0xbd, 0xff, 0x12, // LDA $12FF,X
+            0xbd, 0x00, 0x12, // LDA $1200,X
+            0x00, // BRK
+        ]);
+        memory.mem_write(0x1300, 0x42);
+        let mut cpu = CPU::new(RecordingMemory { memory, reads: vec![] });
+        cpu.reset();
+
+        cpu.step();
+        cpu.bus.reads.clear();
+        cpu.step();
+        // opcode, operand, dummy read at $1200, real read at $1300
+        assert_eq!(cpu.bus.reads, vec![0x0202, 0x0203, 0x0204, 0x1200, 0x1300]);
+        assert_eq!(cpu.register_a, 0x42);
+
+        cpu.bus.reads.clear();
+        cpu.step();
+        assert_eq!(cpu.bus.reads, vec![0x0205, 0x0206, 0x0207, 0x1201]);
+    }
+
+    #[test]
+    fn test_indexed_store_always_does_dummy_read() {
+        let mut memory = FlatMemory::new();
+        memory.load(0x0200, &[
+            0xa2, 0x01, // LDX #$01
+            0x9d, 0x00, 0x12, // STA $1200,X
+            0x9d, 0xff, 0x12, // STA $12FF,X
+            0xfe, 0x00, 0x12, // INC $1200,X
+            0x00, // BRK
+        ]);
+        let mut cpu = CPU::new(RecordingMemory { memory, reads: vec![] });
+        cpu.reset();
+        cpu.step();
+
+        // no page crossed, the dummy read still happens at the (already right) address
+        cpu.bus.reads.clear();
+        cpu.step();
+        assert_eq!(cpu.bus.reads, vec![0x0202, 0x0203, 0x0204, 0x1201]);
+
+        cpu.bus.reads.clear();
+        cpu.step();
+        assert_eq!(cpu.bus.reads, vec![0x0205, 0x0206, 0x0207, 0x1200]);
+
+        // read-modify-write: dummy read, then the real one
+        cpu.bus.reads.clear();
+        cpu.step();
+        assert_eq!(cpu.bus.reads, vec![0x0208, 0x0209, 0x020a, 0x1201, 0x1201]);
+    }
+
+    #[test]
+    fn test_every_opcode_executes() {
+        // none of them may reach the decoder's todo!()
+        for code in 0..=255u8 {
+            let mut memory = FlatMemory::new();
+            memory.load(0x0600, &[code, 0x10, 0x02]);
+            let mut cpu = CPU::new(memory);
+            cpu.reset();
+            cpu.step();
+            assert_eq!(cpu.instructions_executed(), 1, "{:02X} wasn't executed", code);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "accumulator mode")]
+    fn test_accumulator_mode_has_no_operand_address() {
+        let mut cpu = cpu_with_program(&[0x0a]);
+        cpu.get_operand_address(&AddressingMode::Accumulator);
+    }
+
+    #[test]
+    fn test_eval() {
+        let state = eval(vec![0xa9, 0x42, 0x00]);
+        assert_eq!(state.register_a, 0x42);
+        assert_eq!(state.program_counter, 0x0603);
+
+        // an endless loop stops at the cycle cap
+        let state = eval(vec![0x4c, 0x00, 0x06]);
+        assert_eq!(state.program_counter, 0x0600);
+        assert!(state.cycles >= EVAL_MAX_CYCLES);
+    }
+
+    #[test]
+    fn test_load_asm() {
+        let mut cpu = CPU::new(Bus::new(test::test_rom()));
+        assert_eq!(cpu.load_asm("LDA #$01\nSTA $10\nBRK"), 5);
+        assert_eq!(cpu.run(), RunOutcome::Break);
+        assert_eq!(cpu.mem_read(0x10), 0x01);
+    }
+
+    #[test]
+    fn test_step_over() {
+        let mut cpu = CPU::new(Bus::new(test::test_rom()));
+        cpu.load_asm(
+            "       JSR double
+                    LDY #$01
+                    BRK
+            double: ASL A
+                    JSR inner
+                    RTS
+            inner:  LDX #$07
+                    RTS",
+        );
+        cpu.register_a = 0x21;
+
+        assert_eq!(cpu.step_over(), StepResult::Completed);
+        assert_eq!(cpu.program_counter, 0x0603);
+        assert_eq!(cpu.register_a, 0x42);
+        assert_eq!(cpu.register_x, 0x07);
+        assert_eq!(cpu.stack_pointer, STACK_RESET);
+
+        // anything else is a single step
+        assert_eq!(cpu.step_over(), StepResult::Completed);
+        assert_eq!((cpu.program_counter, cpu.register_y), (0x0605, 0x01));
+        assert_eq!(cpu.step_over(), StepResult::Stopped(RunOutcome::Break));
+    }
+
+    #[test]
+    fn test_jmp_to_own_operand_is_not_advanced() {
+        // JMP $0601 lands on its own operand: $01 is ORA ($06,X)
+        let mut cpu = cpu_with_program(&[0x4c, 0x01, 0x06, 0x00]);
+        assert!(cpu.step());
+        assert_eq!(cpu.program_counter, 0x0601);
+
+        // JMP ($0610) -> $0601
+        let mut cpu = cpu_with_program(&[0x6c, 0x10, 0x06, 0x00]);
+        cpu.mem_write(0x0610, 0x01);
+        cpu.mem_write(0x0611, 0x06);
+        assert!(cpu.step());
+        assert_eq!(cpu.program_counter, 0x0601);
+    }
+
+    #[test]
+    fn test_jmp_indirect_page_boundary_bug() {
+        // JMP ($06FF): low byte from $06FF, high byte from $0600, not $0700
+        let mut cpu = cpu_with_program(&[0x6c, 0xff, 0x06]);
+        cpu.mem_write(0x06ff, 0x80);
+        cpu.mem_write(0x0700, 0x50);
+        assert_eq!(cpu.get_absolute_address(&AddressingMode::Indirect, 0x0601), 0x6c80);
+        assert!(cpu.step());
+        assert_eq!(cpu.program_counter, 0x6c80);
+    }
+
+    #[test]
+    fn test_control_flow_to_post_fetch_pc_is_not_advanced() {
+        // BNE -1 branches to its own offset byte
+        let mut cpu = cpu_with_program(&[0xd0, 0xff, 0x00]);
+        cpu.status.remove(CpuFlags::ZERO);
+        assert!(cpu.step());
+        assert_eq!(cpu.program_counter, 0x0601);
+
+        // JSR $0601
+        let mut cpu = cpu_with_program(&[0x20, 0x01, 0x06, 0x00]);
+        assert!(cpu.step());
+        assert_eq!(cpu.program_counter, 0x0601);
+
+        // RTS to $0601, the pushed return address is $0600
+        let mut cpu = cpu_with_program(&[0x60, 0x00]);
+        cpu.stack_push_u16(0x0600);
+        assert!(cpu.step());
+        assert_eq!(cpu.program_counter, 0x0601);
+    }
+
+    #[test]
+    fn test_kil_as_nop() {
+        for op in opcode::CPU_OP_CODES.iter().filter(|op| op.abbreviation == "*KIL") {
+            let mut cpu = cpu_with_program(&[op.opcode, 0xe8, 0x00]);
+            cpu.jam_behavior = JamBehavior::Nop;
+            assert_eq!(cpu.run(), RunOutcome::Break, "{:02x}", op.opcode);
+            assert_eq!(cpu.register_x, 1, "{:02x}", op.opcode);
+            assert_eq!(cpu.cycles, 2 + 2, "{:02x}", op.opcode);
+        }
+    }
+
+    #[test]
+    fn test_jsr_rts_wrap_around_top_of_memory() {
+        // JSR $0600 at $FFFE, its high operand byte wraps around to $0000
+        let mut bus = Bus::new(test::test_rom_with_program(&[0x20, 0x00], 0xfffe));
+        bus.mem_write(0x0000, 0x06);
+        bus.mem_write(0x0001, 0x00); // BRK
+        bus.mem_write(0x0600, 0x60); // RTS
+        let mut cpu = CPU::new(bus);
+        cpu.program_counter = 0xfffe;
+
+        let mut pushed = None;
+        cpu.run_with_callback(|cpu| {
+            if cpu.program_counter == 0x0600 {
+                pushed = Some(cpu.mem_read_u16(STACK + cpu.stack_pointer as u16 + 1));
+            }
+        });
+
+        // the return address points at the last operand byte, $10000 - 1 wrapped to $0000
+        assert_eq!(pushed, Some(0x0000));
+        assert_eq!(cpu.program_counter, 0x0002);
+        assert_eq!(cpu.stack_pointer, STACK_RESET);
+    }
+
+    #[test]
+    fn test_step_with_writes() {
+        // LDA #$42; STA $10; JSR $0610; NOP
+        let mut cpu = cpu_with_program(&[0xa9, 0x42, 0x85, 0x10, 0x20, 0x10, 0x06, 0xea]);
+        cpu.mem_write(0x10, 0x07);
+
+        assert_eq!(cpu.step_with_writes(), (true, vec![]));
+        assert_eq!(cpu.step_with_writes(), (true, vec![MemoryWrite { address: 0x0010, old: 0x07, new: 0x42 }]));
+        // the return address goes on the stack high byte first
+        let (stepped, writes) = cpu.step_with_writes();
+        assert!(stepped);
+        let pushed: Vec<(u16, u8)> = writes.iter().map(|write| (write.address, write.new)).collect();
+        assert_eq!(pushed, vec![(0x01fd, 0x06), (0x01fc, 0x06)]);
+        // logging is off again for plain steps
+        cpu.mem_write(0x20, 1);
+        assert!(cpu.write_log.is_none());
+    }
+
+    #[test]
+    fn test_zero_page_x_wraps() {
+        // LDA $FF,X
+        let mut cpu = cpu_with_program(&[0xb5, 0xff, 0x00]);
+        cpu.register_x = 0x02;
+        cpu.mem_write(0x0001, 0x11);
+        cpu.mem_write(0x0101, 0x22);
+        assert_eq!(cpu.get_absolute_address(&AddressingMode::ZeroPage_X, 0x0601), 0x0001);
+        assert!(cpu.step());
+        assert_eq!(cpu.register_a, 0x11);
+    }
+
+    #[test]
+    fn test_zero_page_y_wraps() {
+        // STX $FF,Y
+        let mut cpu = cpu_with_program(&[0x96, 0xff, 0x00]);
+        cpu.register_x = 0x33;
+        cpu.register_y = 0x02;
+        assert_eq!(cpu.get_absolute_address(&AddressingMode::ZeroPage_Y, 0x0601), 0x0001);
+        assert!(cpu.step());
+        assert_eq!(cpu.mem_read(0x0001), 0x33);
+        assert_eq!(cpu.mem_read(0x0101), 0x00);
+    }
+
+    #[test]
+    fn test_resolve_operand_address_zero_page_x() {
+        // LDA $F0,X wraps within the zero page
+        let mut cpu = cpu_with_program(&[0xb5, 0xf0, 0x00]);
+        cpu.register_x = 0x20;
+        assert_eq!(cpu.resolve_operand_address(0xb5), Some(0x0010));
+        // nothing was executed
+        assert_eq!(cpu.program_counter, 0x0600);
+        assert_eq!(cpu.cycles, 0);
+    }
+
+    #[test]
+    fn test_resolve_operand_address_indirect_y() {
+        // LDA ($40),Y with the pointer at $40 -> $0210
+        let mut cpu = cpu_with_program(&[0xb1, 0x40, 0x00]);
+        cpu.mem_write(0x40, 0x10);
+        cpu.mem_write(0x41, 0x02);
+        cpu.register_y = 0x05;
+        assert_eq!(cpu.resolve_operand_address(0xb1), Some(0x0215));
+    }
+
+    #[test]
+    fn test_resolve_operand_address_implied_is_none() {
+        let cpu = cpu_with_program(&[0xe8, 0x0a, 0x00]);
+        assert_eq!(cpu.resolve_operand_address(0xe8), None); // INX
+        assert_eq!(cpu.resolve_operand_address(0x0a), None); // ASL A
+    }
+
+    #[test]
+    fn test_absolute_indexed_operand_wraps_past_top_of_memory() {
+        // LDA $0200,X at $FFFE, the high base byte is fetched from $0000
+        let mut bus = Bus::new(test::test_rom_with_program(&[0xbd, 0x00], 0xfffe));
+        bus.mem_write(0x0000, 0x02);
+        bus.mem_write(0x0001, 0x00); // BRK
+        bus.mem_write(0x0205, 0x42);
+        let mut cpu = CPU::new(bus);
+        cpu.program_counter = 0xfffe;
+        cpu.register_x = 0x05;
+        cpu.run();
+
+        assert_eq!(cpu.register_a, 0x42);
+        assert_eq!(cpu.program_counter, 0x0002);
+    }
+
+    #[test]
+    fn test_absolute_indexed_address_wraps_past_top_of_memory() {
+        // LDA $FFF0,Y with Y = $20 reads $0010
+        let mut cpu = cpu_with_program(&[0xb9, 0xf0, 0xff, 0x00]);
+        cpu.mem_write(0x0010, 0x37);
+        cpu.register_y = 0x20;
+        assert_eq!(cpu.resolve_operand_address(0xb9), Some(0x0010));
+        cpu.run();
+        assert_eq!(cpu.register_a, 0x37);
+    }
+
+    #[test]
+    fn test_instruction_hook_counts_adc() {
+        use std::{cell::Cell, rc::Rc};
+
+        // CLC; ADC #$01; ADC $10; INX; ADC #$02; BRK
+        let mut cpu = cpu_with_program(&[0x18, 0x69, 0x01, 0x65, 0x10, 0xe8, 0x69, 0x02, 0x00]);
+        let adc_count = Rc::new(Cell::new(0));
+        let counter = adc_count.clone();
+        cpu.set_instruction_hook(move |_, opcode| {
+            if opcode.abbreviation == "ADC" {
+                counter.set(counter.get() + 1);
+            }
+        });
+        cpu.run();
+
+        assert_eq!(adc_count.get(), 3);
+        assert_eq!(cpu.register_x, 1);
+    }
+
+    #[test]
+    fn test_run_to_stops_inside_loop() {
+        // LDX #$00; loop: INX; INY; CPX #$05; BNE loop; BRK
+        let mut cpu = cpu_with_program(&[0xa2, 0x00, 0xe8, 0xc8, 0xe0, 0x05, 0xd0, 0xfa, 0x00]);
+        // first arrival at INY
+        assert!(cpu.run_to(0x0603, 100));
+        assert_eq!((cpu.register_x, cpu.register_y), (1, 0));
+        // already there, nothing runs
+        assert!(cpu.run_to(0x0603, 100));
+        assert_eq!(cpu.register_y, 0);
+        // next pass through the loop body
+        cpu.step();
+        assert!(cpu.run_to(0x0603, 100));
+        assert_eq!((cpu.register_x, cpu.register_y), (2, 1));
+    }
+
+    #[test]
+    fn test_run_to_gives_up() {
+        // loop: JMP loop
+        let mut cpu = cpu_with_program(&[0x4c, 0x00, 0x06]);
+        assert!(!cpu.run_to(0x0700, 30));
+        assert!(cpu.cycles >= 30 && cpu.cycles < 33);
+        // BRK ends the run
+        let mut cpu = cpu_with_program(&[0xe8, 0x00]);
+        assert!(!cpu.run_to(0x0700, 1000));
+        assert_eq!(cpu.register_x, 1);
+    }
+
+    #[test]
+    fn test_instructions_executed_counts_loop_iterations() {
+        // LDX #$00; loop: INX; CPX #$03; BNE loop; BRK
+        let mut cpu = cpu_with_program(&[0xa2, 0x00, 0xe8, 0xe0, 0x03, 0xd0, 0xfb, 0x00]);
+        cpu.run();
+        // LDX + 3 x (INX, CPX, BNE) + BRK
+        assert_eq!(cpu.instructions_executed(), 1 + 3 * 3 + 1);
+
+        cpu.reset();
+        assert_eq!(cpu.instructions_executed(), 0);
+    }
+
+    #[test]
+    fn test_interrupt_vectors() {
+        let mut prg = vec![0; 0x8000];
+        prg[0x7ffa..].copy_from_slice(&[0x34, 0x92, 0x00, 0x80, 0x78, 0xc5]);
+        let cpu = CPU::new(Bus::new(test::test_rom_with_prg(prg)));
+        assert_eq!(cpu.nmi_vector(), 0x9234);
+        assert_eq!(cpu.reset_vector(), 0x8000);
+        assert_eq!(cpu.irq_vector(), 0xc578);
+    }
+
+    #[test]
+    fn test_bit_flag_matrix() {
+        // (accumulator, memory, zero, negative, overflow)
+        let cases = [
+            (0x01, 0x01, false, false, false),
+            (0x02, 0x01, true, false, false),
+            (0x80, 0x80, false, true, false),
+            (0x01, 0x80, true, true, false),
+            (0x40, 0x40, false, false, true),
+            (0x01, 0x40, true, false, true),
+            (0xc0, 0xc0, false, true, true),
+            (0x00, 0xc0, true, true, true),
+            (0x3f, 0xc1, false, true, true),
+            (0xff, 0x00, true, false, false),
+        ];
+        // BIT $10, BIT $0010
+        for program in [vec![0x24, 0x10, 0x00], vec![0x2c, 0x10, 0x00, 0x00]] {
+            for (accumulator, memory, zero, negative, overflow) in cases {
+                let mut cpu = cpu_with_program(&program);
+                cpu.mem_write(0x10, memory);
+                cpu.register_a = accumulator;
+                // start from the opposite flags so every one is actually written
+                cpu.status.set(CpuFlags::ZERO, !zero);
+                cpu.status.set(CpuFlags::NEGATIVE, !negative);
+                cpu.status.set(CpuFlags::OVERFLOW, !overflow);
+                cpu.run();
+
+                let case = format!("{:02x}: A {:02x} M {:02x}", program[0], accumulator, memory);
+                assert_eq!(cpu.status.contains(CpuFlags::ZERO), zero, "Z {}", case);
+                assert_eq!(cpu.status.contains(CpuFlags::NEGATIVE), negative, "N {}", case);
+                assert_eq!(cpu.status.contains(CpuFlags::OVERFLOW), overflow, "V {}", case);
+                // A and memory are left alone
+                assert_eq!(cpu.register_a, accumulator, "{}", case);
+                assert_eq!(cpu.mem_read(0x10), memory, "{}", case);
+            }
+        }
+    }
+
+    #[test]
+    fn test_compare_flag_matrix() {
+        // (register, operand, zero, carry, negative)
+        let cases = [
+            (0x40, 0x40, true, true, false),
+            (0x40, 0x41, false, false, true),
+            (0x41, 0x40, false, true, false),
+            (0x00, 0x00, true, true, false),
+            (0xff, 0xff, true, true, false),
+            (0x00, 0xff, false, false, false),
+            (0xff, 0x00, false, true, true),
+            (0xf0, 0x10, false, true, true),
+            (0x10, 0xf0, false, false, false),
+            (0x80, 0x00, false, true, true),
+            (0x00, 0x80, false, false, true),
+            (0x7f, 0x80, false, false, true),
+            (0x80, 0x7f, false, true, false),
+        ];
+        // CMP #, CPX #, CPY #
+        for opcode in [0xc9, 0xe0, 0xc0] {
+            for (register, operand, zero, carry, negative) in cases {
+                let mut cpu = cpu_with_program(&[opcode, operand, 0x00]);
+                match opcode {
+                    0xc9 => cpu.register_a = register,
+                    0xe0 => cpu.register_x = register,
+                    _ => cpu.register_y = register,
+                }
+                cpu.run();
+
+                let case = format!("{:02x}: {:02x} vs {:02x}", opcode, register, operand);
+                assert_eq!(cpu.status.contains(CpuFlags::ZERO), zero, "Z {}", case);
+                assert_eq!(cpu.status.contains(CpuFlags::CARRY), carry, "C {}", case);
+                assert_eq!(cpu.status.contains(CpuFlags::NEGATIVE), negative, "N {}", case);
+                // the register itself is left alone
+                let compared = match opcode {
+                    0xc9 => cpu.register_a,
+                    0xe0 => cpu.register_x,
+                    _ => cpu.register_y,
+                };
+                assert_eq!(compared, register, "{}", case);
+            }
+        }
+    }
+
+    #[test]
+    fn test_no_page_cross_penalty_within_page() {
+        // LDA $0200,X with X = 1 stays on page 2
+        let mut cpu = cpu_with_program(&[0xbd, 0x00, 0x02, 0x00]);
+        cpu.register_x = 1;
+        cpu.run();
+        assert_eq!(cpu.cycles, 4);
+    }
+
+    #[test]
+    fn test_branch_not_taken_cycles() {
+        // Z is set, so BNE falls through to the BRK
+        let mut cpu = cpu_with_program(&[0xd0, 0x02, 0x00]);
+        cpu.status.insert(CpuFlags::ZERO);
+        cpu.run();
+        assert_eq!(cpu.cycles, 2);
+        assert_eq!(cpu.program_counter, 0x0603);
+    }
+
+    #[test]
+    fn test_branch_taken_same_page_cycles() {
+        // BNE +2 skips the first BRK and lands on the second
+        let mut cpu = cpu_with_program(&[0xd0, 0x02, 0x00, 0x00, 0x00]);
+        cpu.status.remove(CpuFlags::ZERO);
+        cpu.run();
+        assert_eq!(cpu.cycles, 3);
+        assert_eq!(cpu.program_counter, 0x0605);
+    }
+
+    #[test]
+    fn test_branch_taken_cross_page_cycles() {
+        // BNE at $06FC, the next instruction is at $06FE and +4 lands on $0702
+        let mut cpu = cpu_with_program(&[]);
+        cpu.mem_write(0x06fc, 0xd0);
+        cpu.mem_write(0x06fd, 0x04);
+        cpu.program_counter = 0x06fc;
+        cpu.status.remove(CpuFlags::ZERO);
+        cpu.run();
+        assert_eq!(cpu.cycles, 4);
+        assert_eq!(cpu.program_counter, 0x0703);
+    }
+
+    #[test]
+    fn test_branch_taken_backwards_cross_page_cycles() {
+        // BNE at $0700, the next instruction is at $0702 and -4 lands on $06FE
+        let mut cpu = cpu_with_program(&[]);
+        cpu.mem_write(0x0700, 0xd0);
+        cpu.mem_write(0x0701, 0xfc);
+        cpu.program_counter = 0x0700;
+        cpu.status.remove(CpuFlags::ZERO);
+        cpu.run();
+        assert_eq!(cpu.cycles, 4);
+        assert_eq!(cpu.program_counter, 0x06ff);
+    }
+
+    #[test]
+    fn test_dmc_irq_enters_handler() {
+        // IRQ vector -> $9000, which holds BRK
+        let mut bus = Bus::new(test::test_rom_with_program(&[0x00, 0x90], 0xfffe));
+        let program = [
+            0xa9, 0x8f, 0x8d, 0x10, 0x40, // LDA #$8F; STA $4010 (IRQ on, fastest rate)
+            0xa9, 0x00, 0x8d, 0x13, 0x40, // LDA #$00; STA $4013 (1 byte sample)
+            0xa9, 0x10, 0x8d, 0x15, 0x40, // LDA #$10; STA $4015 (start DMC)
+            0x58, // CLI
+            0xea, 0x4c, 0x10, 0x06, // loop: NOP; JMP loop
+        ];
+        for (offset, byte) in program.iter().enumerate() {
+            bus.mem_write(0x0600 + offset as u16, *byte);
+        }
+        let mut cpu = CPU::new(bus);
+        cpu.program_counter = 0x0600;
+        cpu.run();
+
+        assert_eq!(cpu.program_counter, 0x9001);
+        assert!(cpu.status.contains(CpuFlags::INTERRUPT_DISABLE));
+        // pushed status has B clear and bit 5 set
+        let pushed_status = cpu.mem_read(STACK + STACK_RESET as u16 - 2);
+        assert_eq!(pushed_status & 0b0011_0000, 0b0010_0000);
+        // the sample fetch right after the $4015 write stalls the CPU for 4 cycles,
+        // and the NOP after CLI runs before the IRQ is taken
+        assert_eq!(cpu.cycles, 2 + 4 + 2 + 4 + 2 + 4 + 4 + 2 + 2 + 7);
+    }
+
+    fn pending_irq_cpu(program: &[u8]) -> CPU {
+        // IRQ vector -> $9000, which holds BRK
+        let mut bus = Bus::new(test::test_rom_with_program(&[0x00, 0x90], 0xfffe));
+        // 1 byte DMC sample with IRQ enabled, played out before the program starts
+        bus.mem_write(0x4010, 0x8f);
+        bus.mem_write(0x4013, 0x00);
+        bus.mem_write(0x4015, 0x10);
+        bus.tick(1);
+        assert!(bus.poll_irq());
+        for (offset, byte) in program.iter().enumerate() {
+            bus.mem_write(0x0600 + offset as u16, *byte);
+        }
+        let mut cpu = CPU::new(bus);
+        cpu.program_counter = 0x0600;
+        cpu
+    }
+
+    #[test]
+    fn test_irq_taken_one_instruction_after_cli() {
+        // CLI; INX; INY; BRK
+        let mut cpu = pending_irq_cpu(&[0x58, 0xe8, 0xc8, 0x00]);
+        assert!(cpu.step());
+        assert_eq!(cpu.program_counter, 0x0601);
+        assert!(cpu.step());
+        assert_eq!(cpu.register_x, 1);
+        // the IRQ is entered right after INX, before INY
+        assert_eq!(cpu.program_counter, 0x9000);
+        assert_eq!(cpu.register_y, 0);
+        assert_eq!(cpu.mem_read_u16(STACK + STACK_RESET as u16 - 1), 0x0602);
+    }
+
+    #[test]
+    fn test_reset_masks_irqs() {
+        // INX; INY; BRK
+        let mut cpu = pending_irq_cpu(&[0xe8, 0xc8, 0x00]);
+        cpu.reset();
+        assert!(cpu.status.contains(CpuFlags::INTERRUPT_DISABLE));
+        cpu.program_counter = 0x0600;
+        assert!(cpu.step());
+        assert!(cpu.step());
+        assert_eq!((cpu.register_x, cpu.register_y), (1, 1));
+        assert_eq!(cpu.program_counter, 0x0602);
+    }
+
+    #[test]
+    fn test_irq_still_taken_after_sei() {
+        // CLI; SEI; INX; BRK
+        let mut cpu = pending_irq_cpu(&[0x58, 0x78, 0xe8, 0x00]);
+        assert!(cpu.step());
+        assert!(cpu.step());
+        // SEI's own poll still sees I clear
+        assert_eq!(cpu.program_counter, 0x9000);
+        assert_eq!(cpu.register_x, 0);
+    }
+
+    #[test]
+    fn test_irq_keeps_decimal_flag() {
+        // SED; CLI; NOP; BRK
+        let mut cpu = pending_irq_cpu(&[0xf8, 0x58, 0xea, 0x00]);
+        assert!(cpu.step());
+        assert!(cpu.step());
+        assert!(cpu.step());
+        assert_eq!(cpu.program_counter, 0x9000);
+        assert!(cpu.status.contains(CpuFlags::INTERRUPT_DISABLE));
+        assert!(cpu.status.contains(CpuFlags::DECIMAL_MODE));
+    }
+
+    /// Flat memory with the NMI line pulled once.
+    struct NmiMemory {
+        memory: FlatMemory,
+        nmi: bool,
+    }
+
+    impl Memory for NmiMemory {
+        fn mem_read(&mut self, address: u16) -> u8 {
+            self.memory.mem_read(address)
+        }
+
+        fn peek(&self, address: u16) -> u8 {
+            self.memory.peek(address)
+        }
+
+        fn mem_write(&mut self, address: u16, data: u8) {
+            self.memory.mem_write(address, data)
+        }
+
+        fn poll_nmi(&mut self) -> bool {
+            std::mem::take(&mut self.nmi)
+        }
+    }
+
+    #[test]
+    fn test_nmi_keeps_decimal_flag() {
+        let mut memory = FlatMemory::new();
+        // SED
+        memory.load(0x0600, &[0xf8]);
+        memory.mem_write_u16(0xfffa, 0x0700);
+        let mut cpu = CPU::new(NmiMemory { memory, nmi: true });
+        cpu.reset();
+        assert!(cpu.step());
+        assert_eq!(cpu.program_counter, 0x0700);
+        assert!(cpu.status.contains(CpuFlags::INTERRUPT_DISABLE));
+        assert!(cpu.status.contains(CpuFlags::DECIMAL_MODE));
+        // and the pushed status had it too
+        assert!(cpu.stack_pop() & CpuFlags::DECIMAL_MODE.bits() != 0);
+    }
+
+    #[test]
+    fn test_insert_cartridge_restarts_from_new_reset_vector() {
+        // LDA #$11; STA $10; BRK
+        let first = test::test_rom_with_program(&[0xa9, 0x11, 0x85, 0x10, 0x00], 0x8000);
+        // LDX #$22; BRK
+        let second = test::test_rom_with_program(&[0xa2, 0x22, 0x00], 0xc123);
+
+        let mut cpu = CPU::new(Bus::new(first));
+        cpu.reset();
+        assert_eq!(cpu.program_counter, 0x8000);
+        cpu.run();
+        assert_eq!(cpu.register_a, 0x11);
+        assert_eq!(cpu.mem_read(0x10), 0x11);
+
+        cpu.insert_cartridge(second);
+        assert_eq!(cpu.program_counter, 0xc123);
+        // RAM does not survive the swap
+        assert_eq!(cpu.mem_read(0x10), 0x00);
+
+        cpu.run();
+        assert_eq!(cpu.register_x, 0x22);
+        assert_eq!(cpu.program_counter, 0xc126);
+    }
+
+    #[test]
+    fn test_arr_binary_mode() {
+        // ARR #$FF with A = $FF, C = 0 -> A = $7F, C = bit 6, V = bit 6 ^ bit 5
+        let mut cpu = cpu_with_program(&[0x6b, 0xff, 0x00]);
+        cpu.register_a = 0xff;
+        cpu.run();
+
+        assert_eq!(cpu.register_a, 0x7f);
+        assert!(cpu.status.contains(CpuFlags::CARRY));
+        assert!(!cpu.status.contains(CpuFlags::OVERFLOW));
+        assert!(!cpu.status.contains(CpuFlags::NEGATIVE));
+        assert!(!cpu.status.contains(CpuFlags::ZERO));
+
+        // ARR #$40 with A = $FF, C = 1 -> A = $A0, C clear, V set
+        let mut cpu = cpu_with_program(&[0x6b, 0x40, 0x00]);
+        cpu.register_a = 0xff;
+        cpu.status.insert(CpuFlags::CARRY);
+        cpu.run();
+
+        assert_eq!(cpu.register_a, 0xa0);
+        assert!(!cpu.status.contains(CpuFlags::CARRY));
+        assert!(cpu.status.contains(CpuFlags::OVERFLOW));
+        assert!(cpu.status.contains(CpuFlags::NEGATIVE));
+    }
+
+    #[test]
+    fn test_arr_decimal_mode_with_fixup() {
+        // ARR #$FF with A = $FF, C = 0, D = 1 -> rotate gives $7F, both nibbles are adjusted to $D5, C set
+        let mut cpu = cpu_with_program(&[0x6b, 0xff, 0x00]);
+        cpu.register_a = 0xff;
+        cpu.status.insert(CpuFlags::DECIMAL_MODE);
+        cpu.run();
+
+        assert_eq!(cpu.register_a, 0xd5);
+        assert!(cpu.status.contains(CpuFlags::CARRY));
+        assert!(!cpu.status.contains(CpuFlags::OVERFLOW));
+        // N and Z come from the pre-fixup value ($7F)
+        assert!(!cpu.status.contains(CpuFlags::NEGATIVE));
+        assert!(!cpu.status.contains(CpuFlags::ZERO));
+    }
+
+    #[test]
+    fn test_arr_decimal_mode_without_fixup() {
+        // ARR #$FF with A = $12, C = 1, D = 1 -> $89, no nibble adjustment, C clear
+        let mut cpu = cpu_with_program(&[0x6b, 0xff, 0x00]);
+        cpu.register_a = 0x12;
+        cpu.status.insert(CpuFlags::DECIMAL_MODE | CpuFlags::CARRY);
+        cpu.run();
+
+        assert_eq!(cpu.register_a, 0x89);
+        assert!(!cpu.status.contains(CpuFlags::CARRY));
+        assert!(!cpu.status.contains(CpuFlags::OVERFLOW));
+        assert!(cpu.status.contains(CpuFlags::NEGATIVE));
+    }
+
+    #[test]
+    fn test_arr_decimal_mode_sets_overflow_from_bit_6_change() {
+        // ARR #$40 with A = $40, C = 0, D = 1 -> $20, bit 6 changed so V set, low nibble untouched
+        let mut cpu = cpu_with_program(&[0x6b, 0x40, 0x00]);
+        cpu.register_a = 0x40;
+        cpu.status.insert(CpuFlags::DECIMAL_MODE);
+        cpu.run();
+
+        assert_eq!(cpu.register_a, 0x20);
+        assert!(cpu.status.contains(CpuFlags::OVERFLOW));
+        assert!(!cpu.status.contains(CpuFlags::CARRY));
+    }
 
     // #[test]
     // fn test_0xa9_lda_immediate_load_data() {
@@ -0,0 +1,178 @@
+// Each letter encodes a nibble (A = $0 ... N = $F), the nibbles are then scrambled into:
+//
+//  6 letters:  15 bit address (+ $8000) | 8 bit value
+//  8 letters:  15 bit address (+ $8000) | 8 bit value | 8 bit compare
+//
+// http://wiki.nesdev.com/w/index.php/Game_Genie
+
+const LETTERS: &str = "APZLGITYEOXUKSVN";
+
+/// Errors produced while decoding a Game Genie code.
+#[derive(Debug, PartialEq)]
+pub enum GgError {
+    /// Codes are either 6 or 8 letters long.
+    InvalidLength(usize),
+    /// The letter is not part of the Game Genie alphabet.
+    InvalidCharacter(char),
+}
+
+impl std::fmt::Display for GgError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GgError::InvalidLength(len) => write!(f, "Game Genie codes are 6 or 8 letters, got {}", len),
+            GgError::InvalidCharacter(c) => write!(f, "'{}' is not a Game Genie letter", c),
+        }
+    }
+}
+
+/// A decoded Game Genie code patching a single PRG-ROM address.
+#[derive(Debug, PartialEq)]
+pub struct GameGenieCode {
+    /// The CPU address ($8000-$FFFF) being patched.
+    pub address: u16,
+    /// The value returned instead of the ROM byte.
+    pub value: u8,
+    /// For 8 letter codes, the patch only applies while the ROM byte equals this value.
+    pub compare: Option<u8>,
+}
+
+impl GameGenieCode {
+    /// Decodes a 6 or 8 letter Game Genie code (case insensitive).
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The code as printed in the Game Genie code book, e.g. `GOSSIP`.
+    pub fn decode(code: &str) -> Result<GameGenieCode, GgError> {
+        let n = code
+            .chars()
+            .map(|c| {
+                LETTERS
+                    .find(c.to_ascii_uppercase())
+                    .map(|index| index as u16)
+                    .ok_or(GgError::InvalidCharacter(c))
+            })
+            .collect::<Result<Vec<u16>, GgError>>()?;
+
+        if n.len() != 6 && n.len() != 8 {
+            return Err(GgError::InvalidLength(n.len()));
+        }
+
+        let address = 0x8000
+            + (((n[3] & 7) << 12)
+                | ((n[5] & 7) << 8)
+                | ((n[4] & 8) << 8)
+                | ((n[2] & 7) << 4)
+                | ((n[1] & 8) << 4)
+                | (n[4] & 7)
+                | (n[3] & 8));
+
+        let value_high_bit = if n.len() == 6 { n[5] } else { n[7] };
+        let value = ((n[1] & 7) << 4) | ((n[0] & 8) << 4) | (n[0] & 7) | (value_high_bit & 8);
+
+        let compare = if n.len() == 8 {
+            Some((((n[7] & 7) << 4) | ((n[6] & 8) << 4) | (n[6] & 7) | (n[5] & 8)) as u8)
+        } else {
+            None
+        };
+
+        Ok(GameGenieCode {
+            address,
+            value: value as u8,
+            compare,
+        })
+    }
+}
+
+/// A code entered into the Game Genie, kept around while disabled so a cheat manager can turn it back on.
+#[derive(Debug, PartialEq)]
+pub struct Cheat {
+    /// The letters as entered.
+    pub code: String,
+    pub patch: GameGenieCode,
+    pub enabled: bool,
+}
+
+/// Patch layer sitting between the CPU and the cartridge PRG-ROM.
+#[derive(Default)]
+pub struct GameGenie {
+    codes: Vec<Cheat>,
+}
+
+impl GameGenie {
+    pub fn new() -> Self {
+        GameGenie { codes: vec![] }
+    }
+
+    /// Decodes and activates a code.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - A 6 or 8 letter Game Genie code.
+    pub fn add_code(&mut self, code: &str) -> Result<(), GgError> {
+        let patch = GameGenieCode::decode(code)?;
+        self.codes.push(Cheat { code: code.to_string(), patch, enabled: true });
+        Ok(())
+    }
+
+    /// Every code entered, enabled or not, in the order they were added.
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.codes
+    }
+
+    /// Turns a code on or off without forgetting it. Panics if there's no code at `index`.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Position of the code in `cheats`.
+    /// * `enabled` - Whether it patches reads.
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        self.codes[index].enabled = enabled;
+    }
+
+    /// Removes every code.
+    pub fn clear(&mut self) {
+        self.codes.clear();
+    }
+
+    /// Returns the byte the CPU should see for a PRG-ROM read.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The CPU address being read.
+    /// * `data` - The byte stored in ROM at that address.
+    pub fn patch(&self, address: u16, data: u8) -> u8 {
+        self.codes
+            .iter()
+            .filter(|cheat| cheat.enabled)
+            .map(|cheat| &cheat.patch)
+            .find(|code| code.address == address && code.compare.is_none_or(|compare| compare == data))
+            .map_or(data, |code| code.value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode_six_letter_code() {
+        let code = GameGenieCode::decode("GOSSIP").unwrap();
+        assert_eq!(code.address, 0xd1dd);
+        assert_eq!(code.value, 0x14);
+        assert_eq!(code.compare, None);
+    }
+
+    #[test]
+    fn test_decode_eight_letter_code() {
+        let code = GameGenieCode::decode("zexpygla").unwrap();
+        assert_eq!(code.address, 0x94a7);
+        assert_eq!(code.value, 0x02);
+        assert_eq!(code.compare, Some(0x03));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(GameGenieCode::decode("GOSS"), Err(GgError::InvalidLength(4)));
+        assert_eq!(GameGenieCode::decode("GOSSIB"), Err(GgError::InvalidCharacter('B')));
+    }
+}
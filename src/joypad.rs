@@ -0,0 +1,181 @@
+use std::{cell::Cell, rc::Rc};
+
+bitflags! {
+    /// # Standard Controller https://www.nesdev.org/wiki/Standard_controller
+    ///
+    ///  7 6 5 4 3 2 1 0
+    ///  R L D U S s B A
+    ///  | | | | | | | +--- A
+    ///  | | | | | | +----- B
+    ///  | | | | | +------- Select
+    ///  | | | | +--------- Start
+    ///  | | | +----------- Up
+    ///  | | +------------- Down
+    ///  | +--------------- Left
+    ///  +----------------- Right
+    ///
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub struct JoypadButton: u8 {
+        const BUTTON_A = 0b00000001;
+        const BUTTON_B = 0b00000010;
+        const SELECT   = 0b00000100;
+        const START    = 0b00001000;
+        const UP       = 0b00010000;
+        const DOWN     = 0b00100000;
+        const LEFT     = 0b01000000;
+        const RIGHT    = 0b10000000;
+    }
+}
+
+/// A standard controller behind $4016/$4017. While strobe is high the shift register keeps reloading,
+/// once it's low each read returns the next button, in A, B, Select, Start, Up, Down, Left, Right order.
+pub struct Joypad {
+    strobe: bool,
+    button_index: u8,
+    button_status: JoypadButton,
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Joypad {
+            strobe: false,
+            button_index: 0,
+            button_status: JoypadButton::from_bits_truncate(0),
+        }
+    }
+
+    pub fn write(&mut self, data: u8) {
+        self.strobe = data & 1 == 1;
+        if self.strobe {
+            self.button_index = 0
+        }
+    }
+
+    /// Shifts out the next button into bit 0. After all 8 an official controller keeps returning 1.
+    /// The other bits aren't driven by the controller, the bus fills them in with open bus.
+    pub fn read(&mut self) -> u8 {
+        if self.button_index > 7 {
+            return 1;
+        }
+        let response = (self.button_status.bits() & (1 << self.button_index)) >> self.button_index;
+        if !self.strobe && self.button_index <= 7 {
+            self.button_index += 1;
+        }
+        response
+    }
+
+    /// Returns what the next read would, without shifting.
+    pub fn peek(&self) -> u8 {
+        if self.button_index > 7 {
+            return 1;
+        }
+        (self.button_status.bits() & (1 << self.button_index)) >> self.button_index
+    }
+
+    pub fn set_button_pressed_status(&mut self, button: JoypadButton, pressed: bool) {
+        self.button_status.set(button, pressed);
+    }
+
+    /// The buttons currently held.
+    pub fn button_status(&self) -> JoypadButton {
+        self.button_status
+    }
+
+    /// Replaces the held buttons all at once.
+    pub fn set_button_status(&mut self, buttons: JoypadButton) {
+        self.button_status = buttons;
+    }
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Joypad::new()
+    }
+}
+
+/// The buttons held on a controller at one point in time.
+pub type ButtonState = JoypadButton;
+
+/// Where a controller's buttons come from: a keyboard, the network, a bot, a script...
+/// The console polls it once at the start of every frame.
+pub trait InputSource {
+    fn poll(&mut self) -> ButtonState;
+}
+
+/// Buttons pressed and released by hand, the way the `Joypad` setters work.
+/// Clones share their state, so one copy can be handed to the console while the frontend keeps the other.
+#[derive(Clone, Default)]
+pub struct ManualInput {
+    buttons: Rc<Cell<ButtonState>>,
+}
+
+impl ManualInput {
+    pub fn new() -> Self {
+        ManualInput::default()
+    }
+
+    pub fn set_button_pressed_status(&self, button: JoypadButton, pressed: bool) {
+        let mut buttons = self.buttons.get();
+        buttons.set(button, pressed);
+        self.buttons.set(buttons);
+    }
+}
+
+impl InputSource for ManualInput {
+    fn poll(&mut self) -> ButtonState {
+        self.buttons.get()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_manual_input_clones_share_buttons() {
+        let input = ManualInput::new();
+        let mut console_side = input.clone();
+        input.set_button_pressed_status(JoypadButton::START, true);
+        input.set_button_pressed_status(JoypadButton::UP, true);
+        input.set_button_pressed_status(JoypadButton::UP, false);
+        assert_eq!(console_side.poll(), JoypadButton::START);
+    }
+
+    #[test]
+    fn test_strobe_mode() {
+        let mut joypad = Joypad::new();
+        joypad.write(1);
+        joypad.set_button_pressed_status(JoypadButton::BUTTON_A, true);
+        for _x in 0..10 {
+            assert_eq!(joypad.read(), 1);
+        }
+    }
+
+    #[test]
+    fn test_strobe_mode_on_off() {
+        let mut joypad = Joypad::new();
+
+        joypad.write(0);
+        joypad.set_button_pressed_status(JoypadButton::RIGHT, true);
+        joypad.set_button_pressed_status(JoypadButton::LEFT, true);
+        joypad.set_button_pressed_status(JoypadButton::SELECT, true);
+        joypad.set_button_pressed_status(JoypadButton::BUTTON_B, true);
+
+        for _ in 0..=1 {
+            assert_eq!(joypad.read(), 0);
+            assert_eq!(joypad.read(), 1);
+            assert_eq!(joypad.read(), 1);
+            assert_eq!(joypad.read(), 0);
+            assert_eq!(joypad.read(), 0);
+            assert_eq!(joypad.read(), 0);
+            assert_eq!(joypad.read(), 1);
+            assert_eq!(joypad.read(), 1);
+
+            for _x in 0..10 {
+                assert_eq!(joypad.read(), 1);
+            }
+            joypad.write(1);
+            joypad.write(0);
+        }
+    }
+}
@@ -0,0 +1,27 @@
+//! The emulator core: CPU, PPU, APU, cartridge and the `nes::NES` console tying them together.
+//! It has no native dependencies, so it builds without the default `sdl` feature, e.g. for wasm32-unknown-unknown.
+
+pub mod apu;
+pub mod asm;
+pub mod trace;
+pub mod cpu;
+pub mod opcode;
+pub mod bus;
+pub mod blargg;
+pub mod cartridge;
+pub mod game_genie;
+pub mod checksum;
+pub mod clock;
+pub mod ppu;
+pub mod joypad;
+pub mod mapper;
+pub mod movie;
+pub mod nes;
+pub mod render;
+pub mod save_state;
+
+#[macro_use]
+extern crate lazy_static;
+
+#[macro_use]
+extern crate bitflags;
@@ -1,16 +1,10 @@
 // #![allow(dead_code)]
 // #![allow(unused_variables)]
 
-pub mod trace;
-pub mod cpu;
-pub mod opcode;
-pub mod bus;
-pub mod cartridge;
-
-use bus::Bus;
-use cartridge::Rom;
-use cpu::{Memory, CPU};
-use trace::trace;
+use nes_rs::bus::Bus;
+use nes_rs::cartridge::Rom;
+use nes_rs::cpu::{Memory, CPU};
+use nes_rs::trace::trace;
 use rand::Rng;
 
 use sdl2::event::Event;
@@ -20,12 +14,6 @@ use sdl2::pixels::Color;
 use sdl2::pixels::PixelFormatEnum;
 // use std::time::Duration;
 
-#[macro_use]
-extern crate lazy_static;
-
-#[macro_use]
-extern crate bitflags;
-
 fn main() {
     // initialize sdl2
     let sdl_ctxt = sdl2::init().unwrap();
@@ -117,7 +105,7 @@ fn read_screen_state(cpu: &CPU, frame: &mut [u8; 32 * 3 * 32]) -> bool {
     let mut frame_index = 0;
     let mut updated = false;
     for i in 0x0200..0x600 {
-        let color_idx = cpu.mem_read(i as u16);
+        let color_idx = cpu.peek(i as u16);
         let (b1, b2, b3) = color(color_idx).rgb();
         if frame[frame_index] != b1 || frame[frame_index + 1] != b2 || frame[frame_index + 2] != b3{
             frame[frame_index] = b1;
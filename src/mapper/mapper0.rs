@@ -0,0 +1,43 @@
+use super::Mapper;
+use crate::cartridge::Mirroring;
+
+/// # NROM (mapper 0) https://www.nesdev.org/wiki/NROM
+/// No bank switching: 16KB or 32KB of PRG-ROM (16KB is mirrored into $C000-$FFFF) and 8KB of CHR.
+pub struct Mapper0 {
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    mirroring: Mirroring,
+}
+
+impl Mapper0 {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+        Mapper0 {
+            prg_rom,
+            chr_rom,
+            mirroring,
+        }
+    }
+}
+
+impl Mapper for Mapper0 {
+    fn read_prg(&self, mut address: u16) -> u8 {
+        address -= 0x8000;
+        if self.prg_rom.len() == 0x4000 && address >= 0x4000 {
+            //mirror if needed
+            address %= 0x4000;
+        }
+        self.prg_rom[address as usize]
+    }
+
+    fn write_prg(&mut self, _address: u16, _data: u8) {
+        // nothing on the board listens, the write goes nowhere
+    }
+
+    fn chr_view(&self) -> Vec<u8> {
+        self.chr_rom.clone()
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
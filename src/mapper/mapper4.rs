@@ -0,0 +1,319 @@
+use super::{Mapper, MapperState};
+use crate::cartridge::Mirroring;
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+
+//  $8000-$9FFE even  Bank select   CP-- -RRR   CHR A12 inversion, PRG mode, target register
+//  $8001-$9FFF odd   Bank data     DDDD DDDD   new bank value for R0-R7
+//  $A000-$BFFE even  Mirroring     ---- ---M   0: vertical, 1: horizontal
+//  $A001-$BFFF odd   PRG RAM protect
+//  $C000-$DFFE even  IRQ latch     counter reload value
+//  $C001-$DFFF odd   IRQ reload    clears the counter so it reloads on the next clock
+//  $E000-$FFFE even  IRQ disable   also acknowledges a pending IRQ
+//  $E001-$FFFF odd   IRQ enable
+
+// MMC3 REGISTERS
+
+/// Snapshot of the MMC3's registers, as stored in a save state.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mmc3State {
+    pub mirroring: Mirroring,
+    pub bank_select: u8,
+    pub registers: [u8; 8],
+    pub irq_latch: u8,
+    pub irq_counter: u8,
+    pub irq_reload: bool,
+    pub irq_enabled: bool,
+    pub irq_pending: bool,
+}
+
+/// # MMC3 (mapper 4) https://www.nesdev.org/wiki/MMC3
+/// 8KB PRG banks, 1KB/2KB CHR banks and a scanline counter clocked by rising edges of PPU A12,
+/// which pulls the IRQ line when it reaches zero.
+pub struct Mapper4 {
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    mirroring: Mirroring,
+    bank_select: u8,
+    registers: [u8; 8], // R0-R7
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+}
+
+impl Mapper4 {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+        Mapper4 {
+            prg_rom,
+            chr_rom,
+            mirroring,
+            bank_select: 0,
+            registers: [0; 8],
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+        }
+    }
+
+    fn prg_bank_count(&self) -> usize {
+        self.prg_rom.len() / PRG_BANK_SIZE
+    }
+
+    /// Which 8KB PRG bank is mapped into each of the 4 CPU windows ($8000, $A000, $C000, $E000).
+    fn prg_banks(&self) -> [usize; 4] {
+        // saturating so an image with a single 8KB bank maps it everywhere
+        let second_last = self.prg_bank_count().saturating_sub(2);
+        let last = self.prg_bank_count().saturating_sub(1);
+        let r6 = self.registers[6] as usize & 0b0011_1111;
+        let r7 = self.registers[7] as usize & 0b0011_1111;
+
+        if self.bank_select & 0b0100_0000 == 0 {
+            [r6, r7, second_last, last]
+        } else {
+            [second_last, r7, r6, last]
+        }
+    }
+
+    /// Which 1KB CHR bank is mapped into each of the 8 PPU windows ($0000, $0400, ... $1C00).
+    /// R0 and R1 select 2KB banks and ignore their low bit.
+    fn chr_banks(&self) -> [usize; 8] {
+        let r = self.registers.map(|bank| bank as usize);
+        let two_kb = [r[0] & !1, r[0] | 1, r[1] & !1, r[1] | 1];
+        let one_kb = [r[2], r[3], r[4], r[5]];
+
+        let mut banks = [0; 8];
+        if self.bank_select & 0b1000_0000 == 0 {
+            banks[..4].copy_from_slice(&two_kb);
+            banks[4..].copy_from_slice(&one_kb);
+        } else {
+            banks[..4].copy_from_slice(&one_kb);
+            banks[4..].copy_from_slice(&two_kb);
+        }
+        banks
+    }
+
+    pub fn irq_counter(&self) -> u8 {
+        self.irq_counter
+    }
+}
+
+impl Mapper for Mapper4 {
+    fn read_prg(&self, address: u16) -> u8 {
+        let offset = (address - 0x8000) as usize;
+        let bank = self.prg_banks()[offset / PRG_BANK_SIZE] % self.prg_bank_count();
+        self.prg_rom[bank * PRG_BANK_SIZE + offset % PRG_BANK_SIZE]
+    }
+
+    fn write_prg(&mut self, address: u16, data: u8) {
+        let even = address & 1 == 0;
+        match (address, even) {
+            (0x8000..=0x9fff, true) => self.bank_select = data,
+            (0x8000..=0x9fff, false) => self.registers[(self.bank_select & 0b111) as usize] = data,
+            (0xa000..=0xbfff, true) => {
+                if self.mirroring != Mirroring::FOUR_SCREEN {
+                    self.mirroring = if data & 1 == 0 {
+                        Mirroring::VERTICAL
+                    } else {
+                        Mirroring::HORIZONTAL
+                    };
+                }
+            }
+            (0xa000..=0xbfff, false) => { /* PRG RAM protect, no PRG RAM emulated */ }
+            (0xc000..=0xdfff, true) => self.irq_latch = data,
+            (0xc000..=0xdfff, false) => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            (0xe000..=0xffff, true) => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            (0xe000..=0xffff, false) => self.irq_enabled = true,
+            _ => unreachable!("address {:04x} is outside of PRG space", address),
+        }
+    }
+
+    fn chr_view(&self) -> Vec<u8> {
+        if self.chr_rom.is_empty() {
+            return vec![];
+        }
+        let bank_count = self.chr_rom.len() / CHR_BANK_SIZE;
+        self.chr_banks()
+            .iter()
+            .flat_map(|bank| {
+                let start = (bank % bank_count) * CHR_BANK_SIZE;
+                self.chr_rom[start..start + CHR_BANK_SIZE].iter().copied()
+            })
+            .collect()
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn clock_a12(&mut self) {
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn save_state(&self) -> MapperState {
+        MapperState::Mmc3(Mmc3State {
+            mirroring: self.mirroring,
+            bank_select: self.bank_select,
+            registers: self.registers,
+            irq_latch: self.irq_latch,
+            irq_counter: self.irq_counter,
+            irq_reload: self.irq_reload,
+            irq_enabled: self.irq_enabled,
+            irq_pending: self.irq_pending,
+        })
+    }
+
+    fn load_state(&mut self, state: &MapperState) {
+        let MapperState::Mmc3(state) = state else {
+            return;
+        };
+        self.mirroring = state.mirroring;
+        self.bank_select = state.bank_select;
+        self.registers = state.registers;
+        self.irq_latch = state.irq_latch;
+        self.irq_counter = state.irq_counter;
+        self.irq_reload = state.irq_reload;
+        self.irq_enabled = state.irq_enabled;
+        self.irq_pending = state.irq_pending;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// 8 PRG banks and 16 CHR banks, each filled with its own bank number.
+    fn test_mapper() -> Mapper4 {
+        let prg_rom = (0..8).flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE]).collect();
+        let chr_rom = (0..16).flat_map(|bank| vec![bank as u8; CHR_BANK_SIZE]).collect();
+        Mapper4::new(prg_rom, chr_rom, Mirroring::VERTICAL)
+    }
+
+    #[test]
+    fn test_prg_banking_modes() {
+        let mut mapper = test_mapper();
+        mapper.write_prg(0x8000, 6);
+        mapper.write_prg(0x8001, 2);
+        mapper.write_prg(0x8000, 7);
+        mapper.write_prg(0x8001, 3);
+
+        assert_eq!(mapper.read_prg(0x8000), 2);
+        assert_eq!(mapper.read_prg(0xa000), 3);
+        assert_eq!(mapper.read_prg(0xc000), 6);
+        assert_eq!(mapper.read_prg(0xffff), 7);
+
+        // PRG mode 1 swaps $8000 and $C000
+        mapper.write_prg(0x8000, 0b0100_0000);
+        assert_eq!(mapper.read_prg(0x8000), 6);
+        assert_eq!(mapper.read_prg(0xc000), 2);
+        assert_eq!(mapper.read_prg(0xe000), 7);
+    }
+
+    #[test]
+    fn test_single_prg_bank() {
+        let mut mapper = Mapper4::new(vec![0x42; PRG_BANK_SIZE], vec![], Mirroring::VERTICAL);
+        assert_eq!(mapper.read_prg(0x8000), 0x42);
+        assert_eq!(mapper.read_prg(0xe000), 0x42);
+        mapper.write_prg(0x8000, 0b0100_0000);
+        assert_eq!(mapper.read_prg(0xc000), 0x42);
+    }
+
+    #[test]
+    fn test_chr_banking_and_inversion() {
+        let mut mapper = test_mapper();
+        for (register, bank) in [4, 6, 8, 9, 10, 11].iter().enumerate() {
+            mapper.write_prg(0x8000, register as u8);
+            mapper.write_prg(0x8001, *bank);
+        }
+
+        let chr = mapper.chr_view();
+        let banks: Vec<u8> = chr.chunks(CHR_BANK_SIZE).map(|bank| bank[0]).collect();
+        assert_eq!(banks, vec![4, 5, 6, 7, 8, 9, 10, 11]);
+
+        // A12 inversion moves the 2KB banks to $1000
+        mapper.write_prg(0x8000, 0b1000_0000);
+        let chr = mapper.chr_view();
+        let banks: Vec<u8> = chr.chunks(CHR_BANK_SIZE).map(|bank| bank[0]).collect();
+        assert_eq!(banks, vec![8, 9, 10, 11, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn test_mirroring_control() {
+        let mut mapper = test_mapper();
+        mapper.write_prg(0xa000, 1);
+        assert_eq!(mapper.mirroring(), Mirroring::HORIZONTAL);
+        mapper.write_prg(0xa000, 0);
+        assert_eq!(mapper.mirroring(), Mirroring::VERTICAL);
+    }
+
+    #[test]
+    fn test_save_and_load_state() {
+        let mut mapper = test_mapper();
+        mapper.write_prg(0x8000, 0b0100_0110);
+        mapper.write_prg(0x8001, 5);
+        mapper.write_prg(0xa000, 1);
+        mapper.write_prg(0xc000, 9);
+        mapper.write_prg(0xe001, 0);
+        mapper.clock_a12();
+        let state = mapper.save_state();
+
+        let mut restored = test_mapper();
+        restored.load_state(&state);
+        assert_eq!(restored.read_prg(0xc000), 5);
+        assert_eq!(restored.mirroring(), Mirroring::HORIZONTAL);
+        assert_eq!(restored.irq_counter(), 9);
+        assert_eq!(restored.save_state(), state);
+
+        // another board's state leaves it alone
+        restored.load_state(&MapperState::None);
+        assert_eq!(restored.save_state(), state);
+    }
+
+    #[test]
+    fn test_irq_fires_after_latch_scanlines() {
+        let mut mapper = test_mapper();
+        mapper.write_prg(0xc000, 3); // latch
+        mapper.write_prg(0xc001, 0); // reload
+        mapper.write_prg(0xe001, 0); // enable
+
+        // the first clock reloads the counter, then it counts down once per scanline
+        for scanline in 0..3 {
+            mapper.clock_a12();
+            assert!(!mapper.irq(), "fired early on scanline {}", scanline);
+        }
+        mapper.clock_a12();
+        assert!(mapper.irq());
+
+        // $E000 acknowledges and disables
+        mapper.write_prg(0xe000, 0);
+        assert!(!mapper.irq());
+        for _ in 0..8 {
+            mapper.clock_a12();
+        }
+        assert!(!mapper.irq());
+    }
+}
@@ -0,0 +1,106 @@
+pub mod mapper0;
+pub mod mapper4;
+
+use crate::cartridge::{Mirroring, Rom};
+use mapper0::Mapper0;
+use mapper4::{Mapper4, Mmc3State};
+
+/// A board's registers, as stored in a save state. The ROMs are left out since they come with the cartridge.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MapperState {
+    /// NROM and other boards without registers.
+    None,
+    Mmc3(Mmc3State),
+}
+
+/// # Cartridge Mapper https://www.nesdev.org/wiki/Mapper
+/// The cartridge hardware sitting behind $8000-$FFFF on the CPU side and the pattern tables on the PPU side.
+pub trait Mapper {
+    /// Reads PRG-ROM at a CPU address in $8000-$FFFF.
+    fn read_prg(&self, address: u16) -> u8;
+
+    /// Handles a CPU write to $8000-$FFFF, which on most boards hits the mapper's registers.
+    fn write_prg(&mut self, address: u16, data: u8);
+
+    /// The 8KB of CHR currently banked into the PPU's pattern tables ($0000-$1FFF).
+    fn chr_view(&self) -> Vec<u8>;
+
+    /// Nametable mirroring currently selected by the board.
+    fn mirroring(&self) -> Mirroring;
+
+    /// Reads the expansion area at $4020-$5FFF. `None` leaves the bus open, which is what
+    /// boards without anything there do.
+    fn read_exp(&self, _address: u16) -> Option<u8> {
+        None
+    }
+
+    /// Handles a CPU write to $4020-$5FFF. Returns false if nothing on the board listens there.
+    fn write_exp(&mut self, _address: u16, _data: u8) -> bool {
+        false
+    }
+
+    /// Called for every rising edge of PPU address line A12.
+    fn clock_a12(&mut self) {}
+
+    /// Level of the board's IRQ output.
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// Captures the board's registers into a `MapperState`.
+    fn save_state(&self) -> MapperState {
+        MapperState::None
+    }
+
+    /// Restores the board's registers from a `MapperState`. A snapshot of a different board is ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - A snapshot taken by `save_state`.
+    fn load_state(&mut self, _state: &MapperState) {}
+}
+
+/// Common board name for an iNES mapper number.
+///
+/// # Arguments
+///
+/// * `number` - The iNES mapper number.
+pub fn name(number: u8) -> &'static str {
+    match number {
+        0 => "NROM",
+        1 => "MMC1",
+        2 => "UxROM",
+        3 => "CNROM",
+        4 => "MMC3",
+        5 => "MMC5",
+        7 => "AxROM",
+        9 => "MMC2",
+        10 => "MMC4",
+        11 => "Color Dreams",
+        66 => "GxROM",
+        _ => "Unknown",
+    }
+}
+
+/// Whether a mapper is implemented for the board number, rather than falling back to NROM.
+///
+/// # Arguments
+///
+/// * `number` - The iNES mapper number.
+pub fn is_supported(number: u8) -> bool {
+    matches!(number, 0 | 4)
+}
+
+/// Builds the mapper for a cartridge. `Rom::from_bytes` refuses unknown mapper numbers, a `Rom` put
+/// together by hand with one falls back to the NROM layout.
+///
+/// # Arguments
+///
+/// * `rom` - The parsed cartridge.
+pub fn from_rom(rom: &Rom) -> Box<dyn Mapper> {
+    match rom.mapper {
+        4 => Box::new(Mapper4::new(rom.prg_rom.clone(), rom.chr_rom.clone(), rom.mirroring)),
+        _ => Box::new(Mapper0::new(rom.prg_rom.clone(), rom.chr_rom.clone(), rom.mirroring)),
+    }
+}
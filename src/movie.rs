@@ -0,0 +1,69 @@
+use crate::joypad::{Joypad, JoypadButton};
+
+/// Recorded controller input, one entry per frame holding the buttons held on both controllers.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Movie {
+    pub frames: Vec<(u8, u8)>,
+}
+
+impl Movie {
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+/// Logs the controller state at the start of every frame.
+#[derive(Default)]
+pub struct MovieRecorder {
+    movie: Movie,
+}
+
+impl MovieRecorder {
+    pub fn new() -> Self {
+        MovieRecorder::default()
+    }
+
+    pub fn record(&mut self, joypad1: &Joypad, joypad2: &Joypad) {
+        self.movie
+            .frames
+            .push((joypad1.button_status().bits(), joypad2.button_status().bits()));
+    }
+
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+/// Feeds a recorded movie back into the controllers, one frame at a time.
+pub struct MoviePlayer {
+    movie: Movie,
+    position: usize,
+}
+
+impl MoviePlayer {
+    pub fn new(movie: Movie) -> Self {
+        MoviePlayer { movie, position: 0 }
+    }
+
+    /// Applies the next frame's input. Returns false once the movie is over, leaving the controllers alone.
+    pub fn play_frame(&mut self, joypad1: &mut Joypad, joypad2: &mut Joypad) -> bool {
+        match self.movie.frames.get(self.position) {
+            Some(&(buttons1, buttons2)) => {
+                joypad1.set_button_status(JoypadButton::from_bits_truncate(buttons1));
+                joypad2.set_button_status(JoypadButton::from_bits_truncate(buttons2));
+                self.position += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.movie.len()
+    }
+}
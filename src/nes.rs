@@ -0,0 +1,837 @@
+use crate::{
+    apu::{audio_buffer::AudioBuffer, mixer::Mixer},
+    bus::Bus,
+    cartridge::{CartridgeInfo, Rom, RomError},
+    clock::CPU_CLOCK_HZ,
+    cpu::CPU,
+    game_genie::Cheat,
+    joypad::{InputSource, Joypad},
+    movie::{Movie, MoviePlayer, MovieRecorder},
+    ppu::DOTS_PER_FRAME,
+    render::{
+        self,
+        frame::Frame,
+        sink::{NullSink, VideoSink},
+    },
+    save_state::SaveState,
+};
+use std::time::Duration;
+
+/// How long a boot stub gets to hand over to the cartridge, a second's worth of cycles.
+const BOOT_STUB_MAX_CYCLES: usize = CPU_CLOCK_HZ as usize;
+
+/// The CPU's PRG-ROM window, $8000-$FFFF.
+const PRG_WINDOW_SIZE: usize = 0x8000;
+
+/// While fast-forwarding, only every this many frames is rendered and presented.
+pub const FAST_FORWARD_FRAME_SKIP: u64 = 4;
+
+/// The whole console: the CPU and, through its bus, the PPU, APU, controllers and cartridge.
+pub struct NES {
+    pub cpu: CPU,
+    frame: Frame,
+    video_sink: Box<dyn VideoSink>,
+    recorder: Option<MovieRecorder>,
+    player: Option<MoviePlayer>,
+    input1: Option<Box<dyn InputSource>>,
+    input2: Option<Box<dyn InputSource>>,
+    frame_started: bool,       // INPUT WAS ALREADY POLLED FOR THE FRAME IN PROGRESS
+    pub fast_forward: bool,
+    pub speed_multiplier: f32, // EMULATED TIME PER REAL TIME IN run_for, 1.0 IS FULL SPEED
+}
+
+impl NES {
+    /// Builds a console around an iNES image already in memory, for frontends without file access such as WASM.
+    /// Boards without a mapper implementation are refused.
+    ///
+    /// # Arguments
+    ///
+    /// * `rom_bytes` - The raw bytes of an iNES file.
+    pub fn new(rom_bytes: &[u8]) -> Result<Self, RomError> {
+        Ok(NES::from_bus(Bus::from_ines_bytes(rom_bytes)?))
+    }
+
+    /// Plugs a cartridge into a fresh console and presses reset.
+    ///
+    /// # Arguments
+    ///
+    /// * `rom` - The cartridge to run.
+    pub fn from_rom(rom: Rom) -> Self {
+        NES::with_boot_stub(rom, None)
+    }
+
+    /// Like `from_rom`, with `boot_stub` running on every reset and power cycle before the cartridge does.
+    /// The stub sits right below the vectors, shadowing that end of PRG-ROM while it runs,
+    /// and is followed by a JMP ($FFFC) into the cartridge.
+    ///
+    /// # Arguments
+    ///
+    /// * `rom` - The cartridge to run.
+    /// * `boot_stub` - Machine code to run first, `None` to start straight at the cartridge's reset vector.
+    pub fn with_boot_stub(rom: Rom, boot_stub: Option<Vec<u8>>) -> Self {
+        let mut bus = Bus::new(rom);
+        bus.set_boot_stub(boot_stub);
+        NES::from_bus(bus)
+    }
+
+    fn from_bus(bus: Bus) -> Self {
+        let mut nes = NES {
+            cpu: CPU::new(bus),
+            frame: Frame::new(),
+            video_sink: Box::new(NullSink),
+            recorder: None,
+            player: None,
+            input1: None,
+            input2: None,
+            frame_started: false,
+            fast_forward: false,
+            speed_multiplier: 1.0,
+        };
+        nes.reset();
+        nes
+    }
+
+    /// Sets where finished frames are presented, frames are dropped until one is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - Receives every frame at the end of `run_one_frame`.
+    pub fn set_video_sink(&mut self, sink: Box<dyn VideoSink>) {
+        self.video_sink = sink;
+    }
+
+    /// Presses the reset button, restarting execution from the reset vector.
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+        self.run_boot_stub();
+    }
+
+    /// Runs the boot stub, if any, until it arrives at the cartridge's reset vector.
+    fn run_boot_stub(&mut self) {
+        if let Some(entry) = self.cpu.bus.map_boot_stub() {
+            let cartridge_entry = self.cpu.program_counter;
+            self.cpu.program_counter = entry;
+            self.cpu.run_to(cartridge_entry, BOOT_STUB_MAX_CYCLES);
+            self.cpu.bus.unmap_boot_stub();
+        }
+    }
+
+    /// Swaps in a raw binary as an NROM cartridge and resets into it, for assembled programs that carry
+    /// their own vectors rather than an iNES header. The image ends at $FFFF, so its last 6 bytes are the
+    /// NMI, reset and IRQ vectors; a shorter image leaves zeroes below it. CHR and mirroring stay as they were.
+    /// Fails without touching the console if the image is bigger than the 32KB PRG window.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - Up to 32KB of PRG, ending with the vectors.
+    pub fn load_rom_image(&mut self, image: &[u8]) -> Result<(), RomError> {
+        if image.len() > PRG_WINDOW_SIZE {
+            return Err(RomError::TooLarge { limit: PRG_WINDOW_SIZE, got: image.len() });
+        }
+        let mut prg_rom = vec![0; PRG_WINDOW_SIZE];
+        prg_rom[PRG_WINDOW_SIZE - image.len()..].copy_from_slice(image);
+
+        let cartridge = self.cpu.bus.cartridge();
+        let rom = Rom {
+            prg_rom,
+            chr_rom: cartridge.chr_rom.clone(),
+            mapper: 0,
+            mirroring: cartridge.mirroring,
+            has_battery: false,
+            region: cartridge.region,
+        };
+        self.cpu.insert_cartridge(rom);
+        self.run_boot_stub();
+        self.frame = Frame::new();
+        Ok(())
+    }
+
+    /// The Game Genie codes entered so far, for a cheat manager to list.
+    pub fn cheats(&self) -> &[Cheat] {
+        self.cpu.bus.cheats()
+    }
+
+    /// Enables or disables a Game Genie code without removing it. Panics if there's no code at `index`.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Position of the code in `cheats`.
+    /// * `enabled` - Whether it patches reads.
+    pub fn toggle_cheat(&mut self, index: usize, enabled: bool) {
+        self.cpu.bus.toggle_cheat(index, enabled);
+    }
+
+    /// Removes every Game Genie code.
+    pub fn clear_cheats(&mut self) {
+        self.cpu.bus.clear_cheats();
+    }
+
+    /// Describes the loaded cartridge: mapper, mirroring, battery, sizes and region.
+    pub fn cartridge_info(&self) -> CartridgeInfo {
+        self.cpu.bus.cartridge().info()
+    }
+
+    /// Flips the power switch off and on. Every subsystem comes back exactly as `from_rom` builds it,
+    /// while the video sink and any movie being recorded or played keep going.
+    pub fn power_cycle(&mut self) {
+        self.cpu.power_cycle();
+        self.run_boot_stub();
+        self.frame = Frame::new();
+    }
+
+    /// Snapshots the console: the CPU, RAM, SRAM, mapper and PPU.
+    pub fn save_state(&self) -> SaveState {
+        SaveState::capture(&self.cpu)
+    }
+
+    /// Puts the console back into a snapshot from `save_state` and redraws the frame from the restored PPU.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - A snapshot taken with the same cartridge inserted.
+    pub fn load_state(&mut self, state: &SaveState) {
+        state.restore(&mut self.cpu);
+        render::render(&self.cpu.bus.ppu, &mut self.frame);
+    }
+
+    /// Executes a single CPU instruction, servicing pending interrupts first.
+    /// Returns false if the CPU hit BRK.
+    pub fn step(&mut self) -> bool {
+        self.cpu.step()
+    }
+
+    /// Starts logging the controllers at the start of every frame.
+    pub fn start_recording(&mut self) {
+        self.recorder = Some(MovieRecorder::new());
+    }
+
+    /// Stops recording and returns the input logged so far.
+    pub fn stop_recording(&mut self) -> Movie {
+        self.recorder.take().map(MovieRecorder::finish).unwrap_or_default()
+    }
+
+    /// Hands controller 1 to `source`, which is polled at the start of every frame.
+    /// A movie being played still overrides it.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Supplies the buttons held on controller 1.
+    pub fn set_input1(&mut self, source: Box<dyn InputSource>) {
+        self.input1 = Some(source);
+    }
+
+    /// Hands controller 2 to `source`, see `set_input1`.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Supplies the buttons held on controller 2.
+    pub fn set_input2(&mut self, source: Box<dyn InputSource>) {
+        self.input2 = Some(source);
+    }
+
+    /// Drives the controllers from `movie`, one entry per frame, until it runs out.
+    ///
+    /// # Arguments
+    ///
+    /// * `movie` - Input recorded with `start_recording`/`stop_recording`.
+    pub fn play(&mut self, movie: Movie) {
+        self.player = Some(MoviePlayer::new(movie));
+    }
+
+    /// CPU cycles until the PPU wraps back to scanline 0, rounded up: the frame ends during the last one.
+    pub fn cycles_until_frame_end(&self) -> usize {
+        let dots = DOTS_PER_FRAME - self.cpu.bus.ppu.frame_dot();
+        self.cpu.bus.clock().cpu_cycles_for_dots(dots)
+    }
+
+    /// Runs until the PPU completes the current frame, then renders and presents it.
+    /// With `fast_forward` set the frame is still emulated in full, but only every
+    /// `FAST_FORWARD_FRAME_SKIP`th one is rendered and presented.
+    /// Returns false if the CPU hit BRK before the frame was done.
+    pub fn run_one_frame(&mut self) -> bool {
+        self.start_frame();
+        let frame_count = self.cpu.bus.ppu.frame_count;
+        while self.cpu.bus.ppu.frame_count == frame_count {
+            if !self.step() {
+                return false;
+            }
+        }
+        self.finish_frame();
+        true
+    }
+
+    /// Runs as many CPU cycles as `duration` of real time takes, scaled by `speed_multiplier`:
+    /// at 2.0 a second runs two seconds worth of cycles, at 0.5 half a second's.
+    /// The cycle count only depends on the arguments, never on the wall clock.
+    /// Frames completed along the way are presented, like `run_one_frame` does.
+    /// Returns false if the CPU hit BRK.
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - Real time to emulate.
+    pub fn run_for(&mut self, duration: Duration) -> bool {
+        let cycles = duration.as_secs_f64() * CPU_CLOCK_HZ * self.speed_multiplier as f64;
+        self.run_cycles(cycles.round() as usize)
+    }
+
+    /// Runs at least `cycles` CPU cycles, finishing the instruction that crosses the budget,
+    /// and returns how many actually ran so pacing loops can carry the overshoot into the next call.
+    /// Frames completed along the way are presented, like `run_one_frame` does.
+    /// Stops early if the CPU hits BRK.
+    ///
+    /// # Arguments
+    ///
+    /// * `cycles` - The CPU cycle budget.
+    pub fn tick(&mut self, cycles: usize) -> usize {
+        let start = self.cpu.cycles;
+        self.run_cycles(cycles);
+        self.cpu.cycles - start
+    }
+
+    /// Steps whole instructions until `cycles` CPU cycles have passed. Returns false if the CPU hit BRK.
+    fn run_cycles(&mut self, cycles: usize) -> bool {
+        let target = self.cpu.cycles + cycles;
+        while self.cpu.cycles < target {
+            self.start_frame();
+            let frame_count = self.cpu.bus.ppu.frame_count;
+            if !self.step() {
+                return false;
+            }
+            if self.cpu.bus.ppu.frame_count != frame_count {
+                self.finish_frame();
+            }
+        }
+        true
+    }
+
+    /// Applies the input for the frame about to run: input sources, then the movie being played,
+    /// then logs it if recording. Only once per frame, however many calls it's split over.
+    fn start_frame(&mut self) {
+        self.cpu.bus.set_generate_samples(!self.fast_forward);
+        if self.frame_started {
+            return;
+        }
+        self.frame_started = true;
+
+        if let Some(input) = self.input1.as_mut() {
+            self.cpu.bus.joypad1.set_button_status(input.poll());
+        }
+        if let Some(input) = self.input2.as_mut() {
+            self.cpu.bus.joypad2.set_button_status(input.poll());
+        }
+        if let Some(player) = self.player.as_mut() {
+            player.play_frame(&mut self.cpu.bus.joypad1, &mut self.cpu.bus.joypad2);
+            if player.is_finished() {
+                self.player = None;
+            }
+        }
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record(&self.cpu.bus.joypad1, &self.cpu.bus.joypad2);
+        }
+    }
+
+    /// Renders and presents the frame the PPU just completed.
+    fn finish_frame(&mut self) {
+        self.frame_started = false;
+        if !self.fast_forward || self.cpu.bus.ppu.frame_count.is_multiple_of(FAST_FORWARD_FRAME_SKIP) {
+            render::render(&self.cpu.bus.ppu, &mut self.frame);
+            self.video_sink.present(&self.frame);
+        }
+    }
+
+    pub fn joypad1(&mut self) -> &mut Joypad {
+        &mut self.cpu.bus.joypad1
+    }
+
+    pub fn joypad2(&mut self) -> &mut Joypad {
+        &mut self.cpu.bus.joypad2
+    }
+
+    /// The picture rendered at the end of the last frame.
+    pub fn frame(&self) -> &Frame {
+        &self.frame
+    }
+
+    /// Hands queued audio to the sound device, see `AudioBuffer::drain`.
+    /// Samples are produced at `apu::SAMPLE_RATE` while the console runs.
+    ///
+    /// # Arguments
+    ///
+    /// * `into` - The device's buffer, filled completely.
+    pub fn drain_audio(&mut self, into: &mut [f32]) -> usize {
+        self.cpu.bus.audio_buffer().drain(into)
+    }
+
+    /// Sets how many samples may queue up before the oldest are dropped. Queued samples are discarded.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - The target latency in samples.
+    pub fn set_audio_latency(&mut self, samples: usize) {
+        *self.cpu.bus.audio_buffer() = AudioBuffer::new(samples);
+    }
+
+    /// Replaces how the channels are mixed into samples, the nonlinear hardware mix by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `mixer` - The new mixer, kept across resets and power cycles.
+    pub fn set_mixer(&mut self, mixer: Box<dyn Mixer>) {
+        self.cpu.bus.set_mixer(mixer);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::{test, Mirroring, Region};
+    use crate::clock::DividerClock;
+    use crate::cpu::Memory;
+    use crate::joypad::{ButtonState, JoypadButton};
+    use crate::ppu::{DOTS_PER_SCANLINE, SCANLINES_PER_FRAME};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Checksum and bottom-right pixel of each presented frame.
+    type Presented = Rc<RefCell<Vec<(u32, (u8, u8, u8))>>>;
+
+    struct RecordingSink {
+        frames: Presented,
+    }
+
+    impl VideoSink for RecordingSink {
+        fn present(&mut self, frame: &Frame) {
+            let corner = frame.get_pixel(Frame::WIDTH - 1, Frame::HEIGHT - 1);
+            self.frames.borrow_mut().push((frame.crc32(), corner));
+        }
+    }
+
+    /// Enables the VBlank NMI and spins, the NMI handler counts frames in $10,
+    /// stores the first controller bit in $11 and uses it as the backdrop color.
+    fn test_nes() -> NES {
+        let mut prg = vec![0; 0x8000];
+        let main = [
+            0xa9, 0x80, 0x8d, 0x00, 0x20, // LDA #$80; STA $2000
+            0x4c, 0x05, 0x80, // loop: JMP loop
+        ];
+        let nmi = [
+            0xe6, 0x10, // INC $10
+            0xa9, 0x01, 0x8d, 0x16, 0x40, // LDA #$01; STA $4016
+            0xa9, 0x00, 0x8d, 0x16, 0x40, // LDA #$00; STA $4016
+            0xad, 0x16, 0x40, 0x29, 0x01, 0x85, 0x11, // LDA $4016; AND #$01; STA $11
+            0xa9, 0x3f, 0x8d, 0x06, 0x20, // LDA #$3F; STA $2006
+            0xa9, 0x00, 0x8d, 0x06, 0x20, // LDA #$00; STA $2006
+            0xa5, 0x11, 0x8d, 0x07, 0x20, // LDA $11; STA $2007
+            0x40, // RTI
+        ];
+        prg[..main.len()].copy_from_slice(&main);
+        prg[0x100..0x100 + nmi.len()].copy_from_slice(&nmi);
+        prg[0x7ffa..].copy_from_slice(&[0x00, 0x81, 0x00, 0x80, 0x00, 0x00]);
+        NES::from_rom(test::test_rom_with_prg(prg))
+    }
+
+    #[test]
+    fn test_run_one_frame() {
+        let mut nes = test_nes();
+        assert_eq!(nes.cpu.program_counter, 0x8000);
+        nes.joypad1().set_button_pressed_status(JoypadButton::BUTTON_A, true);
+
+        assert!(nes.run_one_frame());
+        assert_eq!(nes.cpu.bus.ppu.frame_count, 1);
+        assert_eq!(nes.cpu.mem_read(0x10), 1);
+        assert_eq!(nes.cpu.mem_read(0x11), 1);
+        // a frame is 341 * 262 dots, 3 per CPU cycle
+        let frame_cycles = DOTS_PER_SCANLINE * SCANLINES_PER_FRAME as usize / 3;
+        assert!(nes.cpu.cycles >= frame_cycles && nes.cpu.cycles < frame_cycles + 8);
+
+        assert!(nes.run_one_frame());
+        assert_eq!(nes.cpu.mem_read(0x10), 2);
+        // blank nametables render as the backdrop color
+        assert_eq!(nes.frame().get_pixel(8, 0), nes.frame().get_pixel(255, 239));
+    }
+
+    #[test]
+    fn test_load_rom_image() {
+        let mut nes = test_nes();
+        let mut image = vec![0; 0x8000];
+        // $9000: LDA #$42; STA $10; BRK
+        image[0x1000..0x1005].copy_from_slice(&[0xa9, 0x42, 0x85, 0x10, 0x00]);
+        // NMI $A000, reset $9000, IRQ $B000
+        image[0x7ffa..].copy_from_slice(&[0x00, 0xa0, 0x00, 0x90, 0x00, 0xb0]);
+        nes.load_rom_image(&image).unwrap();
+
+        assert_eq!(nes.cpu.program_counter, 0x9000);
+        assert_eq!(nes.cpu.nmi_vector(), 0xa000);
+        assert_eq!(nes.cpu.irq_vector(), 0xb000);
+        assert_eq!(nes.cartridge_info().mapper, 0);
+        nes.step();
+        nes.step();
+        assert_eq!(nes.cpu.mem_read(0x10), 0x42);
+
+        // a 16KB image lands at $C000
+        let mut image = vec![0; 0x4000];
+        image[0x3ffc..0x3ffe].copy_from_slice(&[0x34, 0xc2]);
+        nes.load_rom_image(&image).unwrap();
+        assert_eq!(nes.cpu.program_counter, 0xc234);
+
+        // anything past 32KB is refused and the console keeps running what it had
+        assert_eq!(
+            nes.load_rom_image(&vec![0; 0x8001]),
+            Err(RomError::TooLarge { limit: 0x8000, got: 0x8001 })
+        );
+        assert_eq!(nes.cpu.program_counter, 0xc234);
+    }
+
+    #[test]
+    fn test_toggle_cheats() {
+        let mut nes = test_nes();
+        // GOSSIP: $D1DD -> $14, PAAAAA: $8000 -> $01
+        nes.cpu.bus.add_code("GOSSIP").unwrap();
+        nes.cpu.bus.add_code("PAAAAA").unwrap();
+        assert_eq!(nes.cheats().len(), 2);
+        assert_eq!(nes.cheats()[1].code, "PAAAAA");
+        assert_eq!((nes.cpu.peek(0xd1dd), nes.cpu.peek(0x8000)), (0x14, 0x01));
+
+        nes.toggle_cheat(0, false);
+        assert!(!nes.cheats()[0].enabled);
+        assert_eq!((nes.cpu.peek(0xd1dd), nes.cpu.peek(0x8000)), (0x00, 0x01));
+        assert_eq!(nes.cpu.mem_read(0xd1dd), 0x00);
+
+        nes.toggle_cheat(0, true);
+        assert_eq!(nes.cpu.peek(0xd1dd), 0x14);
+
+        nes.clear_cheats();
+        assert!(nes.cheats().is_empty());
+        assert_eq!((nes.cpu.peek(0xd1dd), nes.cpu.peek(0x8000)), (0x00, 0xa9));
+    }
+
+    #[test]
+    fn test_tick_budget() {
+        // the longest instruction plus an interrupt entry
+        let max_overshoot = 7 + 7;
+        let mut nes = test_nes();
+        let start = nes.cpu.cycles;
+
+        let ran = nes.tick(100);
+        assert!(ran >= 100 && ran < 100 + max_overshoot, "{}", ran);
+
+        let mut total = ran;
+        for _ in 0..1000 {
+            total += nes.tick(100);
+        }
+        assert_eq!(nes.cpu.cycles - start, total);
+        // about 3.4 frames worth
+        assert_eq!(nes.cpu.bus.ppu.frame_count, 3);
+        assert_eq!(nes.tick(0), 0);
+    }
+
+    #[test]
+    fn test_cycles_until_frame_end() {
+        let mut nes = test_nes();
+        nes.run_one_frame();
+        let start = nes.cpu.cycles;
+        let full = nes.cycles_until_frame_end();
+        assert!(full <= DOTS_PER_FRAME.div_ceil(3));
+
+        for _ in 0..1000 {
+            nes.step();
+        }
+        let elapsed = nes.cpu.cycles - start;
+        assert!(elapsed > 0);
+        assert_eq!(nes.cycles_until_frame_end() + elapsed, full);
+
+        // the count follows the clock's ratio
+        nes.cpu.bus.set_clock(Box::new(DividerClock::pal()));
+        let dots = DOTS_PER_FRAME - nes.cpu.bus.ppu.frame_dot();
+        assert_eq!(nes.cycles_until_frame_end(), (dots * 5).div_ceil(16));
+        let start = nes.cpu.cycles;
+        let full = nes.cycles_until_frame_end();
+        for _ in 0..1000 {
+            nes.step();
+        }
+        assert_eq!(nes.cycles_until_frame_end() + nes.cpu.cycles - start, full);
+    }
+
+    #[test]
+    fn test_video_sink_receives_each_frame() {
+        let mut nes = test_nes();
+        let frames = Rc::new(RefCell::new(vec![]));
+        nes.set_video_sink(Box::new(RecordingSink { frames: frames.clone() }));
+
+        nes.run_one_frame();
+        assert_eq!(frames.borrow().len(), 1);
+        assert_eq!(frames.borrow()[0].0, nes.frame().crc32());
+        assert_eq!(frames.borrow()[0].1, nes.frame().get_pixel(255, 239));
+    }
+
+    #[test]
+    fn test_fast_forward_skips_rendering() {
+        let mut normal = test_nes();
+        let mut fast = test_nes();
+        normal.set_audio_latency(8192);
+        fast.set_audio_latency(8192);
+        let frames = Rc::new(RefCell::new(vec![]));
+        fast.set_video_sink(Box::new(RecordingSink { frames: frames.clone() }));
+        fast.fast_forward = true;
+
+        for _ in 0..FAST_FORWARD_FRAME_SKIP * 2 {
+            assert!(normal.run_one_frame());
+            assert!(fast.run_one_frame());
+            assert_eq!(fast.cpu.cycles, normal.cpu.cycles);
+        }
+        assert_eq!(fast.cpu.mem_read(0x10), normal.cpu.mem_read(0x10));
+        // only frames 4 and 8 were rendered, and no audio was
+        assert_eq!(frames.borrow().len(), 2);
+        assert!(!normal.cpu.bus.audio_buffer().is_empty());
+        assert!(fast.cpu.bus.audio_buffer().is_empty());
+
+        fast.fast_forward = false;
+        fast.run_one_frame();
+        assert_eq!(frames.borrow().len(), 3);
+        assert!(!fast.cpu.bus.audio_buffer().is_empty());
+    }
+
+    /// Plays back a fixed list of button states, one per poll, then releases everything.
+    struct ScriptedInput {
+        frames: Vec<JoypadButton>,
+        position: usize,
+    }
+
+    impl InputSource for ScriptedInput {
+        fn poll(&mut self) -> ButtonState {
+            let buttons = self.frames.get(self.position).copied().unwrap_or_default();
+            self.position += 1;
+            buttons
+        }
+    }
+
+    /// Strobes controller 1 and shifts out all 8 buttons the way a game would.
+    fn read_joypad1(nes: &mut NES) -> u8 {
+        nes.cpu.mem_write(0x4016, 1);
+        nes.cpu.mem_write(0x4016, 0);
+        (0..8).fold(0, |buttons, bit| buttons | (nes.cpu.mem_read(0x4016) & 1) << bit)
+    }
+
+    #[test]
+    fn test_input_source_is_polled_each_frame() {
+        let script = vec![
+            JoypadButton::BUTTON_A,
+            JoypadButton::START | JoypadButton::LEFT,
+            JoypadButton::empty(),
+        ];
+        let mut nes = test_nes();
+        nes.set_input1(Box::new(ScriptedInput { frames: script.clone(), position: 0 }));
+
+        for buttons in script {
+            assert!(nes.run_one_frame());
+            // the NMI handler saw this frame's A button
+            assert_eq!(nes.cpu.mem_read(0x11), (buttons & JoypadButton::BUTTON_A).bits());
+            assert_eq!(read_joypad1(&mut nes), buttons.bits());
+        }
+    }
+
+    #[test]
+    fn test_speed_multiplier_scales_run_for() {
+        let duration = Duration::from_millis(50);
+        // 50ms of CPU time, give or take the last instruction
+        let full_speed = 89_489;
+        let mut normal = test_nes();
+        assert!(normal.run_for(duration));
+        assert!((full_speed..full_speed + 8).contains(&normal.cpu.cycles));
+
+        let mut turbo = test_nes();
+        turbo.speed_multiplier = 2.0;
+        assert!(turbo.run_for(duration));
+        // 100ms of CPU time rounds to one cycle less than twice 50ms
+        let double_speed = 178_977;
+        assert!((double_speed..double_speed + 8).contains(&turbo.cpu.cycles));
+        // 50ms is 3 frames, twice that is 6
+        assert_eq!(normal.cpu.bus.ppu.frame_count, 3);
+        assert_eq!(turbo.cpu.bus.ppu.frame_count, 6);
+
+        let mut slow = test_nes();
+        slow.speed_multiplier = 0.5;
+        assert!(slow.run_for(duration));
+        assert!((full_speed / 2..full_speed / 2 + 8).contains(&slow.cpu.cycles));
+    }
+
+    #[test]
+    fn test_run_one_frame_fills_audio_buffer() {
+        let mut nes = test_nes();
+        nes.set_audio_latency(4096);
+        nes.run_one_frame();
+        // 44.1kHz at 60 frames a second
+        let mut out = vec![0.0; 1000];
+        let drained = nes.drain_audio(&mut out);
+        assert!((730..740).contains(&drained), "{}", drained);
+
+        nes.set_audio_latency(100);
+        nes.run_one_frame();
+        assert_eq!(nes.drain_audio(&mut out), 100);
+    }
+
+    #[test]
+    fn test_movie_replays_identically() {
+        let input = [false, true, true, false, true, false];
+
+        let mut nes = test_nes();
+        nes.start_recording();
+        let mut recorded = vec![];
+        for pressed in input {
+            nes.joypad1().set_button_pressed_status(JoypadButton::BUTTON_A, pressed);
+            nes.run_one_frame();
+            recorded.push(nes.frame().crc32());
+        }
+        let movie = nes.stop_recording();
+        assert_eq!(movie.len(), input.len());
+        // the input is visible in the picture
+        assert_ne!(recorded[0], recorded[1]);
+
+        let mut replay = test_nes();
+        replay.play(movie);
+        let mut replayed = vec![];
+        for _ in input {
+            replay.run_one_frame();
+            replayed.push(replay.frame().crc32());
+        }
+        assert_eq!(replayed, recorded);
+    }
+
+    #[test]
+    fn test_new_from_bytes_in_memory() {
+        // an NROM image built in memory: LDA #$2A; STA $10; spin
+        let mut image = vec![0x4e, 0x45, 0x53, 0x1a, 0x02, 0x01, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0, 0];
+        let mut prg = vec![0; 0x8000];
+        prg[..7].copy_from_slice(&[0xa9, 0x2a, 0x85, 0x10, 0x4c, 0x04, 0x80]);
+        prg[0x7ffc..0x7ffe].copy_from_slice(&[0x00, 0x80]);
+        image.extend(prg);
+        image.extend(vec![0; 0x2000]);
+
+        let mut nes = NES::new(&image).unwrap();
+        assert!(nes.run_one_frame());
+        assert_eq!(nes.cpu.peek(0x10), 0x2a);
+        let mut audio = [0.0; 16];
+        assert_eq!(nes.drain_audio(&mut audio), 16);
+
+        // mapper 1 isn't implemented
+        image[6] = 0x10;
+        assert_eq!(NES::new(&image).err(), Some(RomError::UnsupportedMapper(1)));
+        assert!(NES::new(&image[..8]).is_err());
+    }
+
+    #[test]
+    fn test_chr_ram_cartridge_renders() {
+        // uploads a solid tile 0 into CHR-RAM, makes its color 1 $16 and turns the background on
+        let mut program = vec![0xa9, 0x00, 0x8d, 0x06, 0x20, 0x8d, 0x06, 0x20, 0xa9, 0xff];
+        for _ in 0..8 {
+            program.extend([0x8d, 0x07, 0x20]);
+        }
+        program.extend([
+            0xa9, 0x3f, 0x8d, 0x06, 0x20, 0xa9, 0x01, 0x8d, 0x06, 0x20, 0xa9, 0x16, 0x8d, 0x07, 0x20,
+            0xa9, 0x00, 0x8d, 0x06, 0x20, 0x8d, 0x06, 0x20,
+            0xa9, 0x0a, 0x8d, 0x01, 0x20,
+        ]);
+        let spin = 0x8000 + program.len() as u16;
+        program.extend([0x4c, spin as u8, (spin >> 8) as u8]);
+
+        // no CHR banks in the header
+        let mut image = vec![0x4e, 0x45, 0x53, 0x1a, 0x02, 0x00, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0, 0];
+        let mut prg = vec![0; 0x8000];
+        prg[..program.len()].copy_from_slice(&program);
+        prg[0x7ffc..0x7ffe].copy_from_slice(&[0x00, 0x80]);
+        image.extend(prg);
+
+        let mut nes = NES::new(&image).unwrap();
+        assert!(nes.run_one_frame());
+        assert!(nes.run_one_frame());
+        let color = crate::render::palette::SYSTEM_PALETTE[0x16];
+        assert_eq!(nes.frame().get_pixel(0, 0), color);
+        assert_eq!(nes.frame().get_pixel(255, 239), color);
+    }
+
+    #[test]
+    fn test_boot_stub_runs_before_cartridge() {
+        // the cartridge starts with a NOP at $8000, the stub loads X and Y
+        let rom = || test::test_rom_with_program(&[0xea], 0x8000);
+        let mut nes = NES::with_boot_stub(rom(), Some(vec![0xa0, 0x42, 0xa2, 0x07]));
+        assert_eq!(nes.cpu.program_counter, 0x8000);
+        assert_eq!((nes.cpu.register_x, nes.cpu.register_y), (0x07, 0x42));
+        // LDY, LDX and the JMP into the cartridge
+        assert_eq!(nes.cpu.cycles, 2 + 2 + 5);
+        // the cartridge shows through again
+        assert_eq!(nes.cpu.peek(0xfff3), 0x00);
+
+        nes.cpu.register_y = 0;
+        nes.reset();
+        assert_eq!((nes.cpu.program_counter, nes.cpu.register_y), (0x8000, 0x42));
+
+        let nes = NES::from_rom(rom());
+        assert_eq!((nes.cpu.program_counter, nes.cpu.register_y, nes.cpu.cycles), (0x8000, 0, 0));
+    }
+
+    #[test]
+    fn test_power_cycle_matches_fresh_console() {
+        let mut nes = test_nes();
+        nes.run_one_frame();
+        nes.run_one_frame();
+        nes.cpu.mem_write(0x0300, 0xaa);
+        nes.cpu.bus.ppu.vram[0x10] = 0x33;
+        nes.cpu.bus.ppu.oam_write(4, 0x77);
+        nes.cpu.bus.ppu.palette_table[1] = 0x21;
+
+        nes.power_cycle();
+        let fresh = test_nes();
+        assert_eq!(nes.save_state(), fresh.save_state());
+        assert_eq!(nes.cpu.bus.ppu.frame_count, 0);
+        assert_eq!(nes.frame().crc32(), fresh.frame().crc32());
+
+        // and it runs the same from there
+        nes.run_one_frame();
+        assert_eq!(nes.cpu.mem_read(0x10), 1);
+    }
+
+    #[test]
+    fn test_save_and_load_state() {
+        let mut nes = test_nes();
+        nes.joypad1().set_button_pressed_status(JoypadButton::BUTTON_A, true);
+        nes.run_one_frame();
+        let state = nes.save_state();
+        let frame = nes.frame().crc32();
+
+        nes.run_one_frame();
+        nes.run_one_frame();
+        assert_eq!(nes.cpu.mem_read(0x10), 3);
+        let ahead = (nes.save_state(), nes.frame().crc32());
+
+        nes.load_state(&state);
+        assert_eq!(nes.save_state(), state);
+        assert_eq!(nes.cpu.bus.ppu.frame_count, 1);
+        assert_eq!(nes.frame().crc32(), frame);
+
+        // and it plays out the same from there
+        nes.run_one_frame();
+        nes.run_one_frame();
+        assert_eq!((nes.save_state(), nes.frame().crc32()), ahead);
+    }
+
+    #[test]
+    fn test_cartridge_info() {
+        // mapper 4, battery, vertical mirroring, PAL, 2 x 16KB PRG, 1 x 8KB CHR
+        let mut raw = vec![0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x43, 0x00, 00, 0x01, 00, 00, 00, 00, 00, 00];
+        raw.extend(vec![0; 0x8000 + 0x2000]);
+        let nes = NES::from_rom(Rom::from_bytes(&raw).unwrap());
+
+        assert_eq!(
+            nes.cartridge_info(),
+            CartridgeInfo {
+                mapper: 4,
+                mapper_name: "MMC3",
+                mirroring: Mirroring::VERTICAL,
+                has_battery: true,
+                prg_rom_size: 0x8000,
+                chr_rom_size: 0x2000,
+                region: Region::PAL,
+            }
+        );
+    }
+}
@@ -92,25 +92,25 @@ lazy_static! {
         OpCode::new(0x11, "ORA", 2, 5/*+1 if page crossed*/, AddressingMode::Indirect_Y),
        /////////////////////////////////////SHIFT/UNSHIFT
         //// ASL
-        OpCode::new(0x0a, "ASL", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0x0a, "ASL", 1, 2, AddressingMode::Accumulator),
         OpCode::new(0x06, "ASL", 2, 5, AddressingMode::ZeroPage),
         OpCode::new(0x16, "ASL", 2, 6, AddressingMode::ZeroPage_X),
         OpCode::new(0x0e, "ASL", 3, 6, AddressingMode::Absolute),
         OpCode::new(0x1e, "ASL", 3, 7, AddressingMode::Absolute_X),
         //// LSR
-        OpCode::new(0x4a, "LSR", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0x4a, "LSR", 1, 2, AddressingMode::Accumulator),
         OpCode::new(0x46, "LSR", 2, 5, AddressingMode::ZeroPage),
         OpCode::new(0x56, "LSR", 2, 6, AddressingMode::ZeroPage_X),
         OpCode::new(0x4e, "LSR", 3, 6, AddressingMode::Absolute),
         OpCode::new(0x5e, "LSR", 3, 7, AddressingMode::Absolute_X),
         //// ROL
-        OpCode::new(0x2a, "ROL", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0x2a, "ROL", 1, 2, AddressingMode::Accumulator),
         OpCode::new(0x26, "ROL", 2, 5, AddressingMode::ZeroPage),
         OpCode::new(0x36, "ROL", 2, 6, AddressingMode::ZeroPage_X),
         OpCode::new(0x2e, "ROL", 3, 6, AddressingMode::Absolute),
         OpCode::new(0x3e, "ROL", 3, 7, AddressingMode::Absolute_X),
         //// ROR
-        OpCode::new(0x6a, "ROR", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0x6a, "ROR", 1, 2, AddressingMode::Accumulator),
         OpCode::new(0x66, "ROR", 2, 5, AddressingMode::ZeroPage),
         OpCode::new(0x76, "ROR", 2, 6, AddressingMode::ZeroPage_X),
         OpCode::new(0x6e, "ROR", 3, 6, AddressingMode::Absolute),
@@ -153,7 +153,7 @@ lazy_static! {
        /////////////////////////////////////FLOW CONTROL
         ////JMP
         OpCode::new(0x4c, "JMP", 3, 3, AddressingMode::NoneAddressing), //AddressingMode that acts as Immediate
-        OpCode::new(0x6c, "JMP", 3, 5, AddressingMode::NoneAddressing), //AddressingMode:Indirect with 6502 bug
+        OpCode::new(0x6c, "JMP", 3, 5, AddressingMode::Indirect), // with the 6502 page boundary bug
         //// JSR
         OpCode::new(0x20, "JSR", 3, 6, AddressingMode::NoneAddressing),
         //// RTS
@@ -161,14 +161,14 @@ lazy_static! {
         //// RTI
         OpCode::new(0x40, "RTI", 1, 6, AddressingMode::NoneAddressing),
         //// BRANCH
-        OpCode::new(0xd0, "BNE", 2, 2 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::NoneAddressing),
-        OpCode::new(0x70, "BVS", 2, 2 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::NoneAddressing),
-        OpCode::new(0x50, "BVC", 2, 2 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::NoneAddressing),
-        OpCode::new(0x30, "BMI", 2, 2 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::NoneAddressing),
-        OpCode::new(0xf0, "BEQ", 2, 2 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::NoneAddressing),
-        OpCode::new(0xb0, "BCS", 2, 2 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::NoneAddressing),
-        OpCode::new(0x90, "BCC", 2, 2 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::NoneAddressing),
-        OpCode::new(0x10, "BPL", 2, 2 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::NoneAddressing),
+        OpCode::new(0xd0, "BNE", 2, 2 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::Relative),
+        OpCode::new(0x70, "BVS", 2, 2 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::Relative),
+        OpCode::new(0x50, "BVC", 2, 2 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::Relative),
+        OpCode::new(0x30, "BMI", 2, 2 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::Relative),
+        OpCode::new(0xf0, "BEQ", 2, 2 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::Relative),
+        OpCode::new(0xb0, "BCS", 2, 2 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::Relative),
+        OpCode::new(0x90, "BCC", 2, 2 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::Relative),
+        OpCode::new(0x10, "BPL", 2, 2 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::Relative),
         //// BIT
         OpCode::new(0x24, "BIT", 2, 3, AddressingMode::ZeroPage),
         OpCode::new(0x2c, "BIT", 3, 4, AddressingMode::Absolute),
@@ -321,18 +321,18 @@ lazy_static! {
         OpCode::new(0xe3, "*ISB", 2,8, AddressingMode::Indirect_X),
         OpCode::new(0xf3, "*ISB", 2,8, AddressingMode::Indirect_Y),
 
-        OpCode::new(0x02, "*NOP", 1,2, AddressingMode::NoneAddressing),
-        OpCode::new(0x12, "*NOP", 1,2, AddressingMode::NoneAddressing),
-        OpCode::new(0x22, "*NOP", 1,2, AddressingMode::NoneAddressing),
-        OpCode::new(0x32, "*NOP", 1,2, AddressingMode::NoneAddressing),
-        OpCode::new(0x42, "*NOP", 1,2, AddressingMode::NoneAddressing),
-        OpCode::new(0x52, "*NOP", 1,2, AddressingMode::NoneAddressing),
-        OpCode::new(0x62, "*NOP", 1,2, AddressingMode::NoneAddressing),
-        OpCode::new(0x72, "*NOP", 1,2, AddressingMode::NoneAddressing),
-        OpCode::new(0x92, "*NOP", 1,2, AddressingMode::NoneAddressing),
-        OpCode::new(0xb2, "*NOP", 1,2, AddressingMode::NoneAddressing),
-        OpCode::new(0xd2, "*NOP", 1,2, AddressingMode::NoneAddressing),
-        OpCode::new(0xf2, "*NOP", 1,2, AddressingMode::NoneAddressing),
+        OpCode::new(0x02, "*KIL", 1,2, AddressingMode::NoneAddressing),
+        OpCode::new(0x12, "*KIL", 1,2, AddressingMode::NoneAddressing),
+        OpCode::new(0x22, "*KIL", 1,2, AddressingMode::NoneAddressing),
+        OpCode::new(0x32, "*KIL", 1,2, AddressingMode::NoneAddressing),
+        OpCode::new(0x42, "*KIL", 1,2, AddressingMode::NoneAddressing),
+        OpCode::new(0x52, "*KIL", 1,2, AddressingMode::NoneAddressing),
+        OpCode::new(0x62, "*KIL", 1,2, AddressingMode::NoneAddressing),
+        OpCode::new(0x72, "*KIL", 1,2, AddressingMode::NoneAddressing),
+        OpCode::new(0x92, "*KIL", 1,2, AddressingMode::NoneAddressing),
+        OpCode::new(0xb2, "*KIL", 1,2, AddressingMode::NoneAddressing),
+        OpCode::new(0xd2, "*KIL", 1,2, AddressingMode::NoneAddressing),
+        OpCode::new(0xf2, "*KIL", 1,2, AddressingMode::NoneAddressing),
 
         OpCode::new(0x1a, "*NOP", 1,2, AddressingMode::NoneAddressing),
         OpCode::new(0x3a, "*NOP", 1,2, AddressingMode::NoneAddressing),
@@ -342,15 +342,15 @@ lazy_static! {
         // OpCode::new(0xea, "NOP", 1,2, AddressingMode::NoneAddressing),
         OpCode::new(0xfa, "*NOP", 1,2, AddressingMode::NoneAddressing),
 
-        OpCode::new(0xab, "*LXA", 2, 3, AddressingMode::Immediate), //todo: highly unstable and not used
+        OpCode::new(0xab, "*LXA", 2, 2, AddressingMode::Immediate), //todo: highly unstable and not used
         //http://visual6502.org/wiki/index.php?title=6502_Opcode_8B_%28XAA,_ANE%29
-        OpCode::new(0x8b, "*XAA", 2, 3, AddressingMode::Immediate), //todo: highly unstable and not used
-        OpCode::new(0xbb, "*LAS", 3, 2, AddressingMode::Absolute_Y), //todo: highly unstable and not used
-        OpCode::new(0x9b, "*TAS", 3, 2, AddressingMode::Absolute_Y), //todo: highly unstable and not used
-        OpCode::new(0x93, "*AHX", 2, /* guess */ 8, AddressingMode::Indirect_Y), //todo: highly unstable and not used
-        OpCode::new(0x9f, "*AHX", 3, /* guess */ 4/* or 5*/, AddressingMode::Absolute_Y), //todo: highly unstable and not used
-        OpCode::new(0x9e, "*SHX", 3, /* guess */ 4/* or 5*/, AddressingMode::Absolute_Y), //todo: highly unstable and not used
-        OpCode::new(0x9c, "*SHY", 3, /* guess */ 4/* or 5*/, AddressingMode::Absolute_X), //todo: highly unstable and not used
+        OpCode::new(0x8b, "*XAA", 2, 2, AddressingMode::Immediate), //todo: highly unstable and not used
+        OpCode::new(0xbb, "*LAS", 3, 4/*+1 if page crossed*/, AddressingMode::Absolute_Y), //todo: highly unstable and not used
+        OpCode::new(0x9b, "*TAS", 3, 5, AddressingMode::Absolute_Y), //todo: highly unstable and not used
+        OpCode::new(0x93, "*AHX", 2, 6, AddressingMode::Indirect_Y), //todo: highly unstable and not used
+        OpCode::new(0x9f, "*AHX", 3, 5, AddressingMode::Absolute_Y), //todo: highly unstable and not used
+        OpCode::new(0x9e, "*SHX", 3, 5, AddressingMode::Absolute_Y), //todo: highly unstable and not used
+        OpCode::new(0x9c, "*SHY", 3, 5, AddressingMode::Absolute_X), //todo: highly unstable and not used
 
         OpCode::new(0xa7, "*LAX", 2, 3, AddressingMode::ZeroPage),
         OpCode::new(0xb7, "*LAX", 2, 4, AddressingMode::ZeroPage_Y),
@@ -374,4 +374,146 @@ lazy_static! {
         }
         map
     };
-}
\ No newline at end of file
+}
+
+/// Every opcode the CPU can decode, official and unofficial.
+pub fn all_opcodes() -> &'static [OpCode] {
+    &CPU_OP_CODES
+}
+
+/// The documented 6502 instruction set, i.e. every opcode whose abbreviation is not `*`-prefixed.
+pub fn official_opcodes() -> impl Iterator<Item = &'static OpCode> {
+    all_opcodes().iter().filter(|op| !op.abbreviation.starts_with('*'))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Base cycle count of every opcode, before page cross and branch penalties.
+    /// Row $x0-$xF per line, the KIL opcodes are listed with the 2 cycles they take to fetch.
+    #[rustfmt::skip]
+    const REFERENCE_CYCLES: [u8; 256] = [
+        7, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 4, 4, 6, 6, // $0x
+        2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // $1x
+        6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 4, 4, 6, 6, // $2x
+        2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // $3x
+        6, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 3, 4, 6, 6, // $4x
+        2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // $5x
+        6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 5, 4, 6, 6, // $6x
+        2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // $7x
+        2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4, // $8x
+        2, 6, 2, 6, 4, 4, 4, 4, 2, 5, 2, 5, 5, 5, 5, 5, // $9x
+        2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4, // $Ax
+        2, 5, 2, 5, 4, 4, 4, 4, 2, 4, 2, 4, 4, 4, 4, 4, // $Bx
+        2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6, // $Cx
+        2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // $Dx
+        2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6, // $Ex
+        2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // $Fx
+    ];
+
+    #[test]
+    fn test_cycles_match_reference() {
+        let mismatches: Vec<String> = CPU_OP_CODES
+            .iter()
+            .filter(|op| op.cycles != REFERENCE_CYCLES[op.opcode as usize])
+            .map(|op| {
+                format!("{:02X} {} has {} cycles, expected {}", op.opcode, op.abbreviation, op.cycles, REFERENCE_CYCLES[op.opcode as usize])
+            })
+            .collect();
+        assert!(mismatches.is_empty(), "{:#?}", mismatches);
+    }
+
+    /// Opcode byte plus the operand bytes the addressing mode reads.
+    /// `NoneAddressing` covers implied and JMP/JSR's address, told apart by mnemonic.
+    fn expected_length(op: &OpCode) -> u8 {
+        match op.mode {
+            AddressingMode::Immediate
+            | AddressingMode::ZeroPage
+            | AddressingMode::ZeroPage_X
+            | AddressingMode::ZeroPage_Y
+            | AddressingMode::Indirect_X
+            | AddressingMode::Indirect_Y
+            | AddressingMode::Relative => 2,
+            AddressingMode::Absolute
+            | AddressingMode::Absolute_X
+            | AddressingMode::Absolute_Y
+            | AddressingMode::Indirect => 3,
+            AddressingMode::Accumulator => 1,
+            AddressingMode::NoneAddressing => match op.abbreviation {
+                "JMP" | "JSR" => 3,
+                _ => 1,
+            },
+        }
+    }
+
+    #[test]
+    fn test_length_matches_addressing_mode() {
+        let mismatches: Vec<String> = CPU_OP_CODES
+            .iter()
+            .filter(|op| op.length != expected_length(op))
+            .map(|op| {
+                format!("{:02X} {} {:?} has length {}, expected {}", op.opcode, op.abbreviation, op.mode, op.length, expected_length(op))
+            })
+            .collect();
+        assert!(mismatches.is_empty(), "{:#?}", mismatches);
+    }
+
+    #[test]
+    fn test_accumulator_mode() {
+        for code in [0x0a, 0x4a, 0x2a, 0x6a] {
+            assert_eq!(OPCODE_MAP[&code].mode, AddressingMode::Accumulator, "{:02X}", code);
+        }
+        assert_eq!(CPU_OP_CODES.iter().filter(|op| op.mode == AddressingMode::Accumulator).count(), 4);
+    }
+
+    #[test]
+    fn test_relative_mode() {
+        for code in [0x10, 0x30, 0x50, 0x70, 0x90, 0xb0, 0xd0, 0xf0] {
+            assert_eq!(OPCODE_MAP[&code].mode, AddressingMode::Relative, "{:02X}", code);
+        }
+        assert_eq!(CPU_OP_CODES.iter().filter(|op| op.mode == AddressingMode::Relative).count(), 8);
+    }
+
+    #[test]
+    fn test_indirect_mode() {
+        assert_eq!(OPCODE_MAP[&0x6c].mode, AddressingMode::Indirect);
+        assert_eq!(CPU_OP_CODES.iter().filter(|op| op.mode == AddressingMode::Indirect).count(), 1);
+    }
+
+    #[test]
+    fn test_no_duplicate_opcodes() {
+        let mut seen: HashMap<u8, &str> = HashMap::new();
+        let mut duplicates = Vec::new();
+        for op in CPU_OP_CODES.iter() {
+            if let Some(first) = seen.insert(op.opcode, op.abbreviation) {
+                duplicates.push(format!("{:02X} is both {} and {}", op.opcode, first, op.abbreviation));
+            }
+        }
+        assert!(duplicates.is_empty(), "{:#?}", duplicates);
+        assert_eq!(OPCODE_MAP.len(), CPU_OP_CODES.len());
+    }
+
+    #[test]
+    fn test_every_byte_decodes() {
+        let missing: Vec<String> = (0..=255u8)
+            .filter(|code| !OPCODE_MAP.contains_key(code))
+            .map(|code| format!("{:02X}", code))
+            .collect();
+        assert!(missing.is_empty(), "no opcode for {}", missing.join(" "));
+    }
+
+    #[test]
+    fn test_all_opcodes_covers_the_table() {
+        assert_eq!(all_opcodes().len(), CPU_OP_CODES.len());
+    }
+
+    #[test]
+    fn test_official_opcodes() {
+        assert_eq!(official_opcodes().count(), 151);
+        // *LAX zero page
+        assert!(official_opcodes().all(|op| op.opcode != 0xa7));
+        // LDA immediate
+        assert!(official_opcodes().any(|op| op.opcode == 0xa9));
+    }
+}
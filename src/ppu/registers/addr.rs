@@ -0,0 +1,60 @@
+/// # Address Register (PPUADDR) https://www.nesdev.org/wiki/PPU_registers#PPUADDR
+/// The 14 bit VRAM address used by PPUDATA, written as two bytes, high byte first.
+/// Which half a write lands in is decided by the PPU's shared write toggle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AddrRegister {
+    value: (u8, u8), // (HIGH BYTE, LOW BYTE)
+}
+
+impl AddrRegister {
+    pub fn new() -> Self {
+        AddrRegister { value: (0, 0) }
+    }
+
+    fn set(&mut self, data: u16) {
+        self.value.0 = (data >> 8) as u8;
+        self.value.1 = (data & 0xff) as u8;
+    }
+
+    /// Writes one half of the address.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The byte written to $2006.
+    /// * `high_byte` - Whether this is the first write of the pair.
+    pub fn update(&mut self, data: u8, high_byte: bool) {
+        if high_byte {
+            self.value.0 = data;
+        } else {
+            self.value.1 = data;
+        }
+
+        if self.get() > 0x3fff {
+            //mirror down addr above 0x3fff
+            self.set(self.get() & 0b11111111111111);
+        }
+    }
+
+    /// Advances the address after a PPUDATA access, wrapping within the 14 bit space.
+    pub fn increment(&mut self, inc: u8) {
+        let lo = self.value.1;
+        self.value.1 = self.value.1.wrapping_add(inc);
+        if lo > self.value.1 {
+            self.value.0 = self.value.0.wrapping_add(1);
+        }
+        if self.get() > 0x3fff {
+            self.set(self.get() & 0b11111111111111);
+        }
+    }
+
+    pub fn get(&self) -> u16 {
+        ((self.value.0 as u16) << 8) | (self.value.1 as u16)
+    }
+}
+
+impl Default for AddrRegister {
+    fn default() -> Self {
+        AddrRegister::new()
+    }
+}
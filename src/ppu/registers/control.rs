@@ -0,0 +1,97 @@
+bitflags! {
+    /// # Controller Register (PPUCTRL) https://www.nesdev.org/wiki/PPU_registers#PPUCTRL
+    ///
+    ///  7 6 5 4 3 2 1 0
+    ///  V P H B S I N N
+    ///  | | | | | | +-+--- Base nametable address
+    ///  | | | | | |        (0 = $2000; 1 = $2400; 2 = $2800; 3 = $2C00)
+    ///  | | | | | +------- VRAM address increment per CPU read/write of PPUDATA
+    ///  | | | | |          (0: add 1, going across; 1: add 32, going down)
+    ///  | | | | +--------- Sprite pattern table address for 8x8 sprites
+    ///  | | | |            (0: $0000; 1: $1000; ignored in 8x16 mode)
+    ///  | | | +----------- Background pattern table address (0: $0000; 1: $1000)
+    ///  | | +------------- Sprite size (0: 8x8 pixels; 1: 8x16 pixels)
+    ///  | +--------------- PPU master/slave select
+    ///  |                  (0: read backdrop from EXT pins; 1: output color on EXT pins)
+    ///  +----------------- Generate an NMI at the start of the vertical blanking interval (0: off; 1: on)
+    ///
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct ControlRegister: u8 {
+        const NAMETABLE1              = 0b00000001;
+        const NAMETABLE2              = 0b00000010;
+        const VRAM_ADD_INCREMENT      = 0b00000100;
+        const SPRITE_PATTERN_ADDR     = 0b00001000;
+        const BACKROUND_PATTERN_ADDR  = 0b00010000;
+        const SPRITE_SIZE             = 0b00100000;
+        const MASTER_SLAVE_SELECT     = 0b01000000;
+        const GENERATE_NMI            = 0b10000000;
+    }
+}
+
+impl ControlRegister {
+    pub fn new() -> Self {
+        ControlRegister::from_bits_truncate(0b00000000)
+    }
+
+    /// Base address of the nametable selected by bits 0-1.
+    pub fn nametable_addr(&self) -> u16 {
+        match self.bits() & 0b11 {
+            0 => 0x2000,
+            1 => 0x2400,
+            2 => 0x2800,
+            3 => 0x2c00,
+            _ => unreachable!(),
+        }
+    }
+
+    /// How far PPUADDR advances after each PPUDATA access.
+    pub fn vram_addr_increment(&self) -> u8 {
+        if !self.contains(ControlRegister::VRAM_ADD_INCREMENT) {
+            1
+        } else {
+            32
+        }
+    }
+
+    /// Pattern table used by 8x8 sprites.
+    pub fn sprt_pattern_addr(&self) -> u16 {
+        if !self.contains(ControlRegister::SPRITE_PATTERN_ADDR) {
+            0
+        } else {
+            0x1000
+        }
+    }
+
+    /// Pattern table used by the background.
+    pub fn bknd_pattern_addr(&self) -> u16 {
+        if !self.contains(ControlRegister::BACKROUND_PATTERN_ADDR) {
+            0
+        } else {
+            0x1000
+        }
+    }
+
+    /// Sprite height in pixels (8 or 16).
+    pub fn sprite_size(&self) -> u8 {
+        if !self.contains(ControlRegister::SPRITE_SIZE) {
+            8
+        } else {
+            16
+        }
+    }
+
+    pub fn generate_vblank_nmi(&self) -> bool {
+        self.contains(ControlRegister::GENERATE_NMI)
+    }
+
+    pub fn update(&mut self, data: u8) {
+        *self = ControlRegister::from_bits_truncate(data);
+    }
+}
+
+impl Default for ControlRegister {
+    fn default() -> Self {
+        ControlRegister::new()
+    }
+}
@@ -0,0 +1,5 @@
+pub mod addr;
+pub mod control;
+pub mod mask;
+pub mod scroll;
+pub mod status;
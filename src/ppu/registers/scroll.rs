@@ -0,0 +1,37 @@
+/// # Scroll Register (PPUSCROLL) https://www.nesdev.org/wiki/PPU_registers#PPUSCROLL
+/// Written twice per frame: X scroll first, then Y scroll, as decided by the PPU's shared write toggle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScrollRegister {
+    pub scroll_x: u8,
+    pub scroll_y: u8,
+}
+
+impl ScrollRegister {
+    pub fn new() -> Self {
+        ScrollRegister {
+            scroll_x: 0,
+            scroll_y: 0,
+        }
+    }
+
+    /// Writes one of the two scroll offsets.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The byte written to $2005.
+    /// * `first_write` - Whether this is the first write of the pair (X scroll).
+    pub fn write(&mut self, data: u8, first_write: bool) {
+        if first_write {
+            self.scroll_x = data;
+        } else {
+            self.scroll_y = data;
+        }
+    }
+}
+
+impl Default for ScrollRegister {
+    fn default() -> Self {
+        ScrollRegister::new()
+    }
+}
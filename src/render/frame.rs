@@ -0,0 +1,326 @@
+use crate::checksum;
+use std::{io, path::Path};
+
+/// Pixels covered by one character of the `to_ansi` preview.
+const ANSI_CELL_WIDTH: usize = 4;
+const ANSI_CELL_HEIGHT: usize = 8;
+const ANSI_COLUMNS: usize = Frame::WIDTH / ANSI_CELL_WIDTH;
+const ANSI_ROWS: usize = Frame::HEIGHT / ANSI_CELL_HEIGHT;
+/// How many differing pixels `diff` lists individually.
+const DIFF_SAMPLES: usize = 8;
+
+/// A rendered 256x240 picture, stored as tightly packed RGB bytes.
+pub struct Frame {
+    data: Vec<u8>,
+}
+
+impl Frame {
+    pub const WIDTH: usize = 256;
+    pub const HEIGHT: usize = 240;
+
+    pub fn new() -> Self {
+        Frame {
+            data: vec![0; Frame::WIDTH * Frame::HEIGHT * 3],
+        }
+    }
+
+    /// Width and height in pixels.
+    pub fn dimensions() -> (usize, usize) {
+        (Frame::WIDTH, Frame::HEIGHT)
+    }
+
+    /// The pixels as tightly packed RGB bytes, row-major from the top row down:
+    /// pixel (x, y) starts at `(y * WIDTH + x) * 3`. Ready to upload as an RGB24 texture.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Colors a single pixel, writes outside the visible area are dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - Column, 0 being the left edge.
+    /// * `y` - Row, 0 being the top edge.
+    /// * `rgb` - The color to paint.
+    pub fn set_pixel(&mut self, x: usize, y: usize, rgb: (u8, u8, u8)) {
+        let base = y * 3 * Frame::WIDTH + x * 3;
+        if x < Frame::WIDTH && base + 2 < self.data.len() {
+            self.data[base] = rgb.0;
+            self.data[base + 1] = rgb.1;
+            self.data[base + 2] = rgb.2;
+        }
+    }
+
+    /// Returns the color of a single pixel.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - Column, 0 being the left edge.
+    /// * `y` - Row, 0 being the top edge.
+    pub fn get_pixel(&self, x: usize, y: usize) -> (u8, u8, u8) {
+        let base = y * 3 * Frame::WIDTH + x * 3;
+        (self.data[base], self.data[base + 1], self.data[base + 2])
+    }
+
+    /// Encodes the frame as an 8 bit RGB PNG.
+    ///
+    /// The image data is stored in uncompressed deflate blocks, which keeps the encoder tiny
+    /// at the cost of file size (~180KB per frame).
+    pub fn encode_png(&self) -> Vec<u8> {
+        // every scanline is prefixed with filter type 0 (none)
+        let mut raw = Vec::with_capacity(Frame::HEIGHT * (Frame::WIDTH * 3 + 1));
+        for row in self.data.chunks(Frame::WIDTH * 3) {
+            raw.push(0);
+            raw.extend_from_slice(row);
+        }
+
+        // zlib stream: header, stored blocks of at most 65535 bytes, adler-32 trailer
+        let mut zlib = vec![0x78, 0x01];
+        let blocks = raw.chunks(0xffff).count();
+        for (i, block) in raw.chunks(0xffff).enumerate() {
+            let len = block.len() as u16;
+            zlib.push((i + 1 == blocks) as u8);
+            zlib.extend_from_slice(&len.to_le_bytes());
+            zlib.extend_from_slice(&(!len).to_le_bytes());
+            zlib.extend_from_slice(block);
+        }
+        zlib.extend_from_slice(&checksum::adler32(&raw).to_be_bytes());
+
+        let mut ihdr = vec![];
+        ihdr.extend_from_slice(&(Frame::WIDTH as u32).to_be_bytes());
+        ihdr.extend_from_slice(&(Frame::HEIGHT as u32).to_be_bytes());
+        // bit depth 8, color type 2 (RGB), default compression, filtering and no interlace
+        ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+        let mut png = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+        for (kind, data) in [(b"IHDR", ihdr), (b"IDAT", zlib), (b"IEND", vec![])] {
+            png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            let start = png.len();
+            png.extend_from_slice(kind);
+            png.extend_from_slice(&data);
+            let crc = checksum::crc32(&png[start..]);
+            png.extend_from_slice(&crc.to_be_bytes());
+        }
+        png
+    }
+
+    /// Writes the frame to a PNG file, for screenshots and golden images.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file to create or overwrite.
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        std::fs::write(path, self.encode_png())
+    }
+
+    /// Downsamples the frame to 64x30 characters of ANSI 24-bit color for previews in a terminal.
+    ///
+    /// Each character is an upper half block painted with two pixels: the foreground takes the
+    /// upper one and the background the lower one, so every 4x8 block of pixels becomes one cell.
+    pub fn to_ansi(&self) -> String {
+        let mut out = String::new();
+        for row in 0..ANSI_ROWS {
+            let top = row * ANSI_CELL_HEIGHT;
+            let bottom = top + ANSI_CELL_HEIGHT / 2;
+            for column in 0..ANSI_COLUMNS {
+                let x = column * ANSI_CELL_WIDTH;
+                let (fr, fg, fb) = self.get_pixel(x, top);
+                let (br, bg, bb) = self.get_pixel(x, bottom);
+                out.push_str(&format!(
+                    "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m\u{2580}",
+                    fr, fg, fb, br, bg, bb
+                ));
+            }
+            out.push_str("\x1b[0m\n");
+        }
+        out
+    }
+
+    /// Compares the frame pixel by pixel against `other`, e.g. a golden frame, to pinpoint a visual regression.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The frame to compare against.
+    pub fn diff(&self, other: &Frame) -> FrameDiff {
+        let mut diff = FrameDiff { pixel_count: 0, bounding_box: None, first_pixels: Vec::new() };
+        for y in 0..Frame::HEIGHT {
+            for x in 0..Frame::WIDTH {
+                let (ours, theirs) = (self.get_pixel(x, y), other.get_pixel(x, y));
+                if ours == theirs {
+                    continue;
+                }
+                diff.pixel_count += 1;
+                diff.bounding_box = Some(match diff.bounding_box {
+                    None => (x, y, x, y),
+                    Some((left, top, right, bottom)) => (left.min(x), top.min(y), right.max(x), bottom.max(y)),
+                });
+                if diff.first_pixels.len() < DIFF_SAMPLES {
+                    diff.first_pixels.push(PixelDiff { x, y, ours, theirs });
+                }
+            }
+        }
+        diff
+    }
+
+    /// CRC-32 of the pixel data, so headless tests can compare a rendered frame against a known-good one.
+    pub fn crc32(&self) -> u32 {
+        checksum::crc32(&self.data)
+    }
+}
+
+/// One pixel two frames disagree on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PixelDiff {
+    pub x: usize,
+    pub y: usize,
+    pub ours: (u8, u8, u8),   // COLOR IN THE FRAME diff WAS CALLED ON
+    pub theirs: (u8, u8, u8), // COLOR IN THE OTHER FRAME
+}
+
+/// Where two frames differ, as returned by `Frame::diff`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameDiff {
+    /// How many pixels differ.
+    pub pixel_count: usize,
+    /// The smallest rectangle holding every differing pixel, inclusive (left, top, right, bottom).
+    /// `None` when the frames are identical.
+    pub bounding_box: Option<(usize, usize, usize, usize)>,
+    /// The first few differing pixels, row-major.
+    pub first_pixels: Vec<PixelDiff>,
+}
+
+impl FrameDiff {
+    pub fn is_empty(&self) -> bool {
+        self.pixel_count == 0
+    }
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Frame::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_data_layout() {
+        let mut frame = Frame::new();
+        assert_eq!(Frame::dimensions(), (256, 240));
+        assert_eq!(frame.data().len(), 256 * 240 * 3);
+
+        frame.set_pixel(7, 3, (1, 2, 3));
+        let offset = (3 * 256 + 7) * 3;
+        assert_eq!(&frame.data()[offset..offset + 3], &[1, 2, 3]);
+        assert_eq!(frame.data().iter().filter(|byte| **byte != 0).count(), 3);
+    }
+
+    /// Reads back the width, height and pixels of a PNG made by `encode_png`, which only uses stored blocks.
+    fn decode_png(png: &[u8]) -> (u32, u32, Vec<u8>) {
+        let (mut width, mut height, mut zlib) = (0, 0, vec![]);
+        let mut offset = 8;
+        while offset < png.len() {
+            let len = u32::from_be_bytes(png[offset..offset + 4].try_into().unwrap()) as usize;
+            let data = &png[offset + 8..offset + 8 + len];
+            match &png[offset + 4..offset + 8] {
+                b"IHDR" => {
+                    width = u32::from_be_bytes(data[0..4].try_into().unwrap());
+                    height = u32::from_be_bytes(data[4..8].try_into().unwrap());
+                }
+                b"IDAT" => zlib.extend_from_slice(data),
+                _ => {}
+            }
+            offset += len + 12;
+        }
+
+        let (mut raw, mut block) = (vec![], 2);
+        loop {
+            let last = zlib[block] & 1 == 1;
+            let len = u16::from_le_bytes([zlib[block + 1], zlib[block + 2]]) as usize;
+            raw.extend_from_slice(&zlib[block + 5..block + 5 + len]);
+            block += 5 + len;
+            if last {
+                break;
+            }
+        }
+        // drop each scanline's filter byte
+        let pixels = raw.chunks(width as usize * 3 + 1).flat_map(|row| row[1..].to_vec()).collect();
+        (width, height, pixels)
+    }
+
+    #[test]
+    fn test_save_png() {
+        let mut frame = Frame::new();
+        for y in 0..Frame::HEIGHT {
+            for x in 0..Frame::WIDTH {
+                frame.set_pixel(x, y, (0x21, 0x42, 0x84));
+            }
+        }
+        let path = std::env::temp_dir().join(format!("nes-rs-save-png-{}.png", std::process::id()));
+        frame.save_png(&path).unwrap();
+
+        let (width, height, pixels) = decode_png(&std::fs::read(&path).unwrap());
+        assert_eq!((width, height), (256, 240));
+        assert_eq!(pixels, frame.data());
+        let sample = (100 * 256 + 37) * 3;
+        assert_eq!(&pixels[sample..sample + 3], &[0x21, 0x42, 0x84]);
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(frame.save_png(std::env::temp_dir().join("no-such-directory").join("frame.png")).is_err());
+    }
+
+    #[test]
+    fn test_diff() {
+        let golden = Frame::new();
+        assert!(golden.diff(&Frame::new()).is_empty());
+        assert_eq!(golden.diff(&Frame::new()).bounding_box, None);
+
+        // a 10x5 block at (20, 30), plus a lone pixel further down and left
+        let mut frame = Frame::new();
+        for y in 30..35 {
+            for x in 20..30 {
+                frame.set_pixel(x, y, (255, 0, 0));
+            }
+        }
+        frame.set_pixel(4, 100, (0, 0, 255));
+
+        let diff = frame.diff(&golden);
+        assert_eq!(diff.pixel_count, 51);
+        assert_eq!(diff.bounding_box, Some((4, 30, 29, 100)));
+        assert_eq!(diff.first_pixels.len(), DIFF_SAMPLES);
+        assert_eq!(diff.first_pixels[0], PixelDiff { x: 20, y: 30, ours: (255, 0, 0), theirs: (0, 0, 0) });
+        assert_eq!((diff.first_pixels[7].x, diff.first_pixels[7].y), (27, 30));
+        // the other way around only swaps the colors
+        assert_eq!(golden.diff(&frame).first_pixels[0].theirs, (255, 0, 0));
+    }
+
+    #[test]
+    fn test_to_ansi_solid_frame() {
+        let mut frame = Frame::new();
+        for y in 0..Frame::HEIGHT {
+            for x in 0..Frame::WIDTH {
+                frame.set_pixel(x, y, (10, 20, 30));
+            }
+        }
+
+        let ansi = frame.to_ansi();
+        let lines: Vec<&str> = ansi.lines().collect();
+        assert_eq!(lines.len(), 30);
+        for line in lines {
+            assert_eq!(line.matches('\u{2580}').count(), 64);
+            assert_eq!(line.matches("\x1b[38;2;10;20;30m\x1b[48;2;10;20;30m").count(), 64);
+            assert!(line.ends_with("\x1b[0m"));
+        }
+    }
+
+    #[test]
+    fn test_to_ansi_splits_cell_vertically() {
+        let mut frame = Frame::new();
+        // the lower half of the first cell
+        frame.set_pixel(0, 4, (255, 0, 0));
+        let ansi = frame.to_ansi();
+        assert!(ansi.starts_with("\x1b[38;2;0;0;0m\x1b[48;2;255;0;0m\u{2580}"));
+    }
+}
@@ -0,0 +1,504 @@
+pub mod frame;
+pub mod palette;
+pub mod sink;
+
+use crate::ppu::NesPPU;
+use frame::Frame;
+
+/// Draws the current PPU state into `frame`.
+pub fn render(ppu: &NesPPU, frame: &mut Frame) {
+    let opaque = render_background(ppu, frame);
+    render_sprites(ppu, frame, &opaque);
+}
+
+/// Lays out all 256 tiles of a pattern table as a 16x16 tile sheet in the top-left 128x128 pixels of `frame`,
+/// colored with the first background palette.
+///
+/// # Arguments
+///
+/// * `table` - Which pattern table: 0 = $0000, 1 = $1000.
+pub fn render_pattern_table(ppu: &NesPPU, table: u8, frame: &mut Frame) {
+    let palette = [ppu.palette_table[0], ppu.palette_table[1], ppu.palette_table[2], ppu.palette_table[3]];
+    for index in 0..=255u8 {
+        let tile_x = index as usize % 16 * 8;
+        let tile_y = index as usize / 16 * 8;
+        for (y, row) in ppu.tile_to_pixels(table, index, &palette).iter().enumerate() {
+            for (x, color) in row.iter().enumerate() {
+                frame.set_pixel(tile_x + x, tile_y + y, palette::SYSTEM_PALETTE[*color as usize]);
+            }
+        }
+    }
+}
+
+/// Looks up the color the PPU outputs for a palette RAM entry. Grayscale (PPUMASK bit 0) only masks the
+/// lookup down to the gray column, the entries themselves stay as they are.
+///
+/// # Arguments
+///
+/// * `entry` - A palette RAM byte, an index into the system palette.
+fn output_color(ppu: &NesPPU, entry: u8) -> (u8, u8, u8) {
+    let index = if ppu.mask.is_grayscale() { entry & 0x30 } else { entry };
+    palette::SYSTEM_PALETTE[index as usize]
+}
+
+/// Draws the background line by line, each from the scroll position (`v` and fine X) the PPU
+/// had when that line started, so scroll changes made mid-frame split the picture where they happened.
+/// Uses the background pattern table selected in PPUCTRL.
+/// Unless PPUMASK enables the leftmost background pixels, columns 0-7 show the backdrop color
+/// and count as transparent.
+///
+/// Returns which pixels got a non-zero background color, row-major like the frame,
+/// so sprites behind the background know where they're covered.
+fn render_background(ppu: &NesPPU, frame: &mut Frame) -> Vec<bool> {
+    let bank = ppu.ctrl.bknd_pattern_addr();
+    let show_leftmost = ppu.mask.leftmost_8pxl_background();
+    let backdrop = output_color(ppu, ppu.palette_table[0]);
+    let mut opaque = vec![false; Frame::WIDTH * Frame::HEIGHT];
+
+    for pixel_y in 0..Frame::HEIGHT {
+        let (v, fine_x) = ppu.line_scroll(pixel_y);
+        let fine_y = (v >> 12) as usize & 0b111;
+        let tile_row = (v >> 5) as usize & 0b11111;
+        let first_x = (v as usize & 0b11111) * 8 + fine_x as usize;
+
+        for pixel_x in 0..Frame::WIDTH {
+            if pixel_x < 8 && !show_leftmost {
+                frame.set_pixel(pixel_x, pixel_y, backdrop);
+                continue;
+            }
+            // the line runs on into the horizontally adjacent nametable
+            let scrolled_x = (first_x + pixel_x) % (2 * Frame::WIDTH);
+            let nametable = 0x2000 + (((v >> 10) & 0b11) ^ (scrolled_x / Frame::WIDTH) as u16) * 0x400;
+            let tile_column = scrolled_x % Frame::WIDTH / 8;
+            let tile_address = nametable + (tile_row * 32 + tile_column) as u16;
+            let tile_index = ppu.vram[ppu.mirror_vram_addr(tile_address) as usize] as u16;
+
+            let row_start = (bank + tile_index * 16) as usize + fine_y;
+            let value = ppu.pattern_pixel(row_start, scrolled_x % 8);
+
+            let palette = bg_palette(ppu, nametable, tile_column, tile_row);
+            let rgb = output_color(ppu, palette[value as usize]);
+            frame.set_pixel(pixel_x, pixel_y, rgb);
+            opaque[pixel_y * Frame::WIDTH + pixel_x] = value != 0;
+        }
+    }
+    opaque
+}
+
+//  Byte 0: Y position of the top of the sprite
+//  Byte 1: Tile index
+//  Byte 2: Attributes
+//      7 6 5 4 3 2 1 0
+//      V H P _ _ _ p p
+//      | | |       +-+--- Palette (4 to 7) of sprite
+//      | | +------------- Priority (0: in front of background; 1: behind background)
+//      | +--------------- Flip sprite horizontally
+//      +----------------- Flip sprite vertically
+//  Byte 3: X position of the left side of the sprite
+
+// OAM SPRITE ENTRY LAYOUT
+
+/// Draws the sprites in OAM, a line below their Y position. 8x8 sprites use the sprite pattern table
+/// selected in PPUCTRL, 8x16 sprites pick theirs with bit 0 of the tile index.
+/// Sprites aren't drawn in columns 0-7 unless PPUMASK enables the leftmost sprite pixels.
+///
+/// Where sprites overlap the lowest OAM index with an opaque pixel there wins, as on hardware.
+/// Sprites with the priority bit set only show where the background is transparent, and since a
+/// behind-background sprite still wins the overlap it hides higher sprites in front of the background.
+///
+/// # Arguments
+///
+/// * `background_opaque` - Per pixel background opacity, as returned by `render_background`.
+fn render_sprites(ppu: &NesPPU, frame: &mut Frame, background_opaque: &[bool]) {
+    let bank = ppu.ctrl.sprt_pattern_addr();
+    let show_leftmost = ppu.mask.leftmost_8pxl_sprite();
+    let height = ppu.ctrl.sprite_size() as usize;
+
+    // set once a sprite has drawn, or tried to draw behind the background, at a pixel
+    let mut claimed = vec![false; Frame::WIDTH * Frame::HEIGHT];
+
+    for i in (0..ppu.oam_data.len()).step_by(4) {
+        // sprites show up one line below their OAM Y
+        let tile_y = ppu.oam_data[i] as usize + 1;
+        let tile_index = ppu.oam_data[i + 1] as usize;
+        let attributes = ppu.oam_data[i + 2];
+        let tile_x = ppu.oam_data[i + 3] as usize;
+
+        let flip_vertical = attributes >> 7 & 1 == 1;
+        let flip_horizontal = attributes >> 6 & 1 == 1;
+        let behind_background = attributes >> 5 & 1 == 1;
+        let palette = sprite_palette(ppu, attributes & 0b11);
+
+        for row in 0..height {
+            for column in 0..8 {
+                let source_row = if flip_vertical { height - 1 - row } else { row };
+                let source_column = if flip_horizontal { 7 - column } else { column };
+                let tile_start = if height == 16 {
+                    // bit 0 picks the pattern table, the top half is the even tile and the bottom half the next one
+                    (tile_index & 1) * 0x1000 + (tile_index & 0xfe) * 16 + source_row / 8 * 16
+                } else {
+                    bank as usize + tile_index * 16
+                };
+                let value = ppu.pattern_pixel(tile_start + source_row % 8, source_column);
+
+                // color 0 is transparent for sprites
+                if value == 0 {
+                    continue;
+                }
+
+                let pixel_x = tile_x + column;
+                let pixel_y = tile_y + row;
+                if pixel_x < 8 && !show_leftmost || pixel_x >= Frame::WIDTH || pixel_y >= Frame::HEIGHT {
+                    continue;
+                }
+                let pixel = pixel_y * Frame::WIDTH + pixel_x;
+                if claimed[pixel] {
+                    continue;
+                }
+                claimed[pixel] = true;
+                if behind_background && background_opaque[pixel] {
+                    continue;
+                }
+                let rgb = output_color(ppu, palette[value as usize]);
+                frame.set_pixel(pixel_x, pixel_y, rgb);
+            }
+        }
+    }
+}
+
+/// Looks up the 4 colors a background tile may use from the nametable's attribute table.
+///
+/// Each attribute byte covers a 4x4 tile area, split into four 2x2 quadrants of 2 bits each.
+///
+/// # Arguments
+///
+/// * `nametable` - PPU address of the nametable the tile is in, $2000/$2400/$2800/$2C00.
+/// * `tile_column` - The tile's column within it.
+/// * `tile_row` - The tile's row within it.
+fn bg_palette(ppu: &NesPPU, nametable: u16, tile_column: usize, tile_row: usize) -> [u8; 4] {
+    let attr_table_idx = tile_row / 4 * 8 + tile_column / 4;
+    let attr_byte = ppu.vram[ppu.mirror_vram_addr(nametable + 0x3c0 + attr_table_idx as u16) as usize];
+
+    let palette_idx = match (tile_column % 4 / 2, tile_row % 4 / 2) {
+        (0, 0) => attr_byte & 0b11,
+        (1, 0) => (attr_byte >> 2) & 0b11,
+        (0, 1) => (attr_byte >> 4) & 0b11,
+        (1, 1) => (attr_byte >> 6) & 0b11,
+        (_, _) => unreachable!(),
+    };
+
+    let palette_start: usize = 1 + (palette_idx as usize) * 4;
+    [
+        ppu.palette_table[0],
+        ppu.palette_table[palette_start],
+        ppu.palette_table[palette_start + 1],
+        ppu.palette_table[palette_start + 2],
+    ]
+}
+
+/// Looks up the 3 colors of one of the 4 sprite palettes (entry 0 is transparent and never drawn).
+fn sprite_palette(ppu: &NesPPU, palette_idx: u8) -> [u8; 4] {
+    let start = 0x11 + (palette_idx as usize) * 4;
+    [
+        0,
+        ppu.palette_table[start],
+        ppu.palette_table[start + 1],
+        ppu.palette_table[start + 2],
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::Mirroring;
+    use crate::ppu::PRE_RENDER_SCANLINE;
+
+    #[test]
+    fn test_render_fixed_nametable_frame_hash() {
+        let mut ppu = NesPPU::new_empty_rom();
+        // tile 1: left half color 1, right half color 3
+        for row in 0..8 {
+            ppu.chr_rom[16 + row] = 0b1111_1111;
+            ppu.chr_rom[16 + 8 + row] = 0b0000_1111;
+        }
+        // checkerboard of tile 0 / tile 1, the top-left 2x2 tiles of every attribute block use palette 1
+        for i in 0..0x3c0 {
+            ppu.vram[i] = ((i % 32 + i / 32) % 2) as u8;
+        }
+        for i in 0x3c0..0x400 {
+            ppu.vram[i] = 0b01;
+        }
+        ppu.palette_table[..8].copy_from_slice(&[0x0f, 0x01, 0x02, 0x03, 0x0f, 0x16, 0x27, 0x30]);
+        ppu.mask.update(0b0000_0010);
+
+        let mut frame = Frame::new();
+        render(&ppu, &mut frame);
+        let hash = frame.crc32();
+
+        assert_ne!(hash, Frame::new().crc32());
+        assert_eq!(hash, 0x91e9_fce0);
+    }
+
+    /// A PPU with a solid tile 1 (color 1) everywhere in the background and as sprite 0 at x = 0.
+    fn clip_test_ppu() -> NesPPU {
+        let mut ppu = NesPPU::new_empty_rom();
+        for row in 0..8 {
+            ppu.chr_rom[16 + row] = 0xff;
+        }
+        ppu.palette_table[0x00] = 0x0f;
+        ppu.palette_table[0x01] = 0x16;
+        ppu.palette_table[0x11] = 0x2a;
+        ppu.oam_data[0..4].copy_from_slice(&[0, 1, 0, 0]);
+        for i in 0..0x3c0 {
+            ppu.vram[i] = 1;
+        }
+        ppu.oam_data[4..].fill(0xff);
+        ppu
+    }
+
+    /// A frame still holding an earlier picture, so clipped pixels can't pass by being left alone.
+    fn dirty_frame() -> Frame {
+        let mut frame = Frame::new();
+        for y in 0..Frame::HEIGHT {
+            for x in 0..Frame::WIDTH {
+                frame.set_pixel(x, y, (0xff, 0x00, 0xff));
+            }
+        }
+        frame
+    }
+
+    #[test]
+    fn test_grayscale_masks_lookup_only() {
+        let mut ppu = clip_test_ppu();
+        let palette_ram = ppu.palette_table;
+        ppu.mask.update(0b0001_1111);
+        let mut frame = Frame::new();
+        render(&ppu, &mut frame);
+
+        // background $16 and sprite $2A come out as $10 and $20
+        assert_eq!(frame.get_pixel(20, 3), palette::SYSTEM_PALETTE[0x10]);
+        assert_eq!(frame.get_pixel(3, 3), palette::SYSTEM_PALETTE[0x20]);
+        assert_eq!(ppu.palette_table, palette_ram);
+
+        ppu.mask.update(0b0001_1110);
+        render(&ppu, &mut frame);
+        assert_eq!(frame.get_pixel(20, 3), palette::SYSTEM_PALETTE[0x16]);
+        assert_eq!(frame.get_pixel(3, 3), palette::SYSTEM_PALETTE[0x2a]);
+    }
+
+    #[test]
+    fn test_left_column_hidden_when_clip_bits_clear() {
+        let mut ppu = clip_test_ppu();
+        ppu.palette_table[0] = 0x21;
+        ppu.mask.update(0b0001_1000);
+        let mut frame = dirty_frame();
+        render(&ppu, &mut frame);
+
+        // the backdrop shows through
+        assert_eq!(frame.get_pixel(3, 3), palette::SYSTEM_PALETTE[0x21]);
+        assert_eq!(frame.get_pixel(7, 7), palette::SYSTEM_PALETTE[0x21]);
+        assert_eq!(frame.get_pixel(0, 200), palette::SYSTEM_PALETTE[0x21]);
+        assert_eq!(frame.get_pixel(8, 3), palette::SYSTEM_PALETTE[0x16]);
+    }
+
+    #[test]
+    fn test_left_column_background_only() {
+        let mut ppu = clip_test_ppu();
+        ppu.mask.update(0b0001_1010);
+        let mut frame = dirty_frame();
+        render(&ppu, &mut frame);
+
+        assert_eq!(frame.get_pixel(3, 3), palette::SYSTEM_PALETTE[0x16]);
+    }
+
+    #[test]
+    fn test_left_column_sprites_only() {
+        let mut ppu = clip_test_ppu();
+        ppu.palette_table[0] = 0x21;
+        ppu.mask.update(0b0001_1100);
+        let mut frame = dirty_frame();
+        render(&ppu, &mut frame);
+
+        assert_eq!(frame.get_pixel(3, 3), palette::SYSTEM_PALETTE[0x2a]);
+        assert_eq!(frame.get_pixel(3, 9), palette::SYSTEM_PALETTE[0x21]);
+
+        // the clipped background is transparent, so a sprite behind it still shows
+        ppu.oam_data[2] = 0b0010_0000;
+        render(&ppu, &mut frame);
+        assert_eq!(frame.get_pixel(3, 3), palette::SYSTEM_PALETTE[0x2a]);
+    }
+
+    #[test]
+    fn test_left_column_shown_when_clip_bits_set() {
+        let mut ppu = clip_test_ppu();
+        ppu.mask.update(0b0001_1110);
+        let mut frame = Frame::new();
+        render(&ppu, &mut frame);
+
+        assert_eq!(frame.get_pixel(3, 3), palette::SYSTEM_PALETTE[0x2a]);
+        assert_eq!(frame.get_pixel(3, 9), palette::SYSTEM_PALETTE[0x16]);
+    }
+
+    #[test]
+    fn test_sprite_priority_against_opaque_background() {
+        let mut ppu = clip_test_ppu();
+        ppu.mask.update(0b0001_1110);
+        // the top-left tile is transparent background
+        ppu.vram[0] = 0;
+        // sprite 0 behind, sprite 1 in front, both over the opaque tile at (16, 0)
+        ppu.oam_data[0..8].copy_from_slice(&[0, 1, 0b0010_0000, 16, 0, 1, 0b0000_0001, 24]);
+        ppu.palette_table[0x15] = 0x30;
+        // sprite 2 behind, over the transparent tile
+        ppu.oam_data[8..12].copy_from_slice(&[0, 1, 0b0010_0000, 0]);
+
+        let mut frame = Frame::new();
+        render(&ppu, &mut frame);
+        assert_eq!(frame.get_pixel(19, 3), palette::SYSTEM_PALETTE[0x16]);
+        assert_eq!(frame.get_pixel(27, 3), palette::SYSTEM_PALETTE[0x30]);
+        assert_eq!(frame.get_pixel(3, 3), palette::SYSTEM_PALETTE[0x2a]);
+    }
+
+    #[test]
+    fn test_lower_oam_index_wins_overlap() {
+        let mut ppu = clip_test_ppu();
+        ppu.mask.update(0b0001_1110);
+        ppu.palette_table[0x15] = 0x30;
+        // sprite 1 (palette 5) half over sprite 2 (palette 4), and sprite 3 the other way around
+        ppu.oam_data[4..12].copy_from_slice(&[40, 1, 0b0000_0001, 40, 40, 1, 0, 44]);
+        ppu.oam_data[12..20].copy_from_slice(&[60, 1, 0, 40, 60, 1, 0b0000_0001, 44]);
+
+        let mut frame = Frame::new();
+        render(&ppu, &mut frame);
+        assert_eq!(frame.get_pixel(45, 43), palette::SYSTEM_PALETTE[0x30]);
+        assert_eq!(frame.get_pixel(50, 43), palette::SYSTEM_PALETTE[0x2a]);
+        assert_eq!(frame.get_pixel(45, 63), palette::SYSTEM_PALETTE[0x2a]);
+        assert_eq!(frame.get_pixel(50, 63), palette::SYSTEM_PALETTE[0x30]);
+    }
+
+    #[test]
+    fn test_behind_background_sprite_still_wins_overlap() {
+        let mut ppu = clip_test_ppu();
+        ppu.mask.update(0b0001_1110);
+        // sprite 1 behind the opaque background covers sprite 2 in front of it
+        ppu.oam_data[4..12].copy_from_slice(&[40, 1, 0b0010_0000, 40, 40, 1, 0, 44]);
+
+        let mut frame = Frame::new();
+        render(&ppu, &mut frame);
+        assert_eq!(frame.get_pixel(45, 43), palette::SYSTEM_PALETTE[0x16]);
+        assert_eq!(frame.get_pixel(50, 43), palette::SYSTEM_PALETTE[0x2a]);
+    }
+
+    #[test]
+    fn test_mid_frame_horizontal_scroll_change() {
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.mirroring = Mirroring::HORIZONTAL;
+        // solid tile 1 (color 1) down the first column, the blank tile 0 everywhere else
+        for row in 0..8 {
+            ppu.chr_rom[16 + row] = 0xff;
+        }
+        for row in 0..30 {
+            ppu.vram[row * 32] = 1;
+        }
+        ppu.palette_table[0x00] = 0x0f;
+        ppu.palette_table[0x01] = 0x16;
+        ppu.write_to_mask(0b0000_1010);
+
+        // run from the pre-render line to the middle of line 100, then scroll 8 pixels right
+        ppu.scanline = PRE_RENDER_SCANLINE;
+        while ppu.scanline != 100 || ppu.dot() != 128 {
+            ppu.step_dot();
+        }
+        ppu.write_to_scroll(8);
+        ppu.write_to_scroll(0);
+        while ppu.scanline < 240 {
+            ppu.step_dot();
+        }
+
+        let mut frame = Frame::new();
+        render(&ppu, &mut frame);
+        let solid = palette::SYSTEM_PALETTE[0x16];
+        let backdrop = palette::SYSTEM_PALETTE[0x0f];
+        // above the change, and the rest of line 100
+        assert_eq!(frame.get_pixel(0, 50), solid);
+        assert_eq!(frame.get_pixel(7, 100), solid);
+        assert_eq!(frame.get_pixel(248, 100), backdrop);
+        // from line 101 the first column has scrolled off to the left,
+        // and the right edge shows the first column of the next nametable
+        assert_eq!(frame.get_pixel(0, 101), backdrop);
+        assert_eq!(frame.get_pixel(248, 101), solid);
+        assert_eq!(frame.get_pixel(255, 239), solid);
+    }
+
+    #[test]
+    fn test_render_pattern_table() {
+        let mut ppu = NesPPU::new_empty_rom();
+        // tile 17 of the second table is solid color 3
+        ppu.chr_rom[0x1110..0x1120].fill(0xff);
+        ppu.palette_table[..4].copy_from_slice(&[0x0f, 0x16, 0x27, 0x30]);
+
+        let mut frame = Frame::new();
+        render_pattern_table(&ppu, 1, &mut frame);
+        // the second row of the sheet, second column
+        assert_eq!(frame.get_pixel(8, 8), palette::SYSTEM_PALETTE[0x30]);
+        assert_eq!(frame.get_pixel(15, 15), palette::SYSTEM_PALETTE[0x30]);
+        assert_eq!(frame.get_pixel(16, 8), palette::SYSTEM_PALETTE[0x0f]);
+        assert_eq!(frame.get_pixel(127, 127), palette::SYSTEM_PALETTE[0x0f]);
+        assert_eq!(frame.get_pixel(128, 0), (0, 0, 0));
+    }
+
+    #[test]
+    fn test_sprite_written_through_direct_oam_api() {
+        let mut ppu = NesPPU::new_empty_rom();
+        for row in 0..8 {
+            ppu.chr_rom[16 + row] = 0xff;
+        }
+        ppu.palette_table[0x11] = 0x2a;
+        // hide every sprite below the screen, then place sprite 5 at (40, 20), it shows from line 21
+        for index in 0..=255 {
+            ppu.oam_write(index, 0xff);
+        }
+        for (field, data) in [20, 1, 0, 40].iter().enumerate() {
+            ppu.oam_write(5 * 4 + field as u8, *data);
+        }
+
+        let mut frame = Frame::new();
+        render_sprites(&ppu, &mut frame, &vec![false; Frame::WIDTH * Frame::HEIGHT]);
+        assert_eq!(frame.get_pixel(40, 20), (0, 0, 0));
+        assert_eq!(frame.get_pixel(40, 21), palette::SYSTEM_PALETTE[0x2a]);
+        assert_eq!(frame.get_pixel(47, 28), palette::SYSTEM_PALETTE[0x2a]);
+        assert_eq!(frame.get_pixel(48, 21), (0, 0, 0));
+
+        // the register path sees the same bytes and doesn't disturb them
+        ppu.write_to_oam_addr(5 * 4);
+        assert_eq!(ppu.read_oam_data(), 20);
+        ppu.write_to_oam_data(21);
+        assert_eq!(ppu.oam_read(5 * 4), 21);
+        assert_eq!(ppu.oam_snapshot()[5 * 4 + 3], 40);
+        assert_eq!(ppu.oam_snapshot(), ppu.oam_data);
+    }
+
+    #[test]
+    fn test_8x16_sprites() {
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.write_to_ctrl(0b0010_0000);
+        // odd tile 3 picks $1000 and tiles 2 (top, color 1) and 3 (bottom, color 2) there
+        ppu.chr_rom[0x1020..0x1028].fill(0xff);
+        ppu.chr_rom[0x1038..0x1040].fill(0xff);
+        ppu.palette_table[0x11] = 0x16;
+        ppu.palette_table[0x12] = 0x2a;
+        ppu.oam_data.fill(0xff);
+        // the second one is flipped vertically, over all 16 lines
+        ppu.oam_data[0..8].copy_from_slice(&[9, 3, 0, 16, 9, 3, 0b1000_0000, 32]);
+
+        let mut frame = Frame::new();
+        render_sprites(&ppu, &mut frame, &vec![false; Frame::WIDTH * Frame::HEIGHT]);
+        assert_eq!(frame.get_pixel(16, 9), (0, 0, 0));
+        assert_eq!(frame.get_pixel(16, 10), palette::SYSTEM_PALETTE[0x16]);
+        assert_eq!(frame.get_pixel(23, 17), palette::SYSTEM_PALETTE[0x16]);
+        assert_eq!(frame.get_pixel(16, 18), palette::SYSTEM_PALETTE[0x2a]);
+        assert_eq!(frame.get_pixel(23, 25), palette::SYSTEM_PALETTE[0x2a]);
+        assert_eq!(frame.get_pixel(16, 26), (0, 0, 0));
+        assert_eq!(frame.get_pixel(32, 10), palette::SYSTEM_PALETTE[0x2a]);
+        assert_eq!(frame.get_pixel(32, 25), palette::SYSTEM_PALETTE[0x16]);
+    }
+}
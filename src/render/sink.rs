@@ -0,0 +1,78 @@
+use super::frame::Frame;
+use std::path::PathBuf;
+
+/// Where finished frames go: a window, image files, a terminal...
+pub trait VideoSink {
+    /// Called once per frame, right after it has been rendered.
+    fn present(&mut self, frame: &Frame);
+}
+
+/// Drops every frame, for headless runs.
+#[derive(Default)]
+pub struct NullSink;
+
+impl VideoSink for NullSink {
+    fn present(&mut self, _frame: &Frame) {}
+}
+
+/// Writes each frame to `<directory>/frame_00000.png`, `frame_00001.png`, ...
+pub struct PngSink {
+    directory: PathBuf,
+    next_index: usize,
+    last_error: Option<std::io::Error>,
+}
+
+impl PngSink {
+    /// # Arguments
+    ///
+    /// * `directory` - An existing directory to write the numbered PNG files into.
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        PngSink {
+            directory: directory.into(),
+            next_index: 0,
+            last_error: None,
+        }
+    }
+
+    /// Takes the error of the most recent failed write, if any.
+    pub fn take_error(&mut self) -> Option<std::io::Error> {
+        self.last_error.take()
+    }
+}
+
+impl VideoSink for PngSink {
+    fn present(&mut self, frame: &Frame) {
+        let path = self.directory.join(format!("frame_{:05}.png", self.next_index));
+        self.next_index += 1;
+        if let Err(error) = std::fs::write(path, frame.encode_png()) {
+            self.last_error = Some(error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_png_sink_writes_numbered_files() {
+        let directory = std::env::temp_dir().join(format!("nes-rs-png-sink-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let mut sink = PngSink::new(&directory);
+        let mut frame = Frame::new();
+        frame.set_pixel(1, 2, (10, 20, 30));
+        sink.present(&frame);
+        sink.present(&frame);
+        assert!(sink.take_error().is_none());
+
+        let png = std::fs::read(directory.join("frame_00001.png")).unwrap();
+        assert_eq!(&png[..8], &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a]);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(u32::from_be_bytes(png[16..20].try_into().unwrap()), 256);
+        assert_eq!(u32::from_be_bytes(png[20..24].try_into().unwrap()), 240);
+        assert_eq!(png, frame.encode_png());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
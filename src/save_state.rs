@@ -0,0 +1,207 @@
+use crate::{
+    cpu::{CpuState, CPU},
+    mapper::MapperState,
+    ppu::PpuState,
+};
+
+/// Everything needed to resume emulation from a point in time, apart from the cartridge itself.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SaveState {
+    pub cpu: CpuState,
+    pub ram: Vec<u8>,
+    pub sram: Vec<u8>,
+    pub mapper: MapperState,
+    pub ppu: PpuState,
+}
+
+impl SaveState {
+    /// Snapshots the CPU, its RAM and SRAM, the mapper's registers and the PPU.
+    ///
+    /// # Arguments
+    ///
+    /// * `cpu` - The CPU, whose bus holds the RAM, the mapper and the PPU.
+    pub fn capture(cpu: &CPU) -> Self {
+        SaveState {
+            cpu: cpu.save_state(),
+            ram: cpu.bus.ram().to_vec(),
+            sram: cpu.bus.sram().to_vec(),
+            mapper: cpu.bus.mapper_state(),
+            ppu: cpu.bus.ppu.save_state(),
+        }
+    }
+
+    /// Puts the CPU, its RAM and SRAM, the mapper's registers and the PPU back into the captured state.
+    ///
+    /// # Arguments
+    ///
+    /// * `cpu` - The CPU to restore, along with everything on its bus.
+    pub fn restore(&self, cpu: &mut CPU) {
+        cpu.load_state(&self.cpu);
+        cpu.bus.load_ram(&self.ram);
+        cpu.bus.load_sram(&self.sram);
+        cpu.bus.load_mapper_state(&self.mapper);
+        cpu.bus.ppu.load_state(&self.ppu);
+    }
+
+    /// Encodes the save state with bincode.
+    #[cfg(feature = "serde")]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("save state is always serializable")
+    }
+
+    /// Decodes a save state produced by `to_bytes`. Memories of the wrong size are a decode error,
+    /// since restoring them couldn't work.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The encoded save state.
+    #[cfg(feature = "serde")]
+    pub fn from_bytes(bytes: &[u8]) -> Result<SaveState, bincode::Error> {
+        let state: SaveState = bincode::deserialize(bytes)?;
+        let sizes = [
+            ("RAM", state.ram.len(), 0x0800, "bytes"),
+            ("SRAM", state.sram.len(), 0x2000, "bytes"),
+            ("VRAM", state.ppu.vram.len(), 0x0800, "bytes"),
+            ("OAM", state.ppu.oam_data.len(), 0x0100, "bytes"),
+            ("Line scroll", state.ppu.line_scroll.len(), 240, "lines"),
+            // only CHR-RAM cartridges keep their pattern tables in the state
+            ("CHR-RAM", state.ppu.chr_ram.len(), if state.ppu.chr_ram.is_empty() { 0 } else { 0x2000 }, "bytes"),
+        ];
+        for (memory, size, expected, unit) in sizes {
+            if size != expected {
+                let message = format!("{} is {} {}, expected {}", memory, size, unit, expected);
+                return Err(Box::new(bincode::ErrorKind::Custom(message)));
+            }
+        }
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::cartridge::{test, Mirroring};
+    use crate::cpu::Memory;
+
+    fn running_system() -> CPU {
+        let mut bus = Bus::new(test::test_rom_with_mapper(4));
+        // MMC3: $C000 in PRG mode 1 with R6 = 3, horizontal mirroring, IRQ latch 5 and enabled
+        for (address, data) in [(0x8000, 0b0100_0110), (0x8001, 3), (0xa000, 1), (0xc000, 5), (0xe001, 0)] {
+            bus.mem_write(address, data);
+        }
+        // LDA #$42; STA $10; STA $6000; LDX #$07; BRK
+        let program = [0xa9, 0x42, 0x85, 0x10, 0x8d, 0x00, 0x60, 0xa2, 0x07, 0x00];
+        for (offset, byte) in program.iter().enumerate() {
+            bus.mem_write(0x0600 + offset as u16, *byte);
+        }
+        let mut cpu = CPU::new(bus);
+        cpu.program_counter = 0x0600;
+        cpu.run();
+
+        let ppu = &mut cpu.bus.ppu;
+        ppu.vram[0x123] = 0x55;
+        ppu.oam_data[4] = 0x66;
+        ppu.palette_table[3] = 0x0f;
+        ppu.ctrl.update(0b1000_0000);
+        // rendering scrolled for a frame and a bit moves the line scroll, A12 and frame counters along
+        ppu.write_to_scroll(12);
+        ppu.write_to_scroll(34);
+        ppu.mask.update(0b0001_1000);
+        for _ in 0..crate::ppu::DOTS_PER_FRAME + 1000 {
+            ppu.step_dot();
+        }
+        cpu
+    }
+
+    #[test]
+    fn test_capture_and_restore() {
+        let mut cpu = running_system();
+        let state = SaveState::capture(&cpu);
+        assert_eq!(state.ppu.frame_count, 1);
+        assert!(state.ppu.chr_ram.is_empty());
+
+        cpu.register_a = 0;
+        cpu.program_counter = 0x1234;
+        cpu.mem_write(0x10, 0);
+        cpu.mem_write(0x6000, 0);
+        cpu.mem_write(0x8000, 0);
+        cpu.mem_write(0xa000, 0);
+        cpu.mem_write(0xe000, 0);
+        cpu.bus.ppu.vram[0x123] = 0;
+        cpu.bus.ppu.ctrl.update(0);
+        for _ in 0..1000 {
+            cpu.bus.ppu.step_dot();
+        }
+
+        state.restore(&mut cpu);
+        assert_eq!(cpu.register_a, 0x42);
+        assert_eq!(cpu.register_x, 0x07);
+        assert_eq!(cpu.mem_read(0x10), 0x42);
+        assert_eq!(cpu.mem_read(0x6000), 0x42);
+        assert_eq!(cpu.bus.mapper_state(), state.mapper);
+        assert_eq!(cpu.bus.ppu.mirroring, Mirroring::HORIZONTAL);
+        assert_eq!(cpu.bus.ppu.vram[0x123], 0x55);
+        assert!(cpu.bus.ppu.ctrl.generate_vblank_nmi());
+        assert_eq!(cpu.bus.ppu.frame_count, 1);
+        assert_eq!(SaveState::capture(&cpu), state);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_round_trip_through_bytes() {
+        let state = SaveState::capture(&running_system());
+        assert!(matches!(state.mapper, MapperState::Mmc3(_)));
+
+        let bytes = state.to_bytes();
+        assert_eq!(SaveState::from_bytes(&bytes).unwrap(), state);
+        assert!(SaveState::from_bytes(&bytes[..bytes.len() / 2]).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_wrong_memory_size_is_an_error() {
+        let state = SaveState::capture(&running_system());
+
+        let mut short_ram = state.clone();
+        short_ram.ram.truncate(16);
+        let err = SaveState::from_bytes(&short_ram.to_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "RAM is 16 bytes, expected 2048");
+
+        let mut long_oam = state.clone();
+        long_oam.ppu.oam_data.push(0);
+        assert!(SaveState::from_bytes(&long_oam.to_bytes()).is_err());
+
+        let mut no_vram = state.clone();
+        no_vram.ppu.vram.clear();
+        assert!(SaveState::from_bytes(&no_vram.to_bytes()).is_err());
+
+        let mut short_sram = state.clone();
+        short_sram.sram.pop();
+        assert!(SaveState::from_bytes(&short_sram.to_bytes()).is_err());
+
+        let mut short_scroll = state.clone();
+        short_scroll.ppu.line_scroll.truncate(3);
+        let err = SaveState::from_bytes(&short_scroll.to_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "Line scroll is 3 lines, expected 240");
+
+        let mut short_chr_ram = state;
+        short_chr_ram.ppu.chr_ram = vec![0; 0x1000];
+        assert!(SaveState::from_bytes(&short_chr_ram.to_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_chr_ram_is_saved() {
+        let mut rom = test::test_rom();
+        rom.chr_rom = vec![];
+        let mut cpu = CPU::new(Bus::new(rom));
+        cpu.bus.ppu.chr_rom[0x0010] = 0x55;
+        let state = SaveState::capture(&cpu);
+        assert_eq!(state.ppu.chr_ram.len(), 0x2000);
+
+        cpu.bus.ppu.chr_rom[0x0010] = 0;
+        state.restore(&mut cpu);
+        assert_eq!(cpu.bus.ppu.chr_rom[0x0010], 0x55);
+    }
+}
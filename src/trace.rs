@@ -7,7 +7,7 @@ use std::collections::HashMap;
 pub fn trace(cpu: &CPU) -> String {
     let ref opscodes: HashMap<u8, &'static opcode::OpCode> = *opcode::OPCODE_MAP;
 
-    let code = cpu.mem_read(cpu.program_counter);
+    let code = cpu.peek(cpu.program_counter);
     let ops = opscodes.get(&code).unwrap();
 
     let begin = cpu.program_counter;
@@ -15,21 +15,24 @@ pub fn trace(cpu: &CPU) -> String {
     hex_dump.push(code);
 
     let (mem_addr, stored_value) = match ops.mode {
-        AddressingMode::Immediate | AddressingMode::NoneAddressing => (0, 0),
+        AddressingMode::Immediate
+        | AddressingMode::Accumulator
+        | AddressingMode::Relative
+        | AddressingMode::NoneAddressing => (0, 0),
         _ => {
             let address = cpu.get_absolute_address(&ops.mode, begin + 1);
-            (address, cpu.mem_read(address))
+            (address, cpu.peek(address))
         }
     };
 
     let tmp = match ops.length {
-        1 => match ops.opcode {
-            0x0a | 0x4a | 0x2a | 0x6a => format!("A "),
+        1 => match ops.mode {
+            AddressingMode::Accumulator => String::from("A "),
             _ => String::from(""),
         },
         2 => {
-            let address: u8 = cpu.mem_read(begin + 1);
-            // let value = cpu.mem_read(address));
+            let address: u8 = cpu.peek(begin + 1);
+            // let value = cpu.peek(address));
             hex_dump.push(address);
 
             match ops.mode {
@@ -57,12 +60,7 @@ pub fn trace(cpu: &CPU) -> String {
                     mem_addr,
                     stored_value
                 ),
-                AddressingMode::NoneAddressing => {
-                    // assuming local jumps: BNE, BVS, etc....
-                    let address: usize =
-                        (begin as usize + 2).wrapping_add((address as i8) as usize);
-                    format!("${:04x}", address)
-                }
+                AddressingMode::Relative => format!("${:04x}", branch_target(begin, address)),
 
                 _ => panic!(
                     "unexpected addressing mode {:?} has ops-len 2. code {:02x}",
@@ -71,31 +69,16 @@ pub fn trace(cpu: &CPU) -> String {
             }
         }
         3 => {
-            let address_lo = cpu.mem_read(begin + 1);
-            let address_hi = cpu.mem_read(begin + 2);
+            let address_lo = cpu.peek(begin + 1);
+            let address_hi = cpu.peek(begin + 2);
             hex_dump.push(address_lo);
             hex_dump.push(address_hi);
 
-            let address = cpu.mem_read_u16(begin + 1);
+            let address = cpu.peek_u16(begin + 1);
 
             match ops.mode {
-                AddressingMode::NoneAddressing => {
-                    if ops.opcode == 0x6c {
-                        //jmp indirect
-                        let jmp_addr = if address & 0x00FF == 0x00FF {
-                            let lo = cpu.mem_read(address);
-                            let hi = cpu.mem_read(address & 0xFF00);
-                            (hi as u16) << 8 | (lo as u16)
-                        } else {
-                            cpu.mem_read_u16(address)
-                        };
-
-                        // let jmp_addr = cpu.mem_read_u16(address);
-                        format!("(${:04x}) = {:04x}", address, jmp_addr)
-                    } else {
-                        format!("${:04x}", address)
-                    }
-                }
+                AddressingMode::Indirect => format!("(${:04x}) = {:04x}", address, mem_addr),
+                AddressingMode::NoneAddressing => format!("${:04x}", address),
                 AddressingMode::Absolute => format!("${:04x} = {:02x}", mem_addr, stored_value),
                 AddressingMode::Absolute_X => format!(
                     "${:04x},X @ {:04x} = {:02x}",
@@ -130,6 +113,57 @@ pub fn trace(cpu: &CPU) -> String {
     .to_ascii_uppercase()
 }
 
+/// Resolves a relative branch operand to its absolute target.
+///
+/// The offset is sign extended and applied to the address of the instruction following the 2 byte branch.
+///
+/// # Arguments
+///
+/// * `begin` - Address of the branch opcode.
+/// * `offset` - The raw operand byte.
+pub fn branch_target(begin: u16, offset: u8) -> u16 {
+    begin.wrapping_add(2).wrapping_add((offset as i8) as u16)
+}
+
+/// Disassembles the instruction at `address` into assembler syntax without resolving memory contents,
+/// e.g. `LDA #$01`, `BNE $C012` or `JMP ($C000)`.
+///
+/// # Arguments
+///
+/// * `cpu` - The CPU whose memory holds the instruction.
+/// * `address` - Address of the opcode byte.
+pub fn disassemble(cpu: &CPU, address: u16) -> String {
+    let opscodes: &HashMap<u8, &'static opcode::OpCode> = &opcode::OPCODE_MAP;
+
+    let code = cpu.peek(address);
+    let ops = opscodes.get(&code).unwrap();
+    let byte = cpu.peek(address.wrapping_add(1));
+    let word = (cpu.peek(address.wrapping_add(2)) as u16) << 8 | byte as u16;
+
+    let operand = match (ops.length, &ops.mode) {
+        (1, AddressingMode::Accumulator) => String::from("A"),
+        (1, _) => String::from(""),
+        (2, AddressingMode::Immediate) => format!("#${:02X}", byte),
+        (2, AddressingMode::ZeroPage) => format!("${:02X}", byte),
+        (2, AddressingMode::ZeroPage_X) => format!("${:02X},X", byte),
+        (2, AddressingMode::ZeroPage_Y) => format!("${:02X},Y", byte),
+        (2, AddressingMode::Indirect_X) => format!("(${:02X},X)", byte),
+        (2, AddressingMode::Indirect_Y) => format!("(${:02X}),Y", byte),
+        (2, AddressingMode::Relative) => format!("${:04X}", branch_target(address, byte)),
+        (3, AddressingMode::Absolute) => format!("${:04X}", word),
+        (3, AddressingMode::Absolute_X) => format!("${:04X},X", word),
+        (3, AddressingMode::Absolute_Y) => format!("${:04X},Y", word),
+        (3, AddressingMode::Indirect) => format!("(${:04X})", word),
+        (3, AddressingMode::NoneAddressing) => format!("${:04X}", word),
+        _ => panic!(
+            "unexpected addressing mode {:?} has ops-len {}. code {:02x}",
+            ops.mode, ops.length, ops.opcode
+        ),
+    };
+
+    format!("{} {}", ops.abbreviation, operand).trim().to_string()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -168,6 +202,54 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_trace_filter() {
+        let mut bus = Bus::new(test_rom());
+        let program = [
+            0x20, 0x10, 0x06, // JSR $0610
+            0xa2, 0x01, // LDX #$01
+            0x00, // BRK
+        ];
+        // $0610: LDA #$05; INY; RTS
+        let subroutine = [0xa9, 0x05, 0xc8, 0x60];
+        for (offset, byte) in program.iter().enumerate() {
+            bus.mem_write(0x0600 + offset as u16, *byte);
+        }
+        for (offset, byte) in subroutine.iter().enumerate() {
+            bus.mem_write(0x0610 + offset as u16, *byte);
+        }
+        let mut cpu = CPU::new(bus);
+        cpu.program_counter = 0x0600;
+        cpu.trace_filter = Some(0x0610..0x0614);
+
+        let mut result: Vec<String> = vec![];
+        cpu.run_with_trace(|line| result.push(line));
+        let addresses: Vec<&str> = result.iter().map(|line| &line[..4]).collect();
+        assert_eq!(addresses, vec!["0610", "0612", "0613"]);
+        assert!(result[2].contains("RTS"));
+        // the whole program still ran
+        assert_eq!(cpu.register_x, 1);
+
+        cpu.program_counter = 0x0600;
+        cpu.trace_filter = None;
+        let mut count = 0;
+        cpu.run_with_trace(|_| count += 1);
+        assert_eq!(count, 6);
+    }
+
+    #[test]
+    fn test_stack_pointer_is_two_hex_digits() {
+        for (stack_pointer, field) in [(0x09, "SP:09"), (0x00, "SP:00"), (0xff, "SP:FF"), (0xa0, "SP:A0")] {
+            let mut bus = Bus::new(test_rom());
+            bus.mem_write(100, 0xea); // NOP
+            let mut cpu = CPU::new(bus);
+            cpu.program_counter = 0x64;
+            cpu.stack_pointer = stack_pointer;
+            let line = trace(&cpu);
+            assert!(line.ends_with(&format!(" {}", field)), "{}", line);
+        }
+    }
+
     #[test]
     fn test_format_mem_access() {
         let mut bus = Bus::new(test_rom());
@@ -194,4 +276,93 @@ mod test {
             result[0]
         );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_disassemble_forward_branch() {
+        let mut bus = Bus::new(test_rom());
+        // BNE +5
+        bus.mem_write(0x0600, 0xd0);
+        bus.mem_write(0x0601, 0x05);
+        let cpu = CPU::new(bus);
+
+        assert_eq!(disassemble(&cpu, 0x0600), "BNE $0607");
+    }
+
+    #[test]
+    fn test_disassemble_backward_branch() {
+        let mut bus = Bus::new(test_rom());
+        // BEQ -16
+        bus.mem_write(0x0610, 0xf0);
+        bus.mem_write(0x0611, 0xf0);
+        // BPL -4 at the bottom of memory wraps around to the top
+        bus.mem_write(0x0000, 0x10);
+        bus.mem_write(0x0001, 0xfc);
+        let cpu = CPU::new(bus);
+
+        assert_eq!(disassemble(&cpu, 0x0610), "BEQ $0602");
+        assert_eq!(disassemble(&cpu, 0x0000), "BPL $FFFE");
+    }
+
+    #[test]
+    fn test_disassemble_jmp_modes() {
+        let mut bus = Bus::new(test_rom());
+        bus.mem_write(0x0600, 0x6c);
+        bus.mem_write(0x0601, 0x00);
+        bus.mem_write(0x0602, 0xc0);
+        bus.mem_write(0x0603, 0x4c);
+        bus.mem_write(0x0604, 0x34);
+        bus.mem_write(0x0605, 0x12);
+        let cpu = CPU::new(bus);
+
+        assert_eq!(disassemble(&cpu, 0x0600), "JMP ($C000)");
+        assert_eq!(disassemble(&cpu, 0x0603), "JMP $1234");
+    }
+
+    #[test]
+    fn test_disassemble_operand_syntax() {
+        let mut bus = Bus::new(test_rom());
+        let program = [0xa9, 0x01, 0xb1, 0x33, 0xa1, 0x44, 0xbd, 0x00, 0x02, 0x0a, 0xe8];
+        for (offset, byte) in program.iter().enumerate() {
+            bus.mem_write(0x0600 + offset as u16, *byte);
+        }
+        let cpu = CPU::new(bus);
+
+        assert_eq!(disassemble(&cpu, 0x0600), "LDA #$01");
+        assert_eq!(disassemble(&cpu, 0x0602), "LDA ($33),Y");
+        assert_eq!(disassemble(&cpu, 0x0604), "LDA ($44,X)");
+        assert_eq!(disassemble(&cpu, 0x0606), "LDA $0200,X");
+        assert_eq!(disassemble(&cpu, 0x0609), "ASL A");
+        assert_eq!(disassemble(&cpu, 0x060a), "INX");
+    }
+
+    #[test]
+    fn test_relative_target() {
+        let mut bus = Bus::new(test_rom());
+        // BNE +$10; BCC -$04; BPL -$80
+        for (offset, byte) in [0xd0, 0x10, 0x90, 0xfc, 0x10, 0x80].iter().enumerate() {
+            bus.mem_write(0x0600 + offset as u16, *byte);
+        }
+        let mut cpu = CPU::new(bus);
+
+        assert_eq!(disassemble(&cpu, 0x0600), "BNE $0612");
+        assert_eq!(disassemble(&cpu, 0x0602), "BCC $0600");
+        assert_eq!(disassemble(&cpu, 0x0604), "BPL $0586");
+        cpu.program_counter = 0x0602;
+        assert!(trace(&cpu).starts_with("0602  90 FC     BCC $0600 "));
+    }
+
+    #[test]
+    fn test_accumulator_operand() {
+        let mut bus = Bus::new(test_rom());
+        for (offset, byte) in [0x4a, 0x2a, 0x6a].iter().enumerate() {
+            bus.mem_write(0x0600 + offset as u16, *byte);
+        }
+        let mut cpu = CPU::new(bus);
+
+        assert_eq!(disassemble(&cpu, 0x0600), "LSR A");
+        assert_eq!(disassemble(&cpu, 0x0601), "ROL A");
+        assert_eq!(disassemble(&cpu, 0x0602), "ROR A");
+        cpu.program_counter = 0x0601;
+        assert!(trace(&cpu).starts_with("0601  2A        ROL A "));
+    }
+}